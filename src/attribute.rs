@@ -0,0 +1,396 @@
+use std::io::Write;
+
+use crate::code_writer::CodeWriter;
+use crate::error::{CodeGenError, Result};
+use crate::function_macro::FunctionMacro;
+use crate::literal::c_string_literal;
+use crate::preprocessor::PpExpr;
+
+/// A compiler-specific property of a generated declaration or type
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Attribute {
+  /// No padding between members (types only)
+  Packed,
+  /// Minimum alignment in bytes
+  Aligned(usize),
+  /// Weak linkage, overridable by a strong definition (declarations only)
+  Weak,
+  /// Placement in a named linker section (declarations only)
+  Section(String),
+  /// The function never returns (declarations only)
+  NoReturn,
+  /// Warn on use, with an optional message (declarations only)
+  Deprecated(Option<String>),
+}
+
+/// What an attribute list is attached to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AttributeTarget {
+  /// A struct or union definition
+  Type,
+  /// A function or variable declaration
+  Declaration,
+}
+
+impl Attribute {
+  /// Get the attribute name used in diagnostics
+  pub fn name(&self) -> &'static str {
+    match self {
+      Attribute::Packed => "packed",
+      Attribute::Aligned(_) => "aligned",
+      Attribute::Weak => "weak",
+      Attribute::Section(_) => "section",
+      Attribute::NoReturn => "noreturn",
+      Attribute::Deprecated(_) => "deprecated",
+    }
+  }
+
+  /// Check whether the attribute can be attached to `target`
+  pub fn applies_to(&self, target: AttributeTarget) -> bool {
+    match self {
+      Attribute::Packed => target == AttributeTarget::Type,
+      Attribute::Aligned(_) => true,
+      _ => target == AttributeTarget::Declaration,
+    }
+  }
+
+  /// Get the GNU spelling used inside `__attribute__((...))`
+  fn gnu(&self) -> String {
+    match self {
+      Attribute::Packed => "packed".to_string(),
+      Attribute::Aligned(n) => format!("aligned({})", n),
+      Attribute::Weak => "weak".to_string(),
+      Attribute::Section(name) => format!("section({})", c_string_literal(name)),
+      Attribute::NoReturn => "noreturn".to_string(),
+      Attribute::Deprecated(None) => "deprecated".to_string(),
+      Attribute::Deprecated(Some(msg)) => format!("deprecated({})", c_string_literal(msg)),
+    }
+  }
+}
+
+/// Text placed around a declaration or type definition for its attributes
+///
+/// For a type the prefix goes before the `struct` keyword and the suffix
+/// between the closing brace and the semicolon. Declarations only use the
+/// prefix.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RenderedAttributes {
+  /// Text written before the declaration, empty if none
+  pub prefix: String,
+  /// Text written after the closing brace of a type, empty if none
+  pub suffix: String,
+}
+
+impl RenderedAttributes {
+  fn push_prefix(&mut self, text: &str) {
+    if !self.prefix.is_empty() {
+      self.prefix.push(' ');
+    }
+    self.prefix.push_str(text);
+  }
+
+  fn push_suffix(&mut self, text: &str) {
+    if !self.suffix.is_empty() {
+      self.suffix.push(' ');
+    }
+    self.suffix.push_str(text);
+  }
+
+  /// Prepend the prefix to `declaration`, e.g. a type or storage class
+  pub fn prefixed(&self, declaration: &str) -> String {
+    if self.prefix.is_empty() {
+      declaration.to_string()
+    } else {
+      format!("{} {}", self.prefix, declaration)
+    }
+  }
+}
+
+/// The compiler whose attribute syntax is generated
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum Compiler {
+  /// GCC and Clang, `__attribute__((...))`
+  #[default]
+  GccClang,
+  /// Microsoft Visual C++, `__declspec(...)` and `__pragma(...)`
+  Msvc,
+  /// IAR C/C++ for Arm, keywords and `_Pragma(...)`
+  Iar,
+  /// Arm Compiler 5, keywords and GNU attributes
+  Armcc,
+  /// Macros with the given prefix, defined by `write_portability_macros`
+  Portable(String),
+}
+
+impl Compiler {
+  /// Get the compiler name used in diagnostics
+  pub fn name(&self) -> &'static str {
+    match self {
+      Compiler::GccClang => "GCC/Clang",
+      Compiler::Msvc => "MSVC",
+      Compiler::Iar => "IAR",
+      Compiler::Armcc => "ARMCC",
+      Compiler::Portable(_) => "portable macros",
+    }
+  }
+
+  fn unsupported(&self, attribute: &Attribute) -> CodeGenError {
+    CodeGenError::UnsupportedAttribute {
+      attribute: attribute.name().to_string(),
+      compiler: self.name().to_string(),
+    }
+  }
+
+  /// Render an attribute list for `target`
+  ///
+  /// IAR can only align variables, so an aligned type is rejected there.
+  pub fn render(
+    &self,
+    attributes: &[Attribute],
+    target: AttributeTarget,
+  ) -> Result<RenderedAttributes> {
+    let mut rendered = RenderedAttributes::default();
+    // GNU attributes are collected into one `__attribute__((...))`
+    let mut gnu = Vec::new();
+    for attribute in attributes {
+      if !attribute.applies_to(target) {
+        return Err(CodeGenError::InvalidDefinition(format!(
+          "{} attribute cannot be attached to a {}",
+          attribute.name(),
+          match target {
+            AttributeTarget::Type => "type",
+            AttributeTarget::Declaration => "declaration",
+          }
+        )));
+      }
+      match (self, attribute) {
+        (Compiler::GccClang, _) => gnu.push(attribute.gnu()),
+
+        (Compiler::Msvc, Attribute::Packed) => {
+          rendered.push_prefix("__pragma(pack(push, 1))");
+          rendered.push_suffix("__pragma(pack(pop))");
+        }
+        (Compiler::Msvc, Attribute::Aligned(n)) => {
+          rendered.push_prefix(&format!("__declspec(align({}))", n))
+        }
+        (Compiler::Msvc, Attribute::NoReturn) => rendered.push_prefix("__declspec(noreturn)"),
+        (Compiler::Msvc, Attribute::Deprecated(None)) => {
+          rendered.push_prefix("__declspec(deprecated)")
+        }
+        (Compiler::Msvc, Attribute::Deprecated(Some(msg))) => rendered.push_prefix(&format!(
+          "__declspec(deprecated({}))",
+          c_string_literal(msg)
+        )),
+
+        (Compiler::Iar | Compiler::Armcc, Attribute::Packed) => rendered.push_prefix("__packed"),
+        (Compiler::Iar | Compiler::Armcc, Attribute::Weak) => rendered.push_prefix("__weak"),
+        (Compiler::Iar, Attribute::Aligned(n)) if target == AttributeTarget::Declaration => {
+          let pragma = c_string_literal(&format!("data_alignment={}", n));
+          rendered.push_prefix(&format!("_Pragma({})", pragma))
+        }
+        (Compiler::Iar, Attribute::Section(name)) => {
+          let pragma = c_string_literal(&format!("location={}", c_string_literal(name)));
+          rendered.push_prefix(&format!("_Pragma({})", pragma))
+        }
+        (Compiler::Iar, Attribute::NoReturn) => rendered.push_prefix("__noreturn"),
+
+        (Compiler::Armcc, Attribute::Aligned(n)) if target == AttributeTarget::Declaration => {
+          rendered.push_prefix(&format!("__align({})", n))
+        }
+        // Arm Compiler 5 ignores deprecation messages
+        (Compiler::Armcc, Attribute::Deprecated(_)) => gnu.push("deprecated".to_string()),
+        (Compiler::Armcc, Attribute::Aligned(_) | Attribute::Section(_) | Attribute::NoReturn) => {
+          gnu.push(attribute.gnu())
+        }
+
+        (Compiler::Portable(prefix), Attribute::Packed) => {
+          rendered.push_prefix(&format!("{}PACKED_BEGIN", prefix));
+          rendered.push_suffix(&format!("{}PACKED_END", prefix));
+        }
+        (Compiler::Portable(prefix), Attribute::Aligned(n))
+          if target == AttributeTarget::Declaration =>
+        {
+          rendered.push_prefix(&format!("{}ALIGNED({})", prefix, n))
+        }
+        (Compiler::Portable(prefix), Attribute::Aligned(n)) => {
+          rendered.push_prefix(&format!("{}ALIGNED_BEGIN({})", prefix, n));
+          rendered.push_suffix(&format!("{}ALIGNED_END({})", prefix, n));
+        }
+        (Compiler::Portable(prefix), Attribute::Weak) => {
+          rendered.push_prefix(&format!("{}WEAK", prefix))
+        }
+        (Compiler::Portable(prefix), Attribute::Section(name)) => {
+          rendered.push_prefix(&format!("{}SECTION({})", prefix, c_string_literal(name)))
+        }
+        (Compiler::Portable(prefix), Attribute::NoReturn) => {
+          rendered.push_prefix(&format!("{}NORETURN", prefix))
+        }
+        (Compiler::Portable(prefix), Attribute::Deprecated(None)) => {
+          rendered.push_prefix(&format!("{}DEPRECATED", prefix))
+        }
+        (Compiler::Portable(prefix), Attribute::Deprecated(Some(msg))) => rendered.push_prefix(
+          &format!("{}DEPRECATED_MSG({})", prefix, c_string_literal(msg)),
+        ),
+
+        _ => return Err(self.unsupported(attribute)),
+      }
+    }
+
+    if !gnu.is_empty() {
+      let text = format!("__attribute__(({}))", gnu.join(", "));
+      match target {
+        AttributeTarget::Type => rendered.push_suffix(&text),
+        AttributeTarget::Declaration => rendered.push_prefix(&text),
+      }
+    }
+    Ok(rendered)
+  }
+}
+
+/// A portability macro and its expansion per compiler
+type PortableMacro = (
+  &'static str,
+  &'static [&'static str],
+  [Option<&'static str>; 4],
+);
+
+/// Macro definitions: (name, parameters, [ARMCC, IAR, GCC/Clang, MSVC])
+///
+/// `None` leaves the macro undefined so that using it fails to compile.
+const PORTABLE_MACROS: &[PortableMacro] = &[
+  (
+    "PACKED_BEGIN",
+    &[],
+    [
+      Some("__packed"),
+      Some("__packed"),
+      Some(""),
+      Some("__pragma(pack(push, 1))"),
+    ],
+  ),
+  (
+    "PACKED_END",
+    &[],
+    [
+      Some(""),
+      Some(""),
+      Some("__attribute__((packed))"),
+      Some("__pragma(pack(pop))"),
+    ],
+  ),
+  (
+    "ALIGNED",
+    &["n"],
+    [
+      Some("__align(n)"),
+      Some("{P}DO_PRAGMA(data_alignment = n)"),
+      Some("__attribute__((aligned(n)))"),
+      Some("__declspec(align(n))"),
+    ],
+  ),
+  (
+    "ALIGNED_BEGIN",
+    &["n"],
+    [Some(""), None, Some(""), Some("__declspec(align(n))")],
+  ),
+  (
+    "ALIGNED_END",
+    &["n"],
+    [
+      Some("__attribute__((aligned(n)))"),
+      None,
+      Some("__attribute__((aligned(n)))"),
+      Some(""),
+    ],
+  ),
+  (
+    "WEAK",
+    &[],
+    [
+      Some("__weak"),
+      Some("__weak"),
+      Some("__attribute__((weak))"),
+      None,
+    ],
+  ),
+  (
+    "SECTION",
+    &["name"],
+    [
+      Some("__attribute__((section(name)))"),
+      Some("{P}DO_PRAGMA(location = name)"),
+      Some("__attribute__((section(name)))"),
+      None,
+    ],
+  ),
+  (
+    "NORETURN",
+    &[],
+    [
+      Some("__attribute__((noreturn))"),
+      Some("__noreturn"),
+      Some("__attribute__((noreturn))"),
+      Some("__declspec(noreturn)"),
+    ],
+  ),
+  (
+    "DEPRECATED",
+    &[],
+    [
+      Some("__attribute__((deprecated))"),
+      Some(""),
+      Some("__attribute__((deprecated))"),
+      Some("__declspec(deprecated)"),
+    ],
+  ),
+  (
+    "DEPRECATED_MSG",
+    &["msg"],
+    [
+      Some("__attribute__((deprecated))"),
+      Some(""),
+      Some("__attribute__((deprecated(msg)))"),
+      Some("__declspec(deprecated(msg))"),
+    ],
+  ),
+];
+
+/// Write the macros used by `Compiler::Portable(prefix)`, defined for each supported compiler
+///
+/// `__packed` is a type qualifier on IAR and ARMCC, so `PACKED_BEGIN`
+/// expands to it there. IAR pragmas go through `DO_PRAGMA`, the usual
+/// `_Pragma(#x)` wrapper. Weak symbols and sections have no MSVC equivalent
+/// and type alignment none on IAR, so those stay undefined; deprecation is
+/// dropped on IAR. Unknown compilers are rejected with `#error`.
+pub fn write_portability_macros<W: Write>(writer: &mut CodeWriter<W>, prefix: &str) -> Result<()> {
+  writer.write_macro(&FunctionMacro::new(
+    &format!("{}DO_PRAGMA", prefix),
+    &["x"],
+    "_Pragma(#x)",
+  ))?;
+  writer.newline()?;
+
+  let compilers = ["__CC_ARM", "__ICCARM__", "__GNUC__", "_MSC_VER"];
+  for (i, compiler) in compilers.iter().enumerate() {
+    if i == 0 {
+      writer.begin_if(&PpExpr::defined(compiler))?;
+    } else {
+      writer.write_elif(&PpExpr::defined(compiler))?;
+    }
+    for (name, params, values) in PORTABLE_MACROS {
+      let Some(value) = values[i] else {
+        continue;
+      };
+      let name = format!("{}{}", prefix, name);
+      let value = value.replace("{P}", prefix);
+      if params.is_empty() {
+        writer.write_define(&name, (!value.is_empty()).then_some(value.as_str()))?;
+      } else {
+        writer.write_macro(&FunctionMacro::new(&name, params, &value))?;
+      }
+    }
+  }
+  writer.write_else()?;
+  writer.writeln("#error \"Unsupported compiler\"")?;
+  writer.end_if()
+}
@@ -1,6 +1,22 @@
+use serde::Serialize;
 use std::io::Write;
 
+use crate::attribute::{Attribute, AttributeTarget, Compiler, RenderedAttributes};
+use crate::comment::{escape_block_comment, format_line_comment, CommentPolicy};
+use crate::cpp::{AccessSpecifier, Method};
+use crate::data_table::{format_table_rows, TableOptions, TableSize, TableValue};
+use crate::doc_comment::{render_file_doc, render_trailing_doc, DocComment, DocStyle};
 use crate::error::{CodeGenError, Result}; // Changed from crate::codegen::
+use crate::expr::Expr;
+use crate::function_macro::FunctionMacro;
+use crate::includes::IncludeSet;
+use crate::initializer::{Initializer, InitializerOptions};
+use crate::invariant::{AssertStyle, Invariant};
+use crate::literal::{c_string_literal, split_string_literal, NonAsciiMode};
+use crate::numeric::{float_literal, int_literal, CType, NumberFormat};
+use crate::preprocessor::PpExpr;
+use crate::standard::LanguageStandard;
+use crate::symbols::{SymbolKind, SymbolOrigin, SymbolRegistry, SymbolRole};
 use crate::utils::repeat_str; // Changed from crate::codegen::
 
 /// A writer for generating code with proper indentation and formatting
@@ -13,6 +29,51 @@ pub struct CodeWriter<W: Write> {
   indent_size: usize,
   /// Whether to add a newline after each write
   with_newline: bool,
+  /// Includes registered for the reserved include point
+  includes: IncludeSet,
+  /// Output buffered after the reserved include point
+  deferred: Option<Vec<u8>>,
+  /// Open preprocessor conditional blocks, innermost last
+  conditionals: Vec<Conditional>,
+  /// Flavour of generated documentation comments
+  doc_style: DocStyle,
+  /// What to do with comment text that would break the generated file
+  comment_policy: CommentPolicy,
+  /// Spelling of compile-time assertions
+  assert_style: AssertStyle,
+  /// Number of compile-time assertions written so far
+  static_asserts: usize,
+  /// Name of the output in assertion typedefs, keeping them apart from other files
+  assert_scope: String,
+  /// Compiler whose attribute syntax is generated
+  compiler: Compiler,
+  /// Language standard the output must conform to (`None` accepts everything)
+  standard: Option<LanguageStandard>,
+  /// Line count at the end of the declarations opening the current function body
+  declarations_end: Option<usize>,
+  /// Registry that emitted symbols are reported into
+  symbols: Option<SymbolRegistry>,
+  /// Label of this output in symbol collision reports
+  origin: String,
+  /// Number of lines written so far
+  lines: usize,
+}
+
+/// An open preprocessor conditional block
+struct Conditional {
+  /// The condition text, used for the `#endif` comment
+  condition: String,
+  /// Whether the block already has an `#else` branch
+  has_else: bool,
+}
+
+impl Conditional {
+  fn new(condition: &str) -> Self {
+    Self {
+      condition: condition.to_string(),
+      has_else: false,
+    }
+  }
 }
 
 impl<W: Write> CodeWriter<W> {
@@ -23,6 +84,20 @@ impl<W: Write> CodeWriter<W> {
       indent_level: 0,
       indent_size: 4,
       with_newline: true,
+      includes: IncludeSet::new(),
+      deferred: None,
+      conditionals: Vec::new(),
+      doc_style: DocStyle::Doxygen,
+      comment_policy: CommentPolicy::Sanitize,
+      assert_style: AssertStyle::StaticAssertC11,
+      static_asserts: 0,
+      assert_scope: String::new(),
+      compiler: Compiler::GccClang,
+      standard: None,
+      declarations_end: None,
+      symbols: None,
+      origin: String::new(),
+      lines: 0,
     }
   }
 
@@ -33,6 +108,20 @@ impl<W: Write> CodeWriter<W> {
       indent_level: 0,
       indent_size,
       with_newline,
+      includes: IncludeSet::new(),
+      deferred: None,
+      conditionals: Vec::new(),
+      doc_style: DocStyle::Doxygen,
+      comment_policy: CommentPolicy::Sanitize,
+      assert_style: AssertStyle::StaticAssertC11,
+      static_asserts: 0,
+      assert_scope: String::new(),
+      compiler: Compiler::GccClang,
+      standard: None,
+      declarations_end: None,
+      symbols: None,
+      origin: String::new(),
+      lines: 0,
     }
   }
 
@@ -56,6 +145,195 @@ impl<W: Write> CodeWriter<W> {
     self.indent_size
   }
 
+  /// Set the flavour of generated documentation comments
+  pub fn set_doc_style(&mut self, doc_style: DocStyle) {
+    self.doc_style = doc_style;
+  }
+
+  /// Get the flavour of generated documentation comments
+  pub fn doc_style(&self) -> DocStyle {
+    self.doc_style
+  }
+
+  /// Set what to do with comment text that would break the generated file
+  pub fn set_comment_policy(&mut self, comment_policy: CommentPolicy) {
+    self.comment_policy = comment_policy;
+  }
+
+  /// Get what to do with comment text that would break the generated file
+  pub fn comment_policy(&self) -> CommentPolicy {
+    self.comment_policy
+  }
+
+  /// Set the language standard the output must conform to
+  ///
+  /// Constructs the standard lacks are rewritten where an equivalent exists
+  /// (`//` comments become `/* */` in C89, `a::b` namespaces are nested
+  /// before C++17) and rejected with `CodeGenError::UnsupportedConstruct`
+  /// otherwise. Also selects the matching assertion style. `None`, the
+  /// default, accepts every construct.
+  pub fn set_language_standard(&mut self, standard: Option<LanguageStandard>) {
+    self.standard = standard;
+    if let Some(standard) = standard {
+      self.assert_style = AssertStyle::for_standard(standard);
+    }
+  }
+
+  /// Get the language standard the output must conform to
+  pub fn language_standard(&self) -> Option<LanguageStandard> {
+    self.standard
+  }
+
+  /// Fail if the selected standard does not provide a construct
+  pub(crate) fn check_standard(
+    &self,
+    construct: &str,
+    available: fn(&LanguageStandard) -> bool,
+  ) -> Result<()> {
+    match self.standard {
+      Some(standard) if !available(&standard) => Err(CodeGenError::UnsupportedConstruct {
+        construct: construct.to_string(),
+        standard: standard.name().to_string(),
+      }),
+      _ => Ok(()),
+    }
+  }
+
+  /// Get the boolean type of the selected standard (`bool` when none is selected)
+  pub fn bool_type(&self) -> Result<&'static str> {
+    match self.standard {
+      Some(standard) => standard
+        .bool_type()
+        .ok_or_else(|| CodeGenError::UnsupportedConstruct {
+          construct: "a boolean type".to_string(),
+          standard: standard.name().to_string(),
+        }),
+      None => Ok("bool"),
+    }
+  }
+
+  /// Get the null pointer constant of the selected standard (`NULL` when none is selected)
+  pub fn null_pointer(&self) -> &'static str {
+    self.standard.map_or("NULL", |s| s.null_pointer())
+  }
+
+  /// Format a single-line comment, as a block comment when `//` is unavailable
+  fn line_comment(&self, text: &str) -> Result<String> {
+    if self.standard.is_some_and(|s| !s.has_line_comments()) {
+      Ok(format!(
+        "/* {} */",
+        escape_block_comment(text, self.comment_policy)?
+      ))
+    } else {
+      format_line_comment("//", text, self.comment_policy)
+    }
+  }
+
+  /// Format a trailing documentation comment, avoiding `///<` when `//` is unavailable
+  pub(crate) fn trailing_doc(&self, text: &str) -> Result<String> {
+    let style = match self.doc_style {
+      DocStyle::Doxygen if self.standard.is_some_and(|s| !s.has_line_comments()) => {
+        DocStyle::Javadoc
+      }
+      style => style,
+    };
+    render_trailing_doc(style, text, self.comment_policy)
+  }
+
+  /// Fail if a declaration would follow a statement where the standard forbids it
+  ///
+  /// Only the declarations opening a function body started with
+  /// `begin_function` are tracked.
+  fn check_declaration_position(&self) -> Result<()> {
+    match self.declarations_end {
+      Some(end) if end != self.lines => self
+        .check_standard("a declaration after a statement", |s| {
+          s.has_mixed_declarations()
+        }),
+      _ => Ok(()),
+    }
+  }
+
+  /// Extend the declarations opening the function body if nothing else was written since
+  fn extend_declarations<F>(&mut self, write: F) -> Result<()>
+  where
+    F: FnOnce(&mut Self) -> Result<()>,
+  {
+    let at_end = self.declarations_end == Some(self.lines);
+    write(self)?;
+    if at_end {
+      self.declarations_end = Some(self.lines);
+    }
+    Ok(())
+  }
+
+  /// Set the spelling of compile-time assertions
+  pub fn set_assert_style(&mut self, assert_style: AssertStyle) {
+    self.assert_style = assert_style;
+  }
+
+  /// Get the spelling of compile-time assertions
+  pub fn assert_style(&self) -> AssertStyle {
+    self.assert_style
+  }
+
+  /// Set the name of the output used in the typedefs of [`AssertStyle::NegativeArray`]
+  ///
+  /// Typedefs are numbered per writer, so headers included into the same
+  /// translation unit need different scopes, e.g. their include guards.
+  pub fn set_assert_scope(&mut self, scope: &str) {
+    self.assert_scope = scope.to_string();
+  }
+
+  /// Set the compiler whose attribute syntax is generated
+  pub fn set_compiler(&mut self, compiler: Compiler) {
+    self.compiler = compiler;
+  }
+
+  /// Get the compiler whose attribute syntax is generated
+  pub fn compiler(&self) -> &Compiler {
+    &self.compiler
+  }
+
+  /// Render attributes for the writer's compiler
+  pub fn render_attributes(
+    &self,
+    attributes: &[Attribute],
+    target: AttributeTarget,
+  ) -> Result<RenderedAttributes> {
+    self.compiler.render(attributes, target)
+  }
+
+  /// Report emitted symbols into a registry, labelling them with `origin` (e.g. the file path)
+  ///
+  /// Line numbers of symbols written after a reserved include point do not
+  /// account for the includes inserted there.
+  pub fn set_symbol_registry(&mut self, registry: SymbolRegistry, origin: &str) {
+    self.symbols = Some(registry);
+    self.origin = origin.to_string();
+  }
+
+  /// Get the registry emitted symbols are reported into
+  pub fn symbol_registry(&self) -> Option<&SymbolRegistry> {
+    self.symbols.as_ref()
+  }
+
+  /// Record a symbol written on the next line in the registry, if any
+  fn register_symbol(&self, name: &str, kind: SymbolKind, role: SymbolRole) -> Result<()> {
+    match &self.symbols {
+      Some(registry) => registry.register(
+        name,
+        kind,
+        role,
+        SymbolOrigin {
+          file: self.origin.clone(),
+          line: self.lines + 1,
+        },
+      ),
+      None => Ok(()),
+    }
+  }
+
   /// Increase the indentation level
   pub fn indent(&mut self) {
     self.indent_level += 1;
@@ -73,14 +351,80 @@ impl<W: Write> CodeWriter<W> {
     self.indent_level
   }
 
+  /// Send raw bytes to the underlying writer, or to the deferred buffer
+  fn emit(&mut self, bytes: &[u8]) -> Result<()> {
+    self.lines += bytes.iter().filter(|&&b| b == b'\n').count();
+    match self.deferred {
+      Some(ref mut buffer) => {
+        buffer.extend_from_slice(bytes);
+        Ok(())
+      }
+      None => self.writer.write_all(bytes).map_err(CodeGenError::Io),
+    }
+  }
+
+  /// Write `body` with the collected `#include` directives placed before its output
+  ///
+  /// Output of `body` is buffered until it returns. Meanwhile `write_include`
+  /// registers headers instead of writing them; the deduplicated, sorted set
+  /// is then written first, followed by the buffered output. If `body` fails,
+  /// its output and includes are discarded. Nested calls share the outermost
+  /// include point.
+  pub fn write_with_includes<F>(&mut self, body: F) -> Result<()>
+  where
+    F: FnOnce(&mut Self) -> Result<()>,
+  {
+    if self.deferred.is_some() {
+      return body(self);
+    }
+    self.deferred = Some(Vec::new());
+    match body(self) {
+      Ok(()) => self.resolve_includes(),
+      Err(e) => {
+        self.deferred = None;
+        self.includes = IncludeSet::new();
+        Err(e)
+      }
+    }
+  }
+
+  /// Check whether includes are being collected by `write_with_includes`
+  pub fn has_reserved_includes(&self) -> bool {
+    self.deferred.is_some()
+  }
+
+  /// Register a header to be written by the enclosing `write_with_includes`
+  pub fn require_include(&mut self, header: &str, is_system: bool) {
+    self.includes.add(header, is_system);
+  }
+
+  /// Get the headers registered so far
+  pub fn includes(&self) -> &IncludeSet {
+    &self.includes
+  }
+
+  /// Write the collected includes at the reserved point, followed by the buffered output
+  ///
+  /// The include set is emptied so that a later include point starts afresh.
+  fn resolve_includes(&mut self) -> Result<()> {
+    if let Some(buffer) = self.deferred.take() {
+      let includes = std::mem::replace(&mut self.includes, IncludeSet::new());
+      let mut rendered = includes.render();
+      if !rendered.is_empty() {
+        rendered.push('\n');
+      }
+      self.emit(rendered.as_bytes())?;
+      // The buffered lines were already counted when they were written
+      self.writer.write_all(&buffer).map_err(CodeGenError::Io)?;
+    }
+    Ok(())
+  }
+
   /// Write a string with the current indentation
   pub fn write(&mut self, content: &str) -> Result<()> {
     if content.is_empty() {
       if self.with_newline {
-        self
-          .writer
-          .write_all(b"\n")
-          .map_err(|e| CodeGenError::Io(e))
+        self.emit(b"\n")
       } else {
         Ok(())
       }
@@ -89,30 +433,18 @@ impl<W: Write> CodeWriter<W> {
 
       for (i, line) in content.lines().enumerate() {
         if i > 0 {
-          self
-            .writer
-            .write_all(b"\n")
-            .map_err(|e| CodeGenError::Io(e))?;
+          self.emit(b"\n")?;
         }
 
         if !line.is_empty() {
-          self
-            .writer
-            .write_all(indent.as_bytes())
-            .map_err(|e| CodeGenError::Io(e))?;
-
-          self
-            .writer
-            .write_all(line.as_bytes())
-            .map_err(|e| CodeGenError::Io(e))?;
+          self.emit(indent.as_bytes())?;
+
+          self.emit(line.as_bytes())?;
         }
       }
 
       if self.with_newline {
-        self
-          .writer
-          .write_all(b"\n")
-          .map_err(|e| CodeGenError::Io(e))
+        self.emit(b"\n")
       } else {
         Ok(())
       }
@@ -130,10 +462,7 @@ impl<W: Write> CodeWriter<W> {
 
   /// Write a newline
   pub fn newline(&mut self) -> Result<()> {
-    self
-      .writer
-      .write_all(b"\n")
-      .map_err(|e| CodeGenError::Io(e))
+    self.emit(b"\n")
   }
 
   /// Write a line comment
@@ -141,22 +470,51 @@ impl<W: Write> CodeWriter<W> {
     if comment.contains('\n') {
       self.writeln("/*")?;
       for line in comment.lines() {
+        let line = escape_block_comment(line, self.comment_policy)?;
         self.writeln(&format!(" * {}", line))?;
       }
       self.writeln(" */")
     } else {
-      self.writeln(&format!("// {}", comment))
+      let line = self.line_comment(comment)?;
+      self.extend_declarations(|w| w.writeln(&line))
+    }
+  }
+
+  /// Write a documentation comment block
+  pub fn write_doc_comment(&mut self, doc: &DocComment) -> Result<()> {
+    let lines = doc.render_lines(self.doc_style);
+    self.write_comment_block(&lines)
+  }
+
+  /// Write a `@file` header comment
+  pub fn write_file_doc(&mut self, file_name: &str, brief: &str) -> Result<()> {
+    let lines = render_file_doc(self.doc_style, file_name, brief);
+    self.write_comment_block(&lines)
+  }
+
+  /// Write rendered block comment lines, escaping everything between the delimiters
+  fn write_comment_block(&mut self, lines: &[String]) -> Result<()> {
+    let last = lines.len().saturating_sub(1);
+    for (i, line) in lines.iter().enumerate() {
+      if i == 0 || i == last {
+        self.writeln(line)?;
+      } else {
+        let line = escape_block_comment(line, self.comment_policy)?;
+        self.writeln(&line)?;
+      }
     }
+    Ok(())
   }
 
   /// Write a separator comment
   pub fn write_separator(&mut self, title: &str, width: usize) -> Result<()> {
+    let title = escape_block_comment(title, self.comment_policy)?;
     let prefix = "/* ";
     let suffix = " */";
 
     let mut line = String::with_capacity(width.max(prefix.len() + title.len() + suffix.len()));
     line.push_str(prefix);
-    line.push_str(title);
+    line.push_str(&title);
 
     if width >= line.len() + suffix.len() {
       // Match legacy generator output where total length becomes `width + 1`.
@@ -172,6 +530,7 @@ impl<W: Write> CodeWriter<W> {
 
   /// Begin a struct definition
   pub fn begin_struct(&mut self, name: &str) -> Result<()> {
+    self.register_symbol(name, SymbolKind::Struct, SymbolRole::Definition)?;
     self.writeln(&format!("struct {} {{", name))
   }
 
@@ -180,6 +539,17 @@ impl<W: Write> CodeWriter<W> {
     self.writeln("};")
   }
 
+  /// Begin a union definition
+  pub fn begin_union(&mut self, name: &str) -> Result<()> {
+    self.register_symbol(name, SymbolKind::Struct, SymbolRole::Definition)?;
+    self.writeln(&format!("union {} {{", name))
+  }
+
+  /// End a union definition
+  pub fn end_union(&mut self) -> Result<()> {
+    self.writeln("};")
+  }
+
   /// Begin an enum definition
   pub fn begin_enum(&mut self, name: &str) -> Result<()> {
     self.writeln(&format!("enum {} {{", name))
@@ -190,15 +560,54 @@ impl<W: Write> CodeWriter<W> {
     self.writeln("};")
   }
 
+  /// Begin a C++ scoped enum definition with an optional underlying type
+  pub fn begin_enum_class(&mut self, name: &str, underlying: Option<&str>) -> Result<()> {
+    self.check_standard("enum class", LanguageStandard::is_cpp)?;
+    match underlying {
+      Some(ty) => self.writeln(&format!("enum class {} : {} {{", name, ty)),
+      None => self.writeln(&format!("enum class {} {{", name)),
+    }
+  }
+
+  /// End a C++ scoped enum definition
+  pub fn end_enum_class(&mut self) -> Result<()> {
+    self.writeln("};")
+  }
+
   /// Write an enum member
   pub fn write_enum_member(&mut self, name: &str, value: Option<&str>) -> Result<()> {
+    self.register_symbol(name, SymbolKind::EnumMember, SymbolRole::Definition)?;
     match value {
       Some(val) => self.writeln(&format!("    {} = {},", name, val)),
       None => self.writeln(&format!("    {},", name)),
     }
   }
 
+  /// Write an enum member followed by a trailing documentation comment
+  pub fn write_documented_enum_member(
+    &mut self,
+    name: &str,
+    value: Option<&str>,
+    doc: &str,
+  ) -> Result<()> {
+    let trailing = self.trailing_doc(doc)?;
+    self.register_symbol(name, SymbolKind::EnumMember, SymbolRole::Definition)?;
+    match value {
+      Some(val) => self.writeln(&format!("    {} = {}, {}", name, val, trailing)),
+      None => self.writeln(&format!("    {}, {}", name, trailing)),
+    }
+  }
+
+  /// Write an enum member whose value is checked to fit in an `int`
+  pub fn write_enum_member_value(&mut self, name: &str, value: i64) -> Result<()> {
+    let literal = int_literal(value as i128, CType::Int32, NumberFormat::Decimal)?;
+    self.write_enum_member(name, Some(&literal))
+  }
+
   /// Begin a function definition
+  ///
+  /// The function is reported to the symbol registry unless `ret_type` makes
+  /// it `static`.
   pub fn begin_function(
     &mut self,
     ret_type: &str,
@@ -216,11 +625,17 @@ impl<W: Write> CodeWriter<W> {
       format!("({})", args_formatted.join(", "))
     };
 
-    self.writeln(&format!("{} {}{} {{", ret_type, name, args_str))
+    if has_external_linkage(ret_type) {
+      self.register_symbol(name, SymbolKind::Function, SymbolRole::Definition)?;
+    }
+    self.writeln(&format!("{} {}{} {{", ret_type, name, args_str))?;
+    self.declarations_end = Some(self.lines);
+    Ok(())
   }
 
   /// End a function definition
   pub fn end_function(&mut self) -> Result<()> {
+    self.declarations_end = None;
     self.writeln("}")
   }
 
@@ -231,15 +646,31 @@ impl<W: Write> CodeWriter<W> {
     var_name: &str,
     comment: Option<&str>,
   ) -> Result<()> {
+    self.check_declaration_position()?;
     if let Some(cmt) = comment {
       self.write_comment(cmt)?;
     }
-    self.writeln(&format!("{} {};", type_name, var_name))
+    self.extend_declarations(|w| w.writeln(&format!("{} {};", type_name, var_name)))
+  }
+
+  /// Write a variable declaration followed by a trailing documentation comment
+  pub fn write_documented_variable(
+    &mut self,
+    type_name: &str,
+    var_name: &str,
+    doc: &str,
+  ) -> Result<()> {
+    self.check_declaration_position()?;
+    let trailing = self.trailing_doc(doc)?;
+    self.extend_declarations(|w| w.writeln(&format!("{} {}; {}", type_name, var_name, trailing)))
   }
 
-  /// Write a #include directive
+  /// Write a #include directive (registered instead inside `write_with_includes`)
   pub fn write_include(&mut self, header: &str, is_system: bool) -> Result<()> {
-    if is_system {
+    if self.deferred.is_some() {
+      self.require_include(header, is_system);
+      Ok(())
+    } else if is_system {
       self.writeln(&format!("#include <{}>", header))
     } else {
       self.writeln(&format!("#include \"{}\"", header))
@@ -248,30 +679,210 @@ impl<W: Write> CodeWriter<W> {
 
   /// Write a #define directive
   pub fn write_define(&mut self, name: &str, value: Option<&str>) -> Result<()> {
+    self.register_symbol(name, SymbolKind::Macro, SymbolRole::Definition)?;
     match value {
       Some(val) => self.writeln(&format!("#define {} {}", name, val)),
       None => self.writeln(&format!("#define {}", name)),
     }
   }
 
+  /// Write a #define directive whose value is an expression, parenthesized unless it is a single token
+  pub fn write_define_expr(&mut self, name: &str, value: &Expr) -> Result<()> {
+    self.write_define(name, Some(&value.macro_body()))
+  }
+
+  /// Write a function-like macro, continuing multi-line bodies with aligned backslashes
+  pub fn write_macro(&mut self, definition: &FunctionMacro) -> Result<()> {
+    definition.validate()?;
+    self.register_symbol(&definition.name, SymbolKind::Macro, SymbolRole::Definition)?;
+    let indent = repeat_str(" ", self.indent_size);
+    for line in definition.render_lines(&indent) {
+      self.writeln(&line)?;
+    }
+    Ok(())
+  }
+
+  /// Write a #undef directive
+  pub fn write_undef(&mut self, name: &str) -> Result<()> {
+    self.writeln(&format!("#undef {}", name))
+  }
+
+  /// Write a #define directive whose value is an integer literal of the given type
+  pub fn write_int_define(
+    &mut self,
+    name: &str,
+    value: i128,
+    ty: CType,
+    format: NumberFormat,
+  ) -> Result<()> {
+    let literal = int_literal(value, ty, format)?;
+    if value < 0 && !literal.starts_with('(') {
+      self.write_define(name, Some(&format!("({})", literal)))
+    } else {
+      self.write_define(name, Some(&literal))
+    }
+  }
+
+  /// Write a #define directive whose value is a floating point literal of the given type
+  pub fn write_float_define(&mut self, name: &str, value: f64, ty: CType) -> Result<()> {
+    let literal = float_literal(value, ty)?;
+    if literal.starts_with('-') {
+      self.write_define(name, Some(&format!("({})", literal)))
+    } else {
+      self.write_define(name, Some(&literal))
+    }
+  }
+
+  /// Write a #define directive whose value is an escaped string literal
+  pub fn write_string_define(&mut self, name: &str, value: &str) -> Result<()> {
+    self.write_define(name, Some(&c_string_literal(value)))
+  }
+
+  /// Write a string constant, splitting long values into adjacent literals
+  ///
+  /// `declaration` is everything before the `=`, e.g. `static const char banner[]`.
+  pub fn write_string_constant(
+    &mut self,
+    declaration: &str,
+    value: &str,
+    max_width: usize,
+  ) -> Result<()> {
+    let width = max_width.saturating_sub((self.indent_level + 1) * self.indent_size + 1);
+    let pieces = split_string_literal(value, width, NonAsciiMode::HexEscape);
+    if pieces.len() == 1 {
+      return self.writeln(&format!("{} = {};", declaration, pieces[0]));
+    }
+
+    self.writeln(&format!("{} =", declaration))?;
+    self.indent();
+    let last = pieces.len() - 1;
+    for (i, piece) in pieces.iter().enumerate() {
+      let terminator = if i == last { ";" } else { "" };
+      self.writeln(&format!("{}{}", piece, terminator))?;
+    }
+    self.dedent();
+    Ok(())
+  }
+
   /// Write a #ifdef directive
   pub fn write_ifdef(&mut self, name: &str) -> Result<()> {
+    self.conditionals.push(Conditional::new(name));
     self.writeln(&format!("#ifdef {}", name))
   }
 
   /// Write a #ifndef directive
   pub fn write_ifndef(&mut self, name: &str) -> Result<()> {
+    self
+      .conditionals
+      .push(Conditional::new(&format!("!{}", name)));
     self.writeln(&format!("#ifndef {}", name))
   }
 
   /// Write a #endif directive
   pub fn write_endif(&mut self, comment: Option<&str>) -> Result<()> {
+    self.pop_conditional("#endif")?;
     match comment {
-      Some(cmt) => self.writeln(&format!("#endif // {}", cmt)),
+      Some(cmt) => {
+        let cmt = self.line_comment(cmt)?;
+        self.writeln(&format!("#endif {}", cmt))
+      }
       None => self.writeln("#endif"),
     }
   }
 
+  /// Begin a #if block
+  pub fn begin_if(&mut self, condition: &PpExpr) -> Result<()> {
+    let condition = condition.to_string();
+    self.writeln(&format!("#if {}", condition))?;
+    self.conditionals.push(Conditional::new(&condition));
+    Ok(())
+  }
+
+  /// Write a #elif directive in the innermost conditional block
+  pub fn write_elif(&mut self, condition: &PpExpr) -> Result<()> {
+    self.check_open_branch("#elif")?;
+    self.writeln(&format!("#elif {}", condition))
+  }
+
+  /// Write a #else directive in the innermost conditional block
+  pub fn write_else(&mut self) -> Result<()> {
+    self.check_open_branch("#else")?;
+    if let Some(conditional) = self.conditionals.last_mut() {
+      conditional.has_else = true;
+    }
+    self.writeln("#else")
+  }
+
+  /// End the innermost conditional block with a `#endif // condition` comment
+  pub fn end_if(&mut self) -> Result<()> {
+    let conditional = self.pop_conditional("#endif")?;
+    let cmt = self.line_comment(&conditional.condition)?;
+    self.writeln(&format!("#endif {}", cmt))
+  }
+
+  /// Write a body inside a #if block
+  pub fn write_if<F>(&mut self, condition: &PpExpr, body: F) -> Result<()>
+  where
+    F: FnOnce(&mut Self) -> Result<()>,
+  {
+    self.begin_if(condition)?;
+    body(self)?;
+    self.end_if()
+  }
+
+  /// Get the number of open conditional blocks
+  pub fn conditional_depth(&self) -> usize {
+    self.conditionals.len()
+  }
+
+  fn check_open_branch(&self, directive: &str) -> Result<()> {
+    match self.conditionals.last() {
+      None => Err(CodeGenError::UnbalancedConditional(format!(
+        "{} without a matching #if",
+        directive
+      ))),
+      Some(conditional) if conditional.has_else => {
+        Err(CodeGenError::UnbalancedConditional(format!(
+          "{} after #else in block '{}'",
+          directive, conditional.condition
+        )))
+      }
+      Some(_) => Ok(()),
+    }
+  }
+
+  fn pop_conditional(&mut self, directive: &str) -> Result<Conditional> {
+    self.conditionals.pop().ok_or_else(|| {
+      CodeGenError::UnbalancedConditional(format!("{} without a matching #if", directive))
+    })
+  }
+
+  /// Open an `extern "C"` block guarded by `__cplusplus`
+  pub fn begin_extern_c(&mut self) -> Result<()> {
+    self.write_ifdef("__cplusplus")?;
+    self.writeln("extern \"C\" {")?;
+    self.write_endif(None)
+  }
+
+  /// Close an `extern "C"` block guarded by `__cplusplus`
+  pub fn end_extern_c(&mut self) -> Result<()> {
+    self.write_ifdef("__cplusplus")?;
+    self.writeln("}")?;
+    self.write_endif(None)
+  }
+
+  /// Write a body wrapped in an `extern "C"` block guarded by `__cplusplus`
+  pub fn write_extern_c<F>(&mut self, body: F) -> Result<()>
+  where
+    F: FnOnce(&mut Self) -> Result<()>,
+  {
+    self.begin_extern_c()?;
+    self.newline()?;
+    body(self)?;
+    self.newline()?;
+    self.end_extern_c()
+  }
+
   /// Write a typedef for a struct
   pub fn write_typedef_struct(&mut self, name: &str) -> Result<()> {
     self.writeln(&format!("typedef struct {} {};", name, name))
@@ -295,11 +906,246 @@ impl<W: Write> CodeWriter<W> {
       format!("({})", args_formatted.join(", "))
     };
 
+    if has_external_linkage(ret_type) {
+      self.register_symbol(name, SymbolKind::Function, SymbolRole::Declaration)?;
+    }
     self.writeln(&format!("{} {}{};", ret_type, name, args_str))
   }
 
+  /// Begin a C++ namespace (an empty name is anonymous)
+  ///
+  /// `a::b` is written as the C++17 nested definition `namespace a::b {`. If a
+  /// standard before C++17 is selected, it is split into `namespace a {` and
+  /// `namespace b {` instead.
+  pub fn begin_namespace(&mut self, name: &str) -> Result<()> {
+    self.check_standard("namespaces", LanguageStandard::is_cpp)?;
+    if name.is_empty() {
+      self.writeln("namespace {")
+    } else if self.splits_namespace(name) {
+      for part in name.split("::") {
+        self.writeln(&format!("namespace {} {{", part))?;
+      }
+      Ok(())
+    } else {
+      self.writeln(&format!("namespace {} {{", name))
+    }
+  }
+
+  /// Check whether a nested namespace name must be split for the selected standard
+  fn splits_namespace(&self, name: &str) -> bool {
+    name.contains("::") && self.standard.is_some_and(|s| !s.has_nested_namespaces())
+  }
+
+  /// End a C++ namespace
+  pub fn end_namespace(&mut self, name: &str) -> Result<()> {
+    if self.splits_namespace(name) {
+      for part in name.rsplit("::") {
+        self.writeln(&format!("}} // namespace {}", part))?;
+      }
+      Ok(())
+    } else if name.is_empty() {
+      self.writeln("} // namespace")
+    } else {
+      self.writeln(&format!("}} // namespace {}", name))
+    }
+  }
+
+  /// Begin a C++ class definition with optional base classes
+  pub fn begin_class(&mut self, name: &str, bases: &[(AccessSpecifier, &str)]) -> Result<()> {
+    self.check_standard("classes", LanguageStandard::is_cpp)?;
+    if bases.is_empty() {
+      self.writeln(&format!("class {} {{", name))
+    } else {
+      let bases: Vec<String> = bases
+        .iter()
+        .map(|(access, base)| format!("{} {}", access.as_str(), base))
+        .collect();
+      self.writeln(&format!("class {} : {} {{", name, bases.join(", ")))
+    }
+  }
+
+  /// End a C++ class definition
+  pub fn end_class(&mut self) -> Result<()> {
+    self.writeln("};")
+  }
+
+  /// Write an access specifier label, outdented one level from the class members
+  pub fn write_access_specifier(&mut self, access: AccessSpecifier) -> Result<()> {
+    self.check_standard("access specifiers", LanguageStandard::is_cpp)?;
+    let level = self.indent_level;
+    self.dedent();
+    let result = self.writeln(&format!("{}:", access.as_str()));
+    self.indent_level = level;
+    result
+  }
+
+  /// Write a template parameter list (e.g. `["typename T", "size_t N"]`)
+  pub fn write_template(&mut self, params: &[&str]) -> Result<()> {
+    self.check_standard("templates", LanguageStandard::is_cpp)?;
+    self.writeln(&format!("template <{}>", params.join(", ")))
+  }
+
+  /// Write a member function declaration inside a class body
+  pub fn write_method_declaration(&mut self, method: &Method) -> Result<()> {
+    self.check_standard("member functions", LanguageStandard::is_cpp)?;
+    self.writeln(&method.declaration())
+  }
+
+  /// Begin a member function definition, qualified with the class name when given
+  pub fn begin_method(&mut self, class_name: Option<&str>, method: &Method) -> Result<()> {
+    self.check_standard("member functions", LanguageStandard::is_cpp)?;
+    self.writeln(&format!("{} {{", method.signature(class_name)))
+  }
+
+  /// End a member function definition
+  pub fn end_method(&mut self) -> Result<()> {
+    self.writeln("}")
+  }
+
+  /// Begin a constructor definition with a member initializer list
+  pub fn begin_constructor(
+    &mut self,
+    class_name: Option<&str>,
+    constructor: &Method,
+    initializers: &[(&str, &str)],
+  ) -> Result<()> {
+    if initializers.is_empty() {
+      return self.begin_method(class_name, constructor);
+    }
+    self.check_standard("constructors", LanguageStandard::is_cpp)?;
+
+    self.writeln(&constructor.signature(class_name))?;
+    let inits: Vec<String> = initializers
+      .iter()
+      .map(|(member, value)| format!("{}({})", member, value))
+      .collect();
+    self.indent();
+    let result = self.writeln(&format!(": {} {{", inits.join(", ")));
+    self.dedent();
+    result
+  }
+
+  /// Write a function declaration preceded by its documentation comment
+  ///
+  /// Fails if the comment documents a parameter the function does not have.
+  pub fn write_documented_function_declaration(
+    &mut self,
+    doc: &DocComment,
+    ret_type: &str,
+    name: &str,
+    args: &[(&str, &str)],
+  ) -> Result<()> {
+    for (param, _) in &doc.params {
+      if !args.iter().any(|(_, arg_name)| arg_name == param) {
+        return Err(CodeGenError::InvalidDefinition(format!(
+          "documentation of '{}' describes unknown parameter '{}'",
+          name, param
+        )));
+      }
+    }
+    self.write_doc_comment(doc)?;
+    self.write_function_declaration(ret_type, name, args)
+  }
+
+  /// Write `declaration = { ... };` with the initializer of any `Serialize` value
+  pub fn write_serialized<T: Serialize + ?Sized>(
+    &mut self,
+    declaration: &str,
+    value: &T,
+    options: &InitializerOptions,
+  ) -> Result<()> {
+    Initializer::from_value(value, options)?.write_definition(self, declaration, options)
+  }
+
+  /// Write a constant data table (`static const T name[N] = {...};`)
+  pub fn write_array<T: TableValue>(
+    &mut self,
+    type_name: &str,
+    name: &str,
+    values: &[T],
+    options: &TableOptions,
+  ) -> Result<()> {
+    if values.is_empty() {
+      return Err(CodeGenError::InvalidDefinition(format!(
+        "data table '{}' has no values",
+        name
+      )));
+    }
+    if options.designated_indices {
+      self.check_standard("array designators", |s| s.has_array_designators())?;
+    }
+    if options.designated_fields && values.iter().any(|v| v.has_fields()) {
+      self.check_standard("designated initializers", |s| {
+        s.has_designated_initializers()
+      })?;
+    }
+
+    let size = match &options.size {
+      TableSize::Count => values.len().to_string(),
+      TableSize::Unsized => String::new(),
+      TableSize::Expr(expr) => expr.clone(),
+    };
+    let storage = if options.storage.is_empty() {
+      String::new()
+    } else {
+      format!("{} ", options.storage)
+    };
+
+    // Format first so that a bad value leaves no partial table behind
+    let rows = format_table_rows(values, options)?;
+    self.writeln(&format!("{}{} {}[{}] = {{", storage, type_name, name, size))?;
+    self.indent();
+    for row in rows {
+      self.writeln(&row)?;
+    }
+    self.dedent();
+    self.writeln("};")
+  }
+
+  /// Write a compile-time assertion of an invariant
+  pub fn write_static_assert(&mut self, invariant: &Invariant) -> Result<()> {
+    self.static_asserts += 1;
+    let id = if self.assert_scope.is_empty() {
+      self.static_asserts.to_string()
+    } else {
+      format!("{}_{}", self.assert_scope, self.static_asserts)
+    };
+    self.writeln(&invariant.render(self.assert_style, &id))
+  }
+
+  /// Write compile-time assertions of several invariants
+  pub fn write_static_asserts(&mut self, invariants: &[Invariant]) -> Result<()> {
+    for invariant in invariants {
+      self.write_static_assert(invariant)?;
+    }
+    Ok(())
+  }
+
   /// Flush the underlying writer
+  ///
+  /// Fails if a preprocessor conditional block is still open, or if called
+  /// inside `write_with_includes`, whose output is still buffered.
   pub fn flush(&mut self) -> Result<()> {
+    if let Some(conditional) = self.conditionals.last() {
+      return Err(CodeGenError::UnbalancedConditional(format!(
+        "{} block(s) left open, innermost '{}'",
+        self.conditionals.len(),
+        conditional.condition
+      )));
+    }
+    if self.deferred.is_some() {
+      return Err(CodeGenError::InvalidDefinition(
+        "cannot flush while includes are being collected".to_string(),
+      ));
+    }
     self.writer.flush().map_err(|e| CodeGenError::Io(e))
   }
 }
+
+/// Check whether a function with this return type is visible to the linker
+///
+/// `static` functions are local to their translation unit, so the same name
+/// may be defined by several generated files.
+fn has_external_linkage(ret_type: &str) -> bool {
+  !ret_type.split_whitespace().any(|word| word == "static")
+}
@@ -0,0 +1,55 @@
+use crate::error::{CodeGenError, Result};
+
+/// What to do with comment text that would break the generated file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommentPolicy {
+  /// Rewrite the text so that it stays inside the comment
+  Sanitize,
+  /// Fail with `CodeGenError::InvalidComment`
+  Reject,
+}
+
+/// Make text safe to place inside a `/* ... */` comment
+///
+/// `*/` would end the comment early and `/*` triggers nested comment
+/// warnings, so both are broken up with a backslash when sanitizing.
+pub fn escape_block_comment(text: &str, policy: CommentPolicy) -> Result<String> {
+  if !text.contains("*/") && !text.contains("/*") {
+    return Ok(text.to_string());
+  }
+
+  match policy {
+    CommentPolicy::Reject => Err(CodeGenError::InvalidComment(format!(
+      "'{}' contains a comment delimiter",
+      text
+    ))),
+    CommentPolicy::Sanitize => Ok(text.replace("*/", "*\\/").replace("/*", "/\\*")),
+  }
+}
+
+/// Check whether a `//` comment holding `text` would swallow the next line
+///
+/// A trailing backslash (or the `??/` trigraph), even when followed by
+/// whitespace, splices the next source line into the comment.
+pub fn continues_line(text: &str) -> bool {
+  let trimmed = text.trim_end_matches([' ', '\t']);
+  trimmed.ends_with('\\') || trimmed.ends_with("??/")
+}
+
+/// Format text as a single-line comment introduced by `marker` (e.g. `//`)
+///
+/// Text that would continue onto the next line is written as a block comment
+/// when sanitizing.
+pub fn format_line_comment(marker: &str, text: &str, policy: CommentPolicy) -> Result<String> {
+  if !continues_line(text) {
+    return Ok(format!("{} {}", marker, text));
+  }
+
+  match policy {
+    CommentPolicy::Reject => Err(CodeGenError::InvalidComment(format!(
+      "'{}' ends with a line continuation",
+      text
+    ))),
+    CommentPolicy::Sanitize => Ok(format!("/* {} */", escape_block_comment(text, policy)?)),
+  }
+}
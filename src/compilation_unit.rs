@@ -0,0 +1,367 @@
+use anyhow::Context as AnyhowContext;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use crate::attribute::{Attribute, AttributeTarget};
+use crate::code_writer::CodeWriter;
+use crate::doc_comment::DocComment;
+use crate::error::Result;
+use crate::header::HeaderScaffold;
+use crate::standard::LanguageStandard;
+use crate::user_section::UserSectionManager;
+use crate::utils::get_file_name;
+
+/// Storage class of a generated function
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StorageClass {
+  /// External linkage, declared in the header
+  Extern,
+  /// `static`, private to the source file
+  Static,
+  /// `static inline`, private to the source file
+  StaticInline,
+}
+
+impl StorageClass {
+  /// Get the specifiers written before the return type
+  fn prefix(&self) -> &'static str {
+    match self {
+      StorageClass::Extern => "",
+      StorageClass::Static => "static ",
+      StorageClass::StaticInline => "static inline ",
+    }
+  }
+}
+
+/// The body of a generated function
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FunctionBody {
+  /// Generated statements, one per line
+  Lines(Vec<String>),
+  /// A user section of the given name, preserved across regeneration
+  UserSection(String),
+}
+
+/// A C function defined once and written as both declaration and definition
+#[derive(Debug, Clone)]
+pub struct FunctionDef {
+  /// The return type
+  pub return_type: String,
+  /// The function name
+  pub name: String,
+  /// The parameters as (type, name) pairs
+  pub params: Vec<(String, String)>,
+  /// The storage class
+  pub storage: StorageClass,
+  /// The function body
+  pub body: FunctionBody,
+  /// Optional documentation comment
+  pub doc: Option<DocComment>,
+  /// Attributes written on the declaration and definition
+  pub attributes: Vec<Attribute>,
+}
+
+impl FunctionDef {
+  /// Create a new function with external linkage and an empty body
+  pub fn new(return_type: &str, name: &str, params: &[(&str, &str)]) -> Self {
+    Self {
+      return_type: return_type.to_string(),
+      name: name.to_string(),
+      params: params
+        .iter()
+        .map(|(type_name, arg_name)| (type_name.to_string(), arg_name.to_string()))
+        .collect(),
+      storage: StorageClass::Extern,
+      body: FunctionBody::Lines(Vec::new()),
+      doc: None,
+      attributes: Vec::new(),
+    }
+  }
+
+  /// Set the storage class, returning the function for chaining
+  pub fn with_storage(mut self, storage: StorageClass) -> Self {
+    self.storage = storage;
+    self
+  }
+
+  /// Set generated body lines, returning the function for chaining
+  pub fn with_body(mut self, lines: &[&str]) -> Self {
+    self.body = FunctionBody::Lines(lines.iter().map(|l| l.to_string()).collect());
+    self
+  }
+
+  /// Make the body a user section, returning the function for chaining
+  pub fn with_user_section(mut self, section: &str) -> Self {
+    self.body = FunctionBody::UserSection(section.to_string());
+    self
+  }
+
+  /// Set the documentation comment, returning the function for chaining
+  pub fn with_doc(mut self, doc: DocComment) -> Self {
+    self.doc = Some(doc);
+    self
+  }
+
+  /// Add an attribute, returning the function for chaining
+  pub fn with_attribute(mut self, attribute: Attribute) -> Self {
+    self.attributes.push(attribute);
+    self
+  }
+
+  /// Check whether the function has external linkage
+  pub fn is_extern(&self) -> bool {
+    self.storage == StorageClass::Extern
+  }
+
+  /// Get the prototype without trailing `;` or `{`, e.g. `static int f(void)`
+  pub fn prototype(&self) -> String {
+    let params = if self.params.is_empty() {
+      "void".to_string()
+    } else {
+      let params: Vec<String> = self
+        .params
+        .iter()
+        .map(|(type_name, arg_name)| format!("{} {}", type_name, arg_name))
+        .collect();
+      params.join(", ")
+    };
+    format!(
+      "{}{} {}({})",
+      self.storage.prefix(),
+      self.return_type,
+      self.name,
+      params
+    )
+  }
+
+  /// Fail if the storage class is unavailable in the writer's language standard
+  fn check_storage<W: Write>(&self, writer: &CodeWriter<W>) -> Result<()> {
+    if self.storage == StorageClass::StaticInline {
+      writer.check_standard("inline functions", LanguageStandard::has_inline)?;
+    }
+    Ok(())
+  }
+
+  /// Get the parameters as borrowed (type, name) pairs
+  fn param_refs(&self) -> Vec<(&str, &str)> {
+    self
+      .params
+      .iter()
+      .map(|(type_name, arg_name)| (type_name.as_str(), arg_name.as_str()))
+      .collect()
+  }
+
+  /// Write the declaration (`prototype;`)
+  pub fn write_declaration<W: Write>(&self, writer: &mut CodeWriter<W>) -> Result<()> {
+    self.check_storage(writer)?;
+    let attributes = writer.render_attributes(&self.attributes, AttributeTarget::Declaration)?;
+    if self.is_extern() {
+      writer.write_function_declaration(
+        &attributes.prefixed(&self.return_type),
+        &self.name,
+        &self.param_refs(),
+      )
+    } else {
+      writer.writeln(&format!("{};", attributes.prefixed(&self.prototype())))
+    }
+  }
+
+  /// Write the definition, taking a user section body from `user_sections`
+  ///
+  /// User section markers are written at the indentation of the function so
+  /// that captured content is preserved verbatim.
+  pub fn write_definition<W: Write>(
+    &self,
+    writer: &mut CodeWriter<W>,
+    user_sections: &UserSectionManager,
+  ) -> Result<()> {
+    self.check_storage(writer)?;
+    let attributes = writer.render_attributes(&self.attributes, AttributeTarget::Declaration)?;
+    // Functions with internal linkage may share names across files, so only
+    // external ones are reported to the symbol registry
+    if self.is_extern() {
+      writer.begin_function(
+        &attributes.prefixed(&self.return_type),
+        &self.name,
+        &self.param_refs(),
+      )?;
+    } else {
+      writer.writeln(&format!("{} {{", attributes.prefixed(&self.prototype())))?;
+    }
+
+    match &self.body {
+      FunctionBody::Lines(lines) => {
+        writer.indent();
+        for line in lines {
+          writer.writeln(line)?;
+        }
+        writer.dedent();
+      }
+      FunctionBody::UserSection(section) => {
+        user_sections.write_section_without_description(writer, section)?;
+      }
+    }
+
+    writer.end_function()
+  }
+}
+
+/// A header/source pair generated from one set of function definitions
+///
+/// Functions with external linkage are declared in the header and defined in
+/// the source. `static` functions are kept out of the header; they are
+/// declared at the top of the source instead so that definition order does
+/// not matter.
+#[derive(Debug, Clone)]
+pub struct CompilationUnit {
+  /// The header scaffold (guard, `extern "C"`, user sections)
+  header: HeaderScaffold,
+  /// Includes required by the declarations, as (header, is_system) pairs
+  header_includes: Vec<(String, bool)>,
+  /// Includes required only by the definitions, as (header, is_system) pairs
+  source_includes: Vec<(String, bool)>,
+  /// The functions of the unit, in definition order
+  functions: Vec<FunctionDef>,
+}
+
+impl CompilationUnit {
+  /// Name of the user section written at the top of the source
+  pub const SOURCE_HEADER_SECTION: &'static str = "Header";
+  /// Name of the user section written after the generated definitions
+  pub const DEFINITIONS_SECTION: &'static str = "Definitions";
+
+  /// Create a new compilation unit whose header is written with `header`
+  pub fn new(header: HeaderScaffold) -> Self {
+    Self {
+      header,
+      header_includes: Vec::new(),
+      source_includes: Vec::new(),
+      functions: Vec::new(),
+    }
+  }
+
+  /// Add an include needed by the header declarations
+  pub fn add_header_include(&mut self, header: &str, is_system: bool) {
+    self.header_includes.push((header.to_string(), is_system));
+  }
+
+  /// Add an include needed only by the source definitions
+  pub fn add_source_include(&mut self, header: &str, is_system: bool) {
+    self.source_includes.push((header.to_string(), is_system));
+  }
+
+  /// Add a function
+  pub fn add_function(&mut self, function: FunctionDef) {
+    self.functions.push(function);
+  }
+
+  /// Get the functions of the unit
+  pub fn functions(&self) -> &[FunctionDef] {
+    &self.functions
+  }
+
+  /// Define the user sections of the header that are not defined yet
+  pub fn define_header_sections(&self, user_sections: &mut UserSectionManager) {
+    self.header.define_sections(user_sections);
+  }
+
+  /// Define the user sections of the source, including function bodies, that are not defined yet
+  pub fn define_source_sections(&self, user_sections: &mut UserSectionManager) {
+    if !user_sections.has_section(Self::SOURCE_HEADER_SECTION) {
+      user_sections
+        .define_section_with_description(Self::SOURCE_HEADER_SECTION, "File header comment");
+    }
+    for function in &self.functions {
+      if let FunctionBody::UserSection(ref section) = function.body {
+        if !user_sections.has_section(section) {
+          user_sections.define_section(section);
+        }
+      }
+    }
+    if !user_sections.has_section(Self::DEFINITIONS_SECTION) {
+      user_sections.define_section_with_description(Self::DEFINITIONS_SECTION, "User definitions");
+    }
+  }
+
+  /// Write the header with the declarations of all extern functions
+  pub fn write_header<W: Write>(
+    &self,
+    writer: &mut CodeWriter<W>,
+    user_sections: &UserSectionManager,
+  ) -> Result<()> {
+    self.header.write(writer, user_sections, |w| {
+      for (header, is_system) in &self.header_includes {
+        w.write_include(header, *is_system)?;
+      }
+      for (i, function) in self.functions.iter().filter(|f| f.is_extern()).enumerate() {
+        if i > 0 {
+          w.newline()?;
+        }
+        if let Some(ref doc) = function.doc {
+          w.write_doc_comment(doc)?;
+        }
+        function.write_declaration(w)?;
+      }
+      Ok(())
+    })
+  }
+
+  /// Write the source with the definitions of all functions
+  pub fn write_source<W: Write>(
+    &self,
+    writer: &mut CodeWriter<W>,
+    header_name: &str,
+    user_sections: &UserSectionManager,
+  ) -> Result<()> {
+    user_sections.write_section(writer, Self::SOURCE_HEADER_SECTION)?;
+    writer.write_include(header_name, false)?;
+    for (header, is_system) in &self.source_includes {
+      writer.write_include(header, *is_system)?;
+    }
+    writer.newline()?;
+
+    let mut has_static = false;
+    for function in self.functions.iter().filter(|f| !f.is_extern()) {
+      function.write_declaration(writer)?;
+      has_static = true;
+    }
+    if has_static {
+      writer.newline()?;
+    }
+
+    for function in &self.functions {
+      // Extern functions are documented in the header
+      if let (Some(doc), false) = (&function.doc, function.is_extern()) {
+        writer.write_doc_comment(doc)?;
+      }
+      function.write_definition(writer, user_sections)?;
+      writer.newline()?;
+    }
+
+    user_sections.write_section(writer, Self::DEFINITIONS_SECTION)
+  }
+
+  /// Generate a header/source pair, preserving user sections of existing files
+  pub fn generate(&self, header_path: &Path, source_path: &Path) -> Result<()> {
+    let header_name = get_file_name(header_path).unwrap_or_default();
+
+    let mut header_sections = UserSectionManager::new();
+    self.define_header_sections(&mut header_sections);
+    header_sections.capture_from_file(header_path)?;
+    let file = File::create(header_path)
+      .with_context(|| format!("Failed to create output file: {}", header_path.display()))?;
+    let mut writer = CodeWriter::new(BufWriter::new(file));
+    self.write_header(&mut writer, &header_sections)?;
+    writer.flush()?;
+
+    let mut source_sections = UserSectionManager::new();
+    self.define_source_sections(&mut source_sections);
+    source_sections.capture_from_file(source_path)?;
+    let file = File::create(source_path)
+      .with_context(|| format!("Failed to create output file: {}", source_path.display()))?;
+    let mut writer = CodeWriter::new(BufWriter::new(file));
+    self.write_source(&mut writer, &header_name, &source_sections)?;
+    writer.flush()
+  }
+}
@@ -0,0 +1,136 @@
+/// A C++ member access specifier
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccessSpecifier {
+  Public,
+  Protected,
+  Private,
+}
+
+impl AccessSpecifier {
+  /// Get the C++ keyword of the access specifier
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      AccessSpecifier::Public => "public",
+      AccessSpecifier::Protected => "protected",
+      AccessSpecifier::Private => "private",
+    }
+  }
+}
+
+/// What follows a member function declaration
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MethodBody {
+  /// A plain declaration (`;`), defined elsewhere
+  Declaration,
+  /// A pure virtual function (`= 0;`)
+  Pure,
+  /// A defaulted function (`= default;`)
+  Default,
+  /// A deleted function (`= delete;`)
+  Delete,
+}
+
+/// A C++ member function, constructor or destructor
+#[derive(Debug, Clone)]
+pub struct Method {
+  /// The return type (empty for constructors and destructors)
+  pub return_type: String,
+  /// The function name
+  pub name: String,
+  /// The parameters as (type, name) pairs
+  pub params: Vec<(String, String)>,
+  /// Whether the function is `const`
+  pub is_const: bool,
+  /// Whether the function is `virtual`
+  pub is_virtual: bool,
+  /// Whether the function is marked `override`
+  pub is_override: bool,
+  /// Whether the function is `static`
+  pub is_static: bool,
+  /// Whether the constructor is `explicit`
+  pub is_explicit: bool,
+  /// Whether the function is `noexcept`
+  pub is_noexcept: bool,
+  /// What follows the declaration
+  pub body: MethodBody,
+}
+
+impl Method {
+  /// Create a new member function
+  pub fn new(return_type: &str, name: &str, params: &[(&str, &str)]) -> Self {
+    Self {
+      return_type: return_type.to_string(),
+      name: name.to_string(),
+      params: params
+        .iter()
+        .map(|(type_name, arg_name)| (type_name.to_string(), arg_name.to_string()))
+        .collect(),
+      is_const: false,
+      is_virtual: false,
+      is_override: false,
+      is_static: false,
+      is_explicit: false,
+      is_noexcept: false,
+      body: MethodBody::Declaration,
+    }
+  }
+
+  /// Create a new constructor or destructor (no return type)
+  pub fn constructor(name: &str, params: &[(&str, &str)]) -> Self {
+    Self::new("", name, params)
+  }
+
+  /// Render the declaration as written inside the class body
+  pub fn declaration(&self) -> String {
+    let mut out = String::new();
+    if self.is_explicit {
+      out.push_str("explicit ");
+    }
+    if self.is_static {
+      out.push_str("static ");
+    }
+    if self.is_virtual {
+      out.push_str("virtual ");
+    }
+    out.push_str(&self.signature(None));
+    if self.is_override {
+      out.push_str(" override");
+    }
+    match self.body {
+      MethodBody::Declaration => {}
+      MethodBody::Pure => out.push_str(" = 0"),
+      MethodBody::Default => out.push_str(" = default"),
+      MethodBody::Delete => out.push_str(" = delete"),
+    }
+    out.push(';');
+    out
+  }
+
+  /// Render the signature, optionally qualified with a class name
+  pub fn signature(&self, class_name: Option<&str>) -> String {
+    let params: Vec<String> = self
+      .params
+      .iter()
+      .map(|(type_name, arg_name)| format!("{} {}", type_name, arg_name))
+      .collect();
+
+    let mut out = String::new();
+    if !self.return_type.is_empty() {
+      out.push_str(&self.return_type);
+      out.push(' ');
+    }
+    if let Some(class_name) = class_name {
+      out.push_str(class_name);
+      out.push_str("::");
+    }
+    out.push_str(&self.name);
+    out.push_str(&format!("({})", params.join(", ")));
+    if self.is_const {
+      out.push_str(" const");
+    }
+    if self.is_noexcept {
+      out.push_str(" noexcept");
+    }
+    out
+  }
+}
@@ -0,0 +1,220 @@
+use crate::error::Result;
+use crate::literal::c_string_literal;
+use crate::numeric::{float_literal, format_int, int_literal, CType, NumberFormat};
+
+/// How the array size is written in the declaration
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TableSize {
+  /// The number of values (`name[4]`)
+  Count,
+  /// No size, left to the compiler (`name[]`)
+  Unsized,
+  /// A size expression such as a macro name (`name[TABLE_SIZE]`)
+  Expr(String),
+}
+
+/// Layout options for a constant data table
+#[derive(Debug, Clone)]
+pub struct TableOptions {
+  /// Storage class and qualifiers written before the element type
+  pub storage: String,
+  /// How the array size is written
+  pub size: TableSize,
+  /// Number of values per line
+  pub per_line: usize,
+  /// How integer values are rendered
+  pub format: NumberFormat,
+  /// Type the integer literals are checked against and suffixed for (`None` writes them bare)
+  pub literal_type: Option<CType>,
+  /// Pad values so that columns line up
+  pub align: bool,
+  /// Prefix each line with a comment holding the index of its first value
+  pub index_comments: bool,
+  /// Designate elements by index (`[i] = v`)
+  pub designated_indices: bool,
+  /// Designate the fields of struct elements by name (`.f = v`)
+  pub designated_fields: bool,
+}
+
+impl Default for TableOptions {
+  fn default() -> Self {
+    Self {
+      storage: "static const".to_string(),
+      size: TableSize::Count,
+      per_line: 8,
+      format: NumberFormat::Decimal,
+      literal_type: None,
+      align: true,
+      index_comments: false,
+      designated_indices: false,
+      designated_fields: false,
+    }
+  }
+}
+
+/// A value that can be written as an element of a data table
+pub trait TableValue {
+  /// Render the value as a C initializer
+  fn format_value(&self, options: &TableOptions) -> Result<String>;
+
+  /// Whether the value is numeric and should be right-aligned
+  fn is_numeric(&self) -> bool {
+    true
+  }
+
+  /// Whether the value is written with named fields
+  fn has_fields(&self) -> bool {
+    false
+  }
+}
+
+macro_rules! impl_table_value_int {
+  ($($t:ty),*) => {
+    $(
+      impl TableValue for $t {
+        fn format_value(&self, options: &TableOptions) -> Result<String> {
+          match options.literal_type {
+            Some(ty) => int_literal(*self as i128, ty, options.format),
+            None => Ok(format_int(*self as i128, options.format)),
+          }
+        }
+      }
+    )*
+  };
+}
+
+impl_table_value_int!(u8, u16, u32, u64, usize, i8, i16, i32, i64, isize);
+
+impl TableValue for f32 {
+  fn format_value(&self, _options: &TableOptions) -> Result<String> {
+    float_literal(*self as f64, CType::Float)
+  }
+}
+
+impl TableValue for f64 {
+  fn format_value(&self, _options: &TableOptions) -> Result<String> {
+    float_literal(*self, CType::Double)
+  }
+}
+
+impl TableValue for &str {
+  fn format_value(&self, _options: &TableOptions) -> Result<String> {
+    Ok(c_string_literal(self))
+  }
+
+  fn is_numeric(&self) -> bool {
+    false
+  }
+}
+
+impl TableValue for String {
+  fn format_value(&self, options: &TableOptions) -> Result<String> {
+    self.as_str().format_value(options)
+  }
+
+  fn is_numeric(&self) -> bool {
+    false
+  }
+}
+
+/// A struct element of a data table, written as a brace-enclosed initializer
+#[derive(Default)]
+pub struct StructValue {
+  fields: Vec<(String, Box<dyn TableValue>)>,
+}
+
+impl StructValue {
+  /// Create an empty struct value
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Add a field value
+  pub fn add_field<V: TableValue + 'static>(&mut self, name: &str, value: V) {
+    self.fields.push((name.to_string(), Box::new(value)));
+  }
+
+  /// Add a field value, returning the struct value for chaining
+  pub fn with_field<V: TableValue + 'static>(mut self, name: &str, value: V) -> Self {
+    self.add_field(name, value);
+    self
+  }
+}
+
+impl TableValue for StructValue {
+  fn format_value(&self, options: &TableOptions) -> Result<String> {
+    let mut fields = Vec::with_capacity(self.fields.len());
+    for (name, value) in &self.fields {
+      let text = value.format_value(options)?;
+      if options.designated_fields {
+        fields.push(format!(".{} = {}", name, text));
+      } else {
+        fields.push(text);
+      }
+    }
+    Ok(format!("{{ {} }}", fields.join(", ")))
+  }
+
+  fn is_numeric(&self) -> bool {
+    false
+  }
+
+  fn has_fields(&self) -> bool {
+    true
+  }
+}
+
+/// Format the values of a data table into initializer lines (without indentation)
+pub fn format_table_rows<T: TableValue>(
+  values: &[T],
+  options: &TableOptions,
+) -> Result<Vec<String>> {
+  let per_line = options.per_line.max(1);
+  let index_width = values.len().saturating_sub(1).to_string().len();
+
+  let mut cells = Vec::with_capacity(values.len());
+  for (i, value) in values.iter().enumerate() {
+    let text = value.format_value(options)?;
+    if options.designated_indices {
+      cells.push(format!("[{:>width$}] = {},", i, text, width = index_width));
+    } else {
+      cells.push(format!("{},", text));
+    }
+  }
+
+  let cell_width = if options.align {
+    cells.iter().map(|c| c.len()).max().unwrap_or(0)
+  } else {
+    0
+  };
+
+  Ok(
+    cells
+      .chunks(per_line)
+      .enumerate()
+      .map(|(row, chunk)| {
+        let mut line = String::new();
+        if options.index_comments {
+          line.push_str(&format!(
+            "/* {:>width$} */ ",
+            row * per_line,
+            width = index_width
+          ));
+        }
+        let padded: Vec<String> = chunk
+          .iter()
+          .zip(&values[row * per_line..])
+          .map(|(cell, value)| {
+            if value.is_numeric() && !options.designated_indices {
+              format!("{:>width$}", cell, width = cell_width)
+            } else {
+              format!("{:<width$}", cell, width = cell_width)
+            }
+          })
+          .collect();
+        line.push_str(padded.join(" ").trim_end());
+        line
+      })
+      .collect(),
+  )
+}
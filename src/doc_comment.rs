@@ -0,0 +1,144 @@
+use crate::comment::{continues_line, escape_block_comment, format_line_comment, CommentPolicy};
+use crate::error::Result;
+
+/// Flavour of generated documentation comments
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DocStyle {
+  /// `/** @brief ... */` blocks and `///<` trailing comments
+  Doxygen,
+  /// `/** ... */` blocks with an implicit brief and `/**< ... */` trailing comments
+  Javadoc,
+  /// Ordinary `/* ... */` comments without markup
+  Plain,
+}
+
+/// A structured documentation comment for a declaration
+#[derive(Debug, Clone, Default)]
+pub struct DocComment {
+  /// One-line summary
+  pub brief: String,
+  /// Optional longer description
+  pub details: Option<String>,
+  /// Parameter descriptions as (name, description) pairs
+  pub params: Vec<(String, String)>,
+  /// Optional description of the return value
+  pub returns: Option<String>,
+}
+
+impl DocComment {
+  /// Create a new doc comment with a brief description
+  pub fn new(brief: &str) -> Self {
+    Self {
+      brief: brief.to_string(),
+      ..Default::default()
+    }
+  }
+
+  /// Add a longer description
+  pub fn with_details(mut self, details: &str) -> Self {
+    self.details = Some(details.to_string());
+    self
+  }
+
+  /// Add a parameter description
+  pub fn with_param(mut self, name: &str, description: &str) -> Self {
+    self
+      .params
+      .push((name.to_string(), description.to_string()));
+    self
+  }
+
+  /// Add a return value description
+  pub fn with_returns(mut self, description: &str) -> Self {
+    self.returns = Some(description.to_string());
+    self
+  }
+
+  /// Render the comment block as lines
+  pub fn render_lines(&self, style: DocStyle) -> Vec<String> {
+    let mut body = Vec::new();
+    if !self.brief.is_empty() {
+      match style {
+        DocStyle::Doxygen => body.push(format!("@brief {}", self.brief)),
+        DocStyle::Javadoc | DocStyle::Plain => body.push(self.brief.clone()),
+      }
+    }
+
+    if let Some(ref details) = self.details {
+      if !body.is_empty() {
+        body.push(String::new());
+      }
+      body.extend(details.lines().map(String::from));
+    }
+
+    if !body.is_empty() && (!self.params.is_empty() || self.returns.is_some()) {
+      body.push(String::new());
+    }
+    match style {
+      DocStyle::Doxygen | DocStyle::Javadoc => {
+        for (name, description) in &self.params {
+          body.push(format!("@param {} {}", name, description));
+        }
+        if let Some(ref returns) = self.returns {
+          body.push(format!("@return {}", returns));
+        }
+      }
+      DocStyle::Plain => {
+        if !self.params.is_empty() {
+          body.push("Parameters:".to_string());
+          for (name, description) in &self.params {
+            body.push(format!("  {} - {}", name, description));
+          }
+        }
+        if let Some(ref returns) = self.returns {
+          body.push(format!("Returns: {}", returns));
+        }
+      }
+    }
+
+    wrap_block(style, &body)
+  }
+}
+
+/// Render a file header comment
+pub fn render_file_doc(style: DocStyle, file_name: &str, brief: &str) -> Vec<String> {
+  let body = match style {
+    DocStyle::Doxygen => vec![format!("@file {}", file_name), format!("@brief {}", brief)],
+    DocStyle::Javadoc => vec![format!("@file {}", file_name), brief.to_string()],
+    DocStyle::Plain => vec![format!("{}: {}", file_name, brief)],
+  };
+  wrap_block(style, &body)
+}
+
+/// Render a trailing comment for a struct field or enum member
+pub fn render_trailing_doc(style: DocStyle, text: &str, policy: CommentPolicy) -> Result<String> {
+  match style {
+    DocStyle::Doxygen if !continues_line(text) => Ok(format!("///< {}", text)),
+    DocStyle::Doxygen if policy == CommentPolicy::Reject => {
+      format_line_comment("///<", text, policy)
+    }
+    // A trailing backslash would continue a `///<` comment, so use the block form
+    DocStyle::Doxygen | DocStyle::Javadoc => {
+      Ok(format!("/**< {} */", escape_block_comment(text, policy)?))
+    }
+    DocStyle::Plain => Ok(format!("/* {} */", escape_block_comment(text, policy)?)),
+  }
+}
+
+fn wrap_block(style: DocStyle, body: &[String]) -> Vec<String> {
+  let opener = match style {
+    DocStyle::Doxygen | DocStyle::Javadoc => "/**",
+    DocStyle::Plain => "/*",
+  };
+
+  let mut lines = vec![opener.to_string()];
+  for line in body {
+    if line.is_empty() {
+      lines.push(" *".to_string());
+    } else {
+      lines.push(format!(" * {}", line));
+    }
+  }
+  lines.push(" */".to_string());
+  lines
+}
@@ -0,0 +1,212 @@
+use anyhow::Context as AnyhowContext;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use crate::attribute::{Attribute, AttributeTarget};
+use crate::code_writer::CodeWriter;
+use crate::data_table::{TableOptions, TableSize};
+use crate::error::{CodeGenError, Result};
+use crate::header::HeaderScaffold;
+use crate::naming::identifier_from_name;
+use crate::numeric::NumberFormat;
+use crate::user_section::UserSectionManager;
+use crate::utils::get_file_name;
+
+/// A binary blob embedded into generated C code as a `const uint8_t` array
+#[derive(Debug, Clone)]
+pub struct BinaryEmbed {
+  /// The C identifier of the array
+  name: String,
+  /// The embedded bytes
+  data: Vec<u8>,
+  /// Optional alignment of the array in bytes
+  alignment: Option<usize>,
+  /// Optional linker section for the array
+  section: Option<String>,
+  /// Whether the data is compressed (emitted as a flag macro)
+  compressed: bool,
+  /// Number of bytes per line
+  bytes_per_line: usize,
+}
+
+impl BinaryEmbed {
+  /// Create a new embed from a byte blob
+  ///
+  /// C has no zero-length arrays, so `data` must not be empty.
+  pub fn new(name: &str, data: Vec<u8>) -> Result<Self> {
+    let name = identifier_from_name(name)?;
+    if data.is_empty() {
+      return Err(CodeGenError::InvalidDefinition(format!(
+        "cannot embed '{}': the data is empty",
+        name
+      )));
+    }
+    Ok(Self {
+      name,
+      data,
+      alignment: None,
+      section: None,
+      compressed: false,
+      bytes_per_line: 12,
+    })
+  }
+
+  /// Create a new embed from the contents of a file
+  pub fn from_file(name: &str, path: &Path) -> Result<Self> {
+    let data =
+      fs::read(path).with_context(|| format!("Failed to read file: {}", path.display()))?;
+    if data.is_empty() {
+      return Err(CodeGenError::InvalidDefinition(format!(
+        "cannot embed '{}': the file is empty",
+        path.display()
+      )));
+    }
+    Self::new(name, data)
+  }
+
+  /// Set the alignment of the array in bytes
+  pub fn set_alignment(&mut self, alignment: usize) {
+    self.alignment = Some(alignment);
+  }
+
+  /// Place the array in a linker section
+  pub fn set_section(&mut self, section: &str) {
+    self.section = Some(section.to_string());
+  }
+
+  /// Mark the data as compressed
+  pub fn set_compressed(&mut self, compressed: bool) {
+    self.compressed = compressed;
+  }
+
+  /// Set the number of bytes per line
+  pub fn set_bytes_per_line(&mut self, bytes_per_line: usize) {
+    self.bytes_per_line = bytes_per_line;
+  }
+
+  /// Get the C identifier of the array
+  pub fn name(&self) -> &str {
+    &self.name
+  }
+
+  /// Get the embedded bytes
+  pub fn data(&self) -> &[u8] {
+    &self.data
+  }
+
+  /// Get the name of the length macro
+  pub fn length_macro(&self) -> String {
+    format!("{}_LEN", self.name.to_uppercase())
+  }
+
+  /// Get the name of the compression flag macro
+  pub fn compressed_macro(&self) -> String {
+    format!("{}_COMPRESSED", self.name.to_uppercase())
+  }
+
+  /// Write the declarations of the embed (length macro, flag macro and extern array)
+  pub fn write_declarations<W: Write>(&self, writer: &mut CodeWriter<W>) -> Result<()> {
+    if writer.has_reserved_includes() {
+      writer.require_include("stddef.h", true);
+      writer.require_include("stdint.h", true);
+    }
+    writer.write_define(&self.length_macro(), Some(&format!("{}u", self.data.len())))?;
+    writer.write_define(
+      &self.compressed_macro(),
+      Some(if self.compressed { "1" } else { "0" }),
+    )?;
+    writer.writeln(&format!(
+      "extern const uint8_t {}[{}];",
+      self.name,
+      self.length_macro()
+    ))
+  }
+
+  /// Write the array definition, with alignment and section in the writer's compiler syntax
+  pub fn write_definition<W: Write>(&self, writer: &mut CodeWriter<W>) -> Result<()> {
+    let mut attributes = Vec::new();
+    if let Some(alignment) = self.alignment {
+      attributes.push(Attribute::Aligned(alignment));
+    }
+    if let Some(ref section) = self.section {
+      attributes.push(Attribute::Section(section.clone()));
+    }
+    let storage = writer
+      .render_attributes(&attributes, AttributeTarget::Declaration)?
+      .prefixed("const");
+
+    let options = TableOptions {
+      storage,
+      size: TableSize::Expr(self.length_macro()),
+      per_line: self.bytes_per_line,
+      format: NumberFormat::Hex { width: 2 },
+      ..Default::default()
+    };
+
+    writer.write_array("uint8_t", &self.name, &self.data, &options)
+  }
+
+  /// Get the header scaffold, with the guard derived from `header_path`
+  pub fn scaffold(&self, header_path: &Path) -> Result<HeaderScaffold> {
+    HeaderScaffold::for_file(header_path)
+  }
+
+  /// Write a complete header file for the embed
+  ///
+  /// `user_sections` must hold the sections of `scaffold`, see
+  /// [`HeaderScaffold::define_sections`].
+  pub fn write_header<W: Write>(
+    &self,
+    writer: &mut CodeWriter<W>,
+    scaffold: &HeaderScaffold,
+    user_sections: &UserSectionManager,
+  ) -> Result<()> {
+    scaffold.write(writer, user_sections, |w| self.write_declarations(w))
+  }
+
+  /// Write a complete source file for the embed
+  pub fn write_source<W: Write>(
+    &self,
+    writer: &mut CodeWriter<W>,
+    header_name: &str,
+    user_sections: &UserSectionManager,
+  ) -> Result<()> {
+    user_sections.write_section(writer, "Header")?;
+    writer.write_include(header_name, false)?;
+    writer.newline()?;
+
+    self.write_definition(writer)?;
+    writer.newline()?;
+
+    user_sections.write_section(writer, "Definitions")
+  }
+
+  /// Generate a header/source pair, preserving user sections of existing files
+  pub fn generate(&self, header_path: &Path, source_path: &Path) -> Result<()> {
+    let header_name = get_file_name(header_path).unwrap_or_else(|| format!("{}.h", self.name));
+
+    let mut header_sections = UserSectionManager::new();
+    self
+      .scaffold(header_path)?
+      .generate(header_path, &mut header_sections, |w| {
+        self.write_declarations(w)
+      })?;
+
+    let mut source_sections = Self::user_sections(&["Header", "Definitions"]);
+    source_sections.capture_from_file(source_path)?;
+    let file = File::create(source_path)
+      .with_context(|| format!("Failed to create output file: {}", source_path.display()))?;
+    let mut writer = CodeWriter::new(BufWriter::new(file));
+    self.write_source(&mut writer, &header_name, &source_sections)?;
+    writer.flush()
+  }
+
+  fn user_sections(names: &[&str]) -> UserSectionManager {
+    let mut user_sections = UserSectionManager::new();
+    for name in names {
+      user_sections.define_section(name);
+    }
+    user_sections
+  }
+}
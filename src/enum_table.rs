@@ -0,0 +1,283 @@
+use std::collections::HashMap;
+use std::io::Write;
+
+use crate::code_writer::CodeWriter;
+use crate::error::{CodeGenError, Result};
+use crate::literal::c_string_literal;
+
+/// Strategy used by the generated to-string function
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LookupStyle {
+  /// A `switch` statement with one `case` per variant
+  Switch,
+  /// A name table indexed by the enum value (values must be contiguous from 0)
+  Array,
+}
+
+/// A single variant of an enum table
+#[derive(Debug, Clone)]
+pub struct EnumVariant {
+  /// The C identifier of the variant
+  pub name: String,
+  /// Optional explicit value
+  pub value: Option<i64>,
+  /// The string the variant maps to (defaults to the identifier)
+  pub string: String,
+}
+
+/// An enum together with its to-string and from-string lookup functions
+#[derive(Debug, Clone)]
+pub struct EnumTable {
+  /// The enum tag name
+  name: String,
+  /// The variants in declaration order
+  variants: Vec<EnumVariant>,
+  /// Strategy used by the to-string function
+  lookup_style: LookupStyle,
+  /// String returned by the to-string function for unknown values
+  unknown_string: String,
+  /// Expression returned by the from-string function when no variant matches
+  not_found: String,
+}
+
+impl EnumTable {
+  /// Create a new enum table with a tag name
+  pub fn new(name: &str) -> Self {
+    Self {
+      name: name.to_string(),
+      variants: Vec::new(),
+      lookup_style: LookupStyle::Switch,
+      unknown_string: "UNKNOWN".to_string(),
+      not_found: format!("(enum {})-1", name),
+    }
+  }
+
+  /// Add a variant whose string is its identifier
+  pub fn add_variant(&mut self, name: &str, value: Option<i64>) {
+    self.add_variant_with_string(name, value, name);
+  }
+
+  /// Add a variant with a custom string
+  pub fn add_variant_with_string(&mut self, name: &str, value: Option<i64>, string: &str) {
+    self.variants.push(EnumVariant {
+      name: name.to_string(),
+      value,
+      string: string.to_string(),
+    });
+  }
+
+  /// Set the strategy used by the to-string function
+  pub fn set_lookup_style(&mut self, style: LookupStyle) {
+    self.lookup_style = style;
+  }
+
+  /// Set the string returned for values without a variant
+  pub fn set_unknown_string(&mut self, unknown: &str) {
+    self.unknown_string = unknown.to_string();
+  }
+
+  /// Set the expression returned by the from-string function when nothing matches
+  pub fn set_not_found(&mut self, expr: &str) {
+    self.not_found = expr.to_string();
+  }
+
+  /// Get the enum tag name
+  pub fn name(&self) -> &str {
+    &self.name
+  }
+
+  /// Get the variants
+  pub fn variants(&self) -> &[EnumVariant] {
+    &self.variants
+  }
+
+  /// Get the C type of the enum
+  pub fn type_name(&self) -> String {
+    format!("enum {}", self.name)
+  }
+
+  /// Get the name of the generated to-string function
+  pub fn to_string_fn(&self) -> String {
+    format!("{}_to_string", self.name)
+  }
+
+  /// Get the name of the generated from-string function
+  pub fn from_string_fn(&self) -> String {
+    format!("{}_from_string", self.name)
+  }
+
+  /// Get the name of the name table used by the array lookup style
+  pub fn names_table(&self) -> String {
+    format!("{}_names", self.name)
+  }
+
+  /// Write the enum definition
+  pub fn write_enum<W: Write>(&self, writer: &mut CodeWriter<W>) -> Result<()> {
+    writer.begin_enum(&self.name)?;
+    for variant in &self.variants {
+      let value = variant.value.map(|v| v.to_string());
+      writer.write_enum_member(&variant.name, value.as_deref())?;
+    }
+    writer.end_enum()
+  }
+
+  /// Write the prototypes of the lookup functions
+  pub fn write_declarations<W: Write>(&self, writer: &mut CodeWriter<W>) -> Result<()> {
+    let type_name = self.type_name();
+    writer.write_function_declaration(
+      "const char *",
+      &self.to_string_fn(),
+      &[(&type_name, "value")],
+    )?;
+    writer.write_function_declaration(
+      &type_name,
+      &self.from_string_fn(),
+      &[("const char *", "str")],
+    )
+  }
+
+  /// Write the lookup function definitions (the source needs `<string.h>`)
+  pub fn write_definitions<W: Write>(&self, writer: &mut CodeWriter<W>) -> Result<()> {
+    match self.lookup_style {
+      LookupStyle::Switch => self.write_switch_lookup(writer)?,
+      LookupStyle::Array => self.write_array_lookup(writer)?,
+    }
+    writer.newline()?;
+    self.write_reverse_lookup(writer)
+  }
+
+  /// Write the enum, the lookup function prototypes and their definitions
+  pub fn write_all<W: Write>(&self, writer: &mut CodeWriter<W>) -> Result<()> {
+    self.write_enum(writer)?;
+    writer.newline()?;
+    self.write_declarations(writer)?;
+    writer.newline()?;
+    self.write_definitions(writer)
+  }
+
+  fn write_switch_lookup<W: Write>(&self, writer: &mut CodeWriter<W>) -> Result<()> {
+    self.check_unique_values()?;
+
+    let type_name = self.type_name();
+    writer.begin_function(
+      "const char *",
+      &self.to_string_fn(),
+      &[(&type_name, "value")],
+    )?;
+    writer.indent();
+    writer.writeln("switch (value) {")?;
+    for variant in &self.variants {
+      writer.writeln(&format!(
+        "case {}: return {};",
+        variant.name,
+        c_string_literal(&variant.string)
+      ))?;
+    }
+    writer.writeln(&format!(
+      "default: return {};",
+      c_string_literal(&self.unknown_string)
+    ))?;
+    writer.writeln("}")?;
+    writer.dedent();
+    writer.end_function()
+  }
+
+  fn write_array_lookup<W: Write>(&self, writer: &mut CodeWriter<W>) -> Result<()> {
+    self.check_contiguous()?;
+
+    let table = self.names_table();
+    writer.writeln(&format!("static const char *const {}[] = {{", table))?;
+    writer.indent();
+    for variant in &self.variants {
+      writer.writeln(&format!("{},", c_string_literal(&variant.string)))?;
+    }
+    writer.dedent();
+    writer.writeln("};")?;
+    writer.newline()?;
+
+    let type_name = self.type_name();
+    writer.begin_function(
+      "const char *",
+      &self.to_string_fn(),
+      &[(&type_name, "value")],
+    )?;
+    writer.indent();
+    writer.writeln(&format!(
+      "if ((unsigned)value >= sizeof({0}) / sizeof({0}[0])) {{",
+      table
+    ))?;
+    writer.indent();
+    writer.writeln(&format!(
+      "return {};",
+      c_string_literal(&self.unknown_string)
+    ))?;
+    writer.dedent();
+    writer.writeln("}")?;
+    writer.writeln(&format!("return {}[value];", table))?;
+    writer.dedent();
+    writer.end_function()
+  }
+
+  fn write_reverse_lookup<W: Write>(&self, writer: &mut CodeWriter<W>) -> Result<()> {
+    let type_name = self.type_name();
+    writer.begin_function(
+      &type_name,
+      &self.from_string_fn(),
+      &[("const char *", "str")],
+    )?;
+    writer.indent();
+    let null = writer.null_pointer();
+    writer.writeln(&format!("if (str == {}) {{", null))?;
+    writer.indent();
+    writer.writeln(&format!("return {};", self.not_found))?;
+    writer.dedent();
+    writer.writeln("}")?;
+    for variant in &self.variants {
+      writer.writeln(&format!(
+        "if (strcmp(str, {}) == 0) {{",
+        c_string_literal(&variant.string)
+      ))?;
+      writer.indent();
+      writer.writeln(&format!("return {};", variant.name))?;
+      writer.dedent();
+      writer.writeln("}")?;
+    }
+    writer.writeln(&format!("return {};", self.not_found))?;
+    writer.dedent();
+    writer.end_function()
+  }
+
+  /// Check that the variants can index a name table
+  fn check_contiguous(&self) -> Result<()> {
+    for (index, variant) in self.variants.iter().enumerate() {
+      let expected = index as i64;
+      let value = variant.value.unwrap_or(expected);
+      if value != expected {
+        return Err(CodeGenError::InvalidDefinition(format!(
+          "enum '{}' cannot use an array lookup: '{}' has value {}, expected {}",
+          self.name, variant.name, value, expected
+        )));
+      }
+    }
+    Ok(())
+  }
+
+  /// Check that no two variants share a value, which would duplicate a `case` label
+  ///
+  /// Variants without an explicit value follow the previous one, as in C.
+  fn check_unique_values(&self) -> Result<()> {
+    let mut seen: HashMap<i64, &str> = HashMap::new();
+    let mut next = 0;
+    for variant in &self.variants {
+      let value = variant.value.unwrap_or(next);
+      if let Some(first) = seen.insert(value, &variant.name) {
+        return Err(CodeGenError::InvalidDefinition(format!(
+          "enum '{}' cannot use a switch lookup: '{}' and '{}' both have value {}",
+          self.name, first, variant.name, value
+        )));
+      }
+      next = value.wrapping_add(1);
+    }
+    Ok(())
+  }
+}
@@ -33,6 +33,46 @@ pub enum CodeGenError {
   #[error("Unknown user section: '{0}'")]
   UnknownSection(String),
 
+  #[error("Invalid definition: {0}")]
+  InvalidDefinition(String),
+
+  #[error("Unbalanced preprocessor conditional: {0}")]
+  UnbalancedConditional(String),
+
+  #[error("Value {value} does not fit in type {type_name}")]
+  ValueOutOfRange { value: String, type_name: String },
+
+  #[error("Invalid comment text: {0}")]
+  InvalidComment(String),
+
+  #[error("Content of user section '{0}' contains one of its own markers")]
+  MarkerInSectionContent(String),
+
+  #[error("{construct} is not available in {standard}")]
+  UnsupportedConstruct { construct: String, standard: String },
+
+  #[error("{attribute} attribute is not supported by {compiler}")]
+  UnsupportedAttribute { attribute: String, compiler: String },
+
+  #[error("Schema error at {location}: {message}")]
+  Schema { location: String, message: String },
+
+  #[error("SVD error at {location}: {message}")]
+  Svd { location: String, message: String },
+
+  #[error("Serialization error: {0}")]
+  Serialization(String),
+
+  #[error("Dependency cycle between types: {0}")]
+  DependencyCycle(String),
+
+  #[error("Symbol '{name}' defined at {first} is redefined at {second}")]
+  SymbolCollision {
+    name: String,
+    first: String,
+    second: String,
+  },
+
   #[error("Regex error: {0}")]
   Regex(#[from] regex::Error),
 
@@ -0,0 +1,379 @@
+use std::fmt;
+
+use crate::error::Result;
+use crate::literal::c_string_literal;
+use crate::numeric::{int_literal, CType, NumberFormat};
+
+/// A unary prefix operator
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnaryOp {
+  Neg,
+  Plus,
+  Not,
+  BitNot,
+  Deref,
+  AddrOf,
+}
+
+impl UnaryOp {
+  /// Get the C spelling of the operator
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      UnaryOp::Neg => "-",
+      UnaryOp::Plus => "+",
+      UnaryOp::Not => "!",
+      UnaryOp::BitNot => "~",
+      UnaryOp::Deref => "*",
+      UnaryOp::AddrOf => "&",
+    }
+  }
+}
+
+/// A binary operator
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryOp {
+  Mul,
+  Div,
+  Mod,
+  Add,
+  Sub,
+  Shl,
+  Shr,
+  Lt,
+  Le,
+  Gt,
+  Ge,
+  Eq,
+  Ne,
+  BitAnd,
+  BitXor,
+  BitOr,
+  And,
+  Or,
+}
+
+impl BinaryOp {
+  /// Get the C spelling of the operator
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      BinaryOp::Mul => "*",
+      BinaryOp::Div => "/",
+      BinaryOp::Mod => "%",
+      BinaryOp::Add => "+",
+      BinaryOp::Sub => "-",
+      BinaryOp::Shl => "<<",
+      BinaryOp::Shr => ">>",
+      BinaryOp::Lt => "<",
+      BinaryOp::Le => "<=",
+      BinaryOp::Gt => ">",
+      BinaryOp::Ge => ">=",
+      BinaryOp::Eq => "==",
+      BinaryOp::Ne => "!=",
+      BinaryOp::BitAnd => "&",
+      BinaryOp::BitXor => "^",
+      BinaryOp::BitOr => "|",
+      BinaryOp::And => "&&",
+      BinaryOp::Or => "||",
+    }
+  }
+
+  /// Get the binding strength of the operator (higher binds tighter)
+  fn precedence(&self) -> u8 {
+    match self {
+      BinaryOp::Mul | BinaryOp::Div | BinaryOp::Mod => 13,
+      BinaryOp::Add | BinaryOp::Sub => 12,
+      BinaryOp::Shl | BinaryOp::Shr => 11,
+      BinaryOp::Lt | BinaryOp::Le | BinaryOp::Gt | BinaryOp::Ge => 10,
+      BinaryOp::Eq | BinaryOp::Ne => 9,
+      BinaryOp::BitAnd => 8,
+      BinaryOp::BitXor => 7,
+      BinaryOp::BitOr => 6,
+      BinaryOp::And => 5,
+      BinaryOp::Or => 4,
+    }
+  }
+
+  /// Whether `a op (b op c)` equals `(a op b) op c` for any operand types
+  ///
+  /// `+` and `*` are left out: regrouping them can change overflow and
+  /// floating-point rounding.
+  fn is_associative(&self) -> bool {
+    matches!(
+      self,
+      BinaryOp::BitAnd | BinaryOp::BitXor | BinaryOp::BitOr | BinaryOp::And | BinaryOp::Or
+    )
+  }
+
+  fn is_arithmetic(&self) -> bool {
+    matches!(
+      self,
+      BinaryOp::Mul | BinaryOp::Div | BinaryOp::Mod | BinaryOp::Add | BinaryOp::Sub
+    )
+  }
+
+  fn is_comparison(&self) -> bool {
+    matches!(
+      self,
+      BinaryOp::Lt | BinaryOp::Le | BinaryOp::Gt | BinaryOp::Ge | BinaryOp::Eq | BinaryOp::Ne
+    )
+  }
+
+  fn is_bitwise(&self) -> bool {
+    matches!(self, BinaryOp::BitAnd | BinaryOp::BitXor | BinaryOp::BitOr)
+  }
+
+  /// Whether a `child` operand is parenthesized for readability
+  ///
+  /// These are the mixes compilers warn about (`-Wparentheses`): arithmetic
+  /// in a shift (`a << (b + 1)`), `&&` in `||`, and arithmetic, comparisons
+  /// or another bitwise operator in `&`, `^` or `|`.
+  fn clarifies_operand(&self, child: BinaryOp) -> bool {
+    match self {
+      BinaryOp::Shl | BinaryOp::Shr => child.is_arithmetic(),
+      BinaryOp::Or => child == BinaryOp::And,
+      BinaryOp::BitAnd | BinaryOp::BitXor | BinaryOp::BitOr => {
+        child != *self && (child.is_arithmetic() || child.is_comparison() || child.is_bitwise())
+      }
+      _ => false,
+    }
+  }
+
+  /// Check whether a `child` operation needs parentheses as an operand of this one
+  pub(crate) fn operand_needs_parens(&self, child: BinaryOp, is_right: bool) -> bool {
+    child.precedence() < self.precedence()
+      || (child.precedence() == self.precedence()
+        && is_right
+        && !(child == *self && self.is_associative()))
+      || self.clarifies_operand(child)
+  }
+}
+
+const PREC_TERNARY: u8 = 3;
+const PREC_UNARY: u8 = 14;
+const PREC_POSTFIX: u8 = 15;
+const PREC_PRIMARY: u8 = 16;
+
+/// A C expression rendered with minimal but correct parentheses
+///
+/// Operator mixes that compilers warn about, such as `a & b | c`, are also
+/// parenthesized.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expr {
+  /// An integer constant
+  Int(i128),
+  /// A literal written verbatim, e.g. `1.5f` or `0x10u`
+  Literal(String),
+  /// An identifier
+  Ident(String),
+  /// A function-like macro parameter, always written as `(name)`
+  Param(String),
+  /// A prefix operation
+  Unary(UnaryOp, Box<Expr>),
+  /// A binary operation
+  Binary(Box<Expr>, BinaryOp, Box<Expr>),
+  /// `(type)operand`
+  Cast(String, Box<Expr>),
+  /// `sizeof(type)`
+  SizeOf(String),
+  /// `callee(args...)`
+  Call(Box<Expr>, Vec<Expr>),
+  /// `base[index]`
+  Index(Box<Expr>, Box<Expr>),
+  /// `base.member`, or `base->member` if `arrow` is set
+  Member {
+    base: Box<Expr>,
+    member: String,
+    arrow: bool,
+  },
+  /// `cond ? then : otherwise`
+  Ternary(Box<Expr>, Box<Expr>, Box<Expr>),
+}
+
+impl Expr {
+  /// Create an integer constant
+  pub fn int(value: i128) -> Self {
+    Expr::Int(value)
+  }
+
+  /// Create an integer literal of a C type, with suffix (`10u`, `(-2147483647 - 1)`)
+  pub fn typed_int(value: i128, ty: CType) -> Result<Self> {
+    int_literal(value, ty, NumberFormat::Decimal).map(Expr::Literal)
+  }
+
+  /// Create a verbatim literal
+  pub fn literal(text: &str) -> Self {
+    Expr::Literal(text.to_string())
+  }
+
+  /// Create an escaped string literal
+  pub fn string(value: &str) -> Self {
+    Expr::Literal(c_string_literal(value))
+  }
+
+  /// Create an identifier
+  pub fn ident(name: &str) -> Self {
+    Expr::Ident(name.to_string())
+  }
+
+  /// Create a function-like macro parameter reference
+  pub fn param(name: &str) -> Self {
+    Expr::Param(name.to_string())
+  }
+
+  /// Create a `sizeof(type)` expression
+  pub fn size_of(type_name: &str) -> Self {
+    Expr::SizeOf(type_name.to_string())
+  }
+
+  /// Create a prefix operation
+  pub fn unary(op: UnaryOp, operand: Expr) -> Self {
+    Expr::Unary(op, Box::new(operand))
+  }
+
+  /// Create a binary operation
+  pub fn binary(lhs: Expr, op: BinaryOp, rhs: Expr) -> Self {
+    Expr::Binary(Box::new(lhs), op, Box::new(rhs))
+  }
+
+  /// Create a function call
+  pub fn call(callee: &str, args: Vec<Expr>) -> Self {
+    Expr::Call(Box::new(Expr::ident(callee)), args)
+  }
+
+  /// Create a ternary conditional
+  pub fn ternary(cond: Expr, then: Expr, otherwise: Expr) -> Self {
+    Expr::Ternary(Box::new(cond), Box::new(then), Box::new(otherwise))
+  }
+
+  /// Combine with another expression using `op`
+  pub fn op(self, op: BinaryOp, rhs: Expr) -> Self {
+    Self::binary(self, op, rhs)
+  }
+
+  /// Cast to `type_name`
+  pub fn cast(self, type_name: &str) -> Self {
+    Expr::Cast(type_name.to_string(), Box::new(self))
+  }
+
+  /// Index with `index`
+  pub fn index(self, index: Expr) -> Self {
+    Expr::Index(Box::new(self), Box::new(index))
+  }
+
+  /// Access `member` of a struct value
+  pub fn member(self, member: &str) -> Self {
+    Expr::Member {
+      base: Box::new(self),
+      member: member.to_string(),
+      arrow: false,
+    }
+  }
+
+  /// Access `member` through a pointer
+  pub fn arrow(self, member: &str) -> Self {
+    Expr::Member {
+      base: Box::new(self),
+      member: member.to_string(),
+      arrow: true,
+    }
+  }
+
+  /// Render as a macro body: parenthesized unless it is a single token
+  pub fn macro_body(&self) -> String {
+    if self.precedence() >= PREC_POSTFIX {
+      self.to_string()
+    } else {
+      format!("({})", self)
+    }
+  }
+
+  /// Get the binding strength of the outermost operation
+  fn precedence(&self) -> u8 {
+    match self {
+      Expr::Int(value) if *value < 0 => PREC_UNARY,
+      Expr::Literal(text) if text.starts_with(['-', '+']) => PREC_UNARY,
+      Expr::Int(_) | Expr::Literal(_) | Expr::Ident(_) | Expr::Param(_) => PREC_PRIMARY,
+      Expr::SizeOf(_) | Expr::Unary(..) | Expr::Cast(..) => PREC_UNARY,
+      Expr::Call(..) | Expr::Index(..) | Expr::Member { .. } => PREC_POSTFIX,
+      Expr::Binary(_, op, _) => op.precedence(),
+      Expr::Ternary(..) => PREC_TERNARY,
+    }
+  }
+
+  fn fmt_wrapped(&self, f: &mut fmt::Formatter<'_>, needs_parens: bool) -> fmt::Result {
+    if needs_parens {
+      write!(f, "({})", self)
+    } else {
+      write!(f, "{}", self)
+    }
+  }
+
+  fn fmt_operand(
+    &self,
+    f: &mut fmt::Formatter<'_>,
+    parent: BinaryOp,
+    is_right: bool,
+  ) -> fmt::Result {
+    let needs_parens = match self {
+      Expr::Binary(_, op, _) => parent.operand_needs_parens(*op, is_right),
+      _ => self.precedence() < parent.precedence(),
+    };
+    self.fmt_wrapped(f, needs_parens)
+  }
+}
+
+impl fmt::Display for Expr {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Expr::Int(value) => write!(f, "{}", value),
+      Expr::Literal(text) | Expr::Ident(text) => write!(f, "{}", text),
+      Expr::Param(name) => write!(f, "({})", name),
+      Expr::SizeOf(type_name) => write!(f, "sizeof({})", type_name),
+      Expr::Unary(op, operand) => {
+        write!(f, "{}", op.as_str())?;
+        // `--x`, `++x` and `&&x` would lex as other tokens
+        let merges = matches!(op, UnaryOp::Neg | UnaryOp::Plus | UnaryOp::AddrOf)
+          && operand.precedence() == PREC_UNARY
+          && operand.to_string().starts_with(op.as_str());
+        operand.fmt_wrapped(f, merges || operand.precedence() < PREC_UNARY)
+      }
+      Expr::Cast(type_name, operand) => {
+        write!(f, "({})", type_name)?;
+        operand.fmt_wrapped(f, operand.precedence() < PREC_UNARY)
+      }
+      Expr::Call(callee, args) => {
+        callee.fmt_wrapped(f, callee.precedence() < PREC_POSTFIX)?;
+        write!(f, "(")?;
+        for (i, arg) in args.iter().enumerate() {
+          if i > 0 {
+            write!(f, ", ")?;
+          }
+          write!(f, "{}", arg)?;
+        }
+        write!(f, ")")
+      }
+      Expr::Index(base, index) => {
+        base.fmt_wrapped(f, base.precedence() < PREC_POSTFIX)?;
+        write!(f, "[{}]", index)
+      }
+      Expr::Member {
+        base,
+        member,
+        arrow,
+      } => {
+        base.fmt_wrapped(f, base.precedence() < PREC_POSTFIX)?;
+        write!(f, "{}{}", if *arrow { "->" } else { "." }, member)
+      }
+      Expr::Binary(lhs, op, rhs) => {
+        lhs.fmt_operand(f, *op, false)?;
+        write!(f, " {} ", op.as_str())?;
+        rhs.fmt_operand(f, *op, true)
+      }
+      Expr::Ternary(cond, then, otherwise) => {
+        cond.fmt_wrapped(f, cond.precedence() <= PREC_TERNARY)?;
+        write!(f, " ? {} : ", then)?;
+        otherwise.fmt_wrapped(f, otherwise.precedence() < PREC_TERNARY)
+      }
+    }
+  }
+}
@@ -0,0 +1,136 @@
+use crate::error::{CodeGenError, Result};
+use crate::expr::Expr;
+
+/// A function-like preprocessor macro
+#[derive(Debug, Clone)]
+pub struct FunctionMacro {
+  /// The macro name
+  pub name: String,
+  /// The parameter names
+  pub params: Vec<String>,
+  /// Whether the macro takes variadic arguments (`...`)
+  pub variadic: bool,
+  /// The body lines
+  pub body: Vec<String>,
+  /// Whether the body is wrapped in `do { ... } while (0)`
+  pub do_while: bool,
+}
+
+impl FunctionMacro {
+  /// Create a new function-like macro, splitting the body into lines
+  pub fn new(name: &str, params: &[&str], body: &str) -> Self {
+    Self {
+      name: name.to_string(),
+      params: params.iter().map(|p| p.to_string()).collect(),
+      variadic: false,
+      body: body.lines().map(|l| l.trim_end().to_string()).collect(),
+      do_while: false,
+    }
+  }
+
+  /// Create a function-like macro whose body is an expression
+  ///
+  /// Refer to parameters with `Expr::param` so that arguments are
+  /// parenthesized at every use.
+  pub fn from_expr(name: &str, params: &[&str], body: &Expr) -> Self {
+    Self::new(name, params, &body.macro_body())
+  }
+
+  /// Render the macro head (`NAME(a, b, ...)`)
+  pub fn head(&self) -> String {
+    let mut params = self.params.clone();
+    if self.variadic {
+      params.push("...".to_string());
+    }
+    format!("{}({})", self.name, params.join(", "))
+  }
+
+  /// Render the directive as lines with aligned continuation backslashes
+  pub fn render_lines(&self, indent: &str) -> Vec<String> {
+    let head = format!("#define {}", self.head());
+
+    if !self.do_while && self.body.len() <= 1 {
+      return match self.body.first() {
+        Some(line) if !line.is_empty() => vec![format!("{} {}", head, line)],
+        _ => vec![head],
+      };
+    }
+
+    let mut lines = vec![head];
+    if self.do_while {
+      lines.push(format!("{}do {{", indent));
+      for line in &self.body {
+        lines.push(Self::indented(line, &format!("{}{}", indent, indent)));
+      }
+      lines.push(format!("{}}} while (0)", indent));
+    } else {
+      for line in &self.body {
+        lines.push(Self::indented(line, indent));
+      }
+    }
+
+    // Widths are counted in characters so that non-ASCII bodies stay aligned
+    let width = lines.iter().map(|l| l.chars().count()).max().unwrap_or(0);
+    let last = lines.len() - 1;
+    lines
+      .iter()
+      .enumerate()
+      .map(|(i, line)| {
+        if i == last {
+          line.clone()
+        } else {
+          let padding = " ".repeat(width - line.chars().count());
+          format!("{}{} \\", line, padding)
+        }
+      })
+      .collect()
+  }
+
+  /// Check that no continued body line contains a `//` comment
+  ///
+  /// A line comment would swallow the continuation backslash and end the
+  /// macro early.
+  pub fn validate(&self) -> Result<()> {
+    let continued = if self.do_while {
+      self.body.len()
+    } else if self.body.len() > 1 {
+      self.body.len() - 1
+    } else {
+      0
+    };
+    match self.body[..continued].iter().find(|l| has_line_comment(l)) {
+      Some(line) => Err(CodeGenError::InvalidDefinition(format!(
+        "macro '{}' has a // comment in a continued line: {}",
+        self.name, line
+      ))),
+      None => Ok(()),
+    }
+  }
+
+  fn indented(line: &str, indent: &str) -> String {
+    if line.is_empty() {
+      String::new()
+    } else {
+      format!("{}{}", indent, line)
+    }
+  }
+}
+
+/// Check whether a line contains `//` outside string and character literals
+fn has_line_comment(line: &str) -> bool {
+  let mut quote = None;
+  let mut chars = line.chars().peekable();
+  while let Some(c) = chars.next() {
+    match quote {
+      Some(q) if c == q => quote = None,
+      Some(_) if c == '\\' => {
+        chars.next();
+      }
+      Some(_) => {}
+      None if c == '"' || c == '\'' => quote = Some(c),
+      None if c == '/' && chars.peek() == Some(&'/') => return true,
+      None => {}
+    }
+  }
+  false
+}
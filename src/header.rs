@@ -0,0 +1,161 @@
+use anyhow::Context as AnyhowContext;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use crate::code_writer::CodeWriter;
+use crate::error::{CodeGenError, Result};
+use crate::naming::identifier_from_name;
+use crate::user_section::UserSectionManager;
+use crate::utils::get_file_name;
+
+/// How a header protects itself against multiple inclusion
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IncludeGuard {
+  /// `#ifndef NAME` / `#define NAME` / `#endif // NAME`
+  Define(String),
+  /// `#pragma once`
+  PragmaOnce,
+}
+
+/// Boilerplate for a header consumable from both C and C++
+///
+/// The scaffold writes the include guard, an `extern "C"` block around the
+/// generated body and the standard user sections `Header`, `Includes` and
+/// `Declarations`. Includes registered by the body are deduplicated, sorted
+/// and written before the `Includes` section.
+/// Fallback assertion typedefs are named after the guard, so headers
+/// included together do not redefine each other's typedefs.
+#[derive(Debug, Clone)]
+pub struct HeaderScaffold {
+  /// The include guard style
+  guard: IncludeGuard,
+  /// Whether the body is wrapped in an `extern "C"` block
+  extern_c: bool,
+}
+
+impl HeaderScaffold {
+  /// Name of the user section written before the include guard
+  pub const HEADER_SECTION: &'static str = "Header";
+  /// Name of the user section written after the generated includes
+  pub const INCLUDES_SECTION: &'static str = "Includes";
+  /// Name of the user section written after the generated body
+  pub const DECLARATIONS_SECTION: &'static str = "Declarations";
+
+  /// Create a new header scaffold with an include guard
+  pub fn new(guard: IncludeGuard) -> Self {
+    Self {
+      guard,
+      extern_c: true,
+    }
+  }
+
+  /// Create a new header scaffold whose guard is derived from the file name
+  ///
+  /// Fails if the path has no file name usable as an identifier.
+  pub fn for_file(path: &Path) -> Result<Self> {
+    let file_name = get_file_name(path).unwrap_or_default();
+    let guard = identifier_from_name(&file_name).map_err(|_| {
+      CodeGenError::InvalidDefinition(format!(
+        "cannot derive an include guard from '{}'",
+        path.display()
+      ))
+    })?;
+    Ok(Self::new(IncludeGuard::Define(guard.to_uppercase())))
+  }
+
+  /// Set whether the body is wrapped in an `extern "C"` block
+  pub fn set_extern_c(&mut self, extern_c: bool) {
+    self.extern_c = extern_c;
+  }
+
+  /// Get the include guard style
+  pub fn guard(&self) -> &IncludeGuard {
+    &self.guard
+  }
+
+  /// Define the standard user sections that are not defined yet
+  pub fn define_sections(&self, user_sections: &mut UserSectionManager) {
+    if !user_sections.has_section(Self::HEADER_SECTION) {
+      user_sections.define_section_with_description(Self::HEADER_SECTION, "File header comment");
+    }
+    if !user_sections.has_section(Self::INCLUDES_SECTION) {
+      user_sections.define_section_with_description(Self::INCLUDES_SECTION, "Additional includes");
+    }
+    if !user_sections.has_section(Self::DECLARATIONS_SECTION) {
+      user_sections
+        .define_section_with_description(Self::DECLARATIONS_SECTION, "User declarations");
+    }
+  }
+
+  /// Write the header, calling `body` for the generated declarations
+  pub fn write<W, F>(
+    &self,
+    writer: &mut CodeWriter<W>,
+    user_sections: &UserSectionManager,
+    body: F,
+  ) -> Result<()>
+  where
+    W: Write,
+    F: FnOnce(&mut CodeWriter<W>) -> Result<()>,
+  {
+    user_sections.write_section(writer, Self::HEADER_SECTION)?;
+    match self.guard {
+      IncludeGuard::Define(ref name) => {
+        writer.set_assert_scope(name);
+        writer.write_ifndef(name)?;
+        writer.write_define(name, None)?;
+      }
+      IncludeGuard::PragmaOnce => writer.writeln("#pragma once")?,
+    }
+    writer.newline()?;
+
+    // Includes registered by the body are collected and written here
+    writer.write_with_includes(|writer| {
+      user_sections.write_section(writer, Self::INCLUDES_SECTION)?;
+      writer.newline()?;
+
+      if self.extern_c {
+        writer.write_extern_c(|w| {
+          body(w)?;
+          w.newline()?;
+          user_sections.write_section(w, Self::DECLARATIONS_SECTION)
+        })?;
+      } else {
+        body(writer)?;
+        writer.newline()?;
+        user_sections.write_section(writer, Self::DECLARATIONS_SECTION)?;
+      }
+
+      if let IncludeGuard::Define(ref name) = self.guard {
+        writer.newline()?;
+        writer.write_endif(Some(name))?;
+      }
+      Ok(())
+    })
+  }
+
+  /// Generate a header file, preserving user sections of an existing file
+  pub fn generate<F>(
+    &self,
+    path: &Path,
+    user_sections: &mut UserSectionManager,
+    body: F,
+  ) -> Result<()>
+  where
+    F: FnOnce(&mut CodeWriter<BufWriter<File>>) -> Result<()>,
+  {
+    self.define_sections(user_sections);
+    user_sections.capture_from_file(path)?;
+
+    let file = File::create(path)
+      .with_context(|| format!("Failed to create output file: {}", path.display()))?;
+    let mut writer = CodeWriter::new(BufWriter::new(file));
+    // A `#pragma once` header has no guard to name its assertions after
+    if let Ok(name) = identifier_from_name(&get_file_name(path).unwrap_or_default()) {
+      writer.set_assert_scope(&name.to_uppercase());
+    }
+    self.write(&mut writer, user_sections, body)?;
+    writer.flush()
+  }
+}
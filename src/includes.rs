@@ -0,0 +1,111 @@
+use std::collections::BTreeSet;
+use std::io::Write;
+
+use crate::code_writer::CodeWriter;
+use crate::error::Result;
+
+/// A deduplicated, sorted set of `#include` directives
+///
+/// System headers (`<...>`) are written first, followed by local headers
+/// (`"..."`), each group sorted by name and separated by a blank line.
+#[derive(Debug, Clone, Default)]
+pub struct IncludeSet {
+  /// System headers
+  system: BTreeSet<String>,
+  /// Local headers
+  local: BTreeSet<String>,
+}
+
+impl IncludeSet {
+  /// Create an empty include set
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Register a header
+  pub fn add(&mut self, header: &str, is_system: bool) {
+    if is_system {
+      self.system.insert(header.to_string());
+    } else {
+      self.local.insert(header.to_string());
+    }
+  }
+
+  /// Register a system header
+  pub fn add_system(&mut self, header: &str) {
+    self.add(header, true);
+  }
+
+  /// Register a local header
+  pub fn add_local(&mut self, header: &str) {
+    self.add(header, false);
+  }
+
+  /// Register all headers of another set
+  pub fn merge(&mut self, other: &IncludeSet) {
+    self.system.extend(other.system.iter().cloned());
+    self.local.extend(other.local.iter().cloned());
+  }
+
+  /// Check whether a header is registered
+  pub fn contains(&self, header: &str, is_system: bool) -> bool {
+    if is_system {
+      self.system.contains(header)
+    } else {
+      self.local.contains(header)
+    }
+  }
+
+  /// Check whether the set is empty
+  pub fn is_empty(&self) -> bool {
+    self.system.is_empty() && self.local.is_empty()
+  }
+
+  /// Get the number of registered headers
+  pub fn len(&self) -> usize {
+    self.system.len() + self.local.len()
+  }
+
+  /// Get the system headers in sorted order
+  pub fn system_headers(&self) -> impl Iterator<Item = &str> {
+    self.system.iter().map(|s| s.as_str())
+  }
+
+  /// Get the local headers in sorted order
+  pub fn local_headers(&self) -> impl Iterator<Item = &str> {
+    self.local.iter().map(|s| s.as_str())
+  }
+
+  /// Render the directives, one group per block
+  pub fn render(&self) -> String {
+    let mut groups = Vec::new();
+    if !self.system.is_empty() {
+      groups.push(
+        self
+          .system
+          .iter()
+          .map(|h| format!("#include <{}>\n", h))
+          .collect::<String>(),
+      );
+    }
+    if !self.local.is_empty() {
+      groups.push(
+        self
+          .local
+          .iter()
+          .map(|h| format!("#include \"{}\"\n", h))
+          .collect::<String>(),
+      );
+    }
+    groups.join("\n")
+  }
+
+  /// Write the directives to a CodeWriter
+  pub fn write<W: Write>(&self, writer: &mut CodeWriter<W>) -> Result<()> {
+    let rendered = self.render();
+    if !rendered.is_empty() {
+      writer.write(&rendered)?;
+    }
+    Ok(())
+  }
+}
@@ -0,0 +1,494 @@
+use serde::ser::{self, Impossible, Serialize};
+use std::fmt::Display;
+use std::io::Write;
+
+use crate::code_writer::CodeWriter;
+use crate::data_table::{format_table_rows, TableOptions, TableValue};
+use crate::error::{CodeGenError, Result};
+use crate::literal::{c_char_literal, c_string_literal};
+use crate::naming::{Case, NamingConvention};
+use crate::numeric::{float_literal, int_literal, CType, NumberFormat};
+use crate::standard::LanguageStandard;
+
+/// Options for converting Rust values into C initializers
+#[derive(Debug, Clone)]
+pub struct InitializerOptions {
+  /// Name struct fields (`.f = v`) instead of relying on their order
+  pub designated: bool,
+  /// How integer values are rendered
+  pub format: NumberFormat,
+  /// How enum variants are mapped to C enumerators
+  pub variant_naming: NamingConvention,
+  /// Put the enum name in front of the variant (`Mode::Fast` -> `MODE_FAST`)
+  pub prefix_variants: bool,
+  /// Number of values per line in lists of scalars too long for one line
+  pub per_line: usize,
+}
+
+impl Default for InitializerOptions {
+  fn default() -> Self {
+    Self {
+      designated: true,
+      format: NumberFormat::Decimal,
+      variant_naming: NamingConvention::new(Case::ScreamingSnake, LanguageStandard::C99),
+      prefix_variants: true,
+      per_line: 8,
+    }
+  }
+}
+
+/// A C initializer produced from a serialized value
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Initializer {
+  /// A single literal or identifier
+  Scalar(String),
+  /// A boolean, spelled `true`/`false` or `1`/`0` depending on the standard
+  Bool(bool),
+  /// Array or tuple elements, in order
+  List(Vec<Initializer>),
+  /// Struct fields as (name, value) pairs, in declaration order
+  Struct(Vec<(String, Initializer)>),
+}
+
+/// How an initializer is laid out by a writer
+struct Layout {
+  /// Whether struct fields are designated
+  designated: bool,
+  /// Whether `true` and `false` exist, rather than `_Bool` or no boolean type
+  bool_keywords: bool,
+  /// Number of values per line in long lists of scalars
+  per_line: usize,
+}
+
+/// A scalar already rendered to text, so that lists reuse the data table layout
+struct ScalarText(String);
+
+impl TableValue for ScalarText {
+  fn format_value(&self, _options: &TableOptions) -> Result<String> {
+    Ok(self.0.clone())
+  }
+
+  fn is_numeric(&self) -> bool {
+    !self.0.starts_with(['"', '\''])
+  }
+}
+
+impl Initializer {
+  /// Convert a value with its `Serialize` implementation
+  pub fn from_value<T: Serialize + ?Sized>(
+    value: &T,
+    options: &InitializerOptions,
+  ) -> Result<Self> {
+    value.serialize(&InitializerSerializer { options })
+  }
+
+  /// Get the text of a scalar, or `None` for lists and structs
+  fn scalar_text(&self, layout: &Layout) -> Option<String> {
+    match self {
+      Initializer::Scalar(text) => Some(text.clone()),
+      Initializer::Bool(value) => Some(
+        match (layout.bool_keywords, value) {
+          (true, true) => "true",
+          (true, false) => "false",
+          (false, true) => "1",
+          (false, false) => "0",
+        }
+        .to_string(),
+      ),
+      Initializer::List(_) | Initializer::Struct(_) => None,
+    }
+  }
+
+  /// Get the texts of a list whose elements are all scalars
+  fn scalar_list(&self, layout: &Layout) -> Option<Vec<ScalarText>> {
+    match self {
+      Initializer::List(items) => items
+        .iter()
+        .map(|item| item.scalar_text(layout).map(ScalarText))
+        .collect(),
+      _ => None,
+    }
+  }
+
+  /// Render an initializer that fits on one line, if this one does
+  ///
+  /// Scalars and short lists of scalars are written inline; structs, nested
+  /// lists and long lists of scalars span several lines.
+  fn inline(&self, layout: &Layout) -> Option<String> {
+    match self {
+      Initializer::List(items) if items.is_empty() => Some("{ 0 }".to_string()),
+      Initializer::Struct(fields) if fields.is_empty() => Some("{ 0 }".to_string()),
+      Initializer::List(items) if items.len() <= layout.per_line.max(1) => {
+        self.scalar_list(layout).map(|texts| {
+          let texts: Vec<String> = texts.into_iter().map(|text| text.0).collect();
+          format!("{{ {} }}", texts.join(", "))
+        })
+      }
+      _ => self.scalar_text(layout),
+    }
+  }
+
+  fn write_value<W: Write>(
+    &self,
+    writer: &mut CodeWriter<W>,
+    head: &str,
+    terminator: &str,
+    layout: &Layout,
+  ) -> Result<()> {
+    if let Some(text) = self.inline(layout) {
+      return writer.writeln(&format!("{}{}{}", head, text, terminator));
+    }
+
+    writer.writeln(&format!("{}{{", head))?;
+    writer.indent();
+    if let Some(texts) = self.scalar_list(layout) {
+      let options = TableOptions {
+        per_line: layout.per_line,
+        ..Default::default()
+      };
+      for row in format_table_rows(&texts, &options)? {
+        writer.writeln(&row)?;
+      }
+    } else {
+      let entries: Vec<(String, &Initializer)> = match self {
+        Initializer::Struct(fields) => fields
+          .iter()
+          .map(|(name, value)| {
+            let head = if layout.designated {
+              format!(".{} = ", name)
+            } else {
+              String::new()
+            };
+            (head, value)
+          })
+          .collect(),
+        Initializer::List(items) => items.iter().map(|item| (String::new(), item)).collect(),
+        Initializer::Scalar(_) | Initializer::Bool(_) => Vec::new(),
+      };
+      for (head, value) in entries {
+        value.write_value(writer, &head, ",", layout)?;
+      }
+    }
+    writer.dedent();
+    writer.writeln(&format!("}}{}", terminator))
+  }
+
+  /// Check whether a struct appears anywhere in the initializer
+  fn has_struct(&self) -> bool {
+    match self {
+      Initializer::Struct(_) => true,
+      Initializer::List(items) => items.iter().any(|item| item.has_struct()),
+      Initializer::Scalar(_) | Initializer::Bool(_) => false,
+    }
+  }
+
+  /// Write `declaration = initializer;`, e.g. for `static const config_t cfg`
+  ///
+  /// Booleans are written as `1`/`0` unless the selected standard has `true`
+  /// and `false`.
+  pub fn write_definition<W: Write>(
+    &self,
+    writer: &mut CodeWriter<W>,
+    declaration: &str,
+    options: &InitializerOptions,
+  ) -> Result<()> {
+    // Only struct fields are designated
+    if options.designated && self.has_struct() {
+      writer.check_standard("designated initializers", |s| {
+        s.has_designated_initializers()
+      })?;
+    }
+    let layout = Layout {
+      designated: options.designated,
+      bool_keywords: writer.bool_type().is_ok_and(|name| name == "bool"),
+      per_line: options.per_line,
+    };
+    self.write_value(writer, &format!("{} = ", declaration), ";", &layout)
+  }
+}
+
+impl ser::Error for CodeGenError {
+  fn custom<T: Display>(msg: T) -> Self {
+    CodeGenError::Serialization(msg.to_string())
+  }
+}
+
+fn unsupported(what: &str) -> CodeGenError {
+  CodeGenError::Serialization(format!("{} cannot be written as a C initializer", what))
+}
+
+/// A serde `Serializer` producing an `Initializer`
+pub struct InitializerSerializer<'a> {
+  /// Conversion options
+  pub options: &'a InitializerOptions,
+}
+
+impl InitializerSerializer<'_> {
+  fn int(&self, value: i128) -> Result<Initializer> {
+    // Pick the narrowest type whose literal keeps the value's meaning
+    let ty = [CType::Int32, CType::UInt32, CType::Int64, CType::UInt64]
+      .into_iter()
+      .find(|ty| {
+        let (min, max) = ty.int_range().unwrap_or((0, 0));
+        value >= min && value <= max
+      })
+      .ok_or_else(|| CodeGenError::ValueOutOfRange {
+        value: value.to_string(),
+        type_name: CType::UInt64.name().to_string(),
+      })?;
+    int_literal(value, ty, self.options.format).map(Initializer::Scalar)
+  }
+}
+
+/// Collects the elements of a sequence or tuple
+pub struct ListBuilder<'a> {
+  serializer: &'a InitializerSerializer<'a>,
+  items: Vec<Initializer>,
+}
+
+/// Collects the fields of a struct
+pub struct StructBuilder<'a> {
+  serializer: &'a InitializerSerializer<'a>,
+  fields: Vec<(String, Initializer)>,
+}
+
+impl<'a> ser::Serializer for &'a InitializerSerializer<'a> {
+  type Ok = Initializer;
+  type Error = CodeGenError;
+  type SerializeSeq = ListBuilder<'a>;
+  type SerializeTuple = ListBuilder<'a>;
+  type SerializeTupleStruct = ListBuilder<'a>;
+  type SerializeTupleVariant = Impossible<Initializer, CodeGenError>;
+  type SerializeMap = Impossible<Initializer, CodeGenError>;
+  type SerializeStruct = StructBuilder<'a>;
+  type SerializeStructVariant = Impossible<Initializer, CodeGenError>;
+
+  fn serialize_bool(self, v: bool) -> Result<Initializer> {
+    Ok(Initializer::Bool(v))
+  }
+
+  fn serialize_i8(self, v: i8) -> Result<Initializer> {
+    self.int(v as i128)
+  }
+
+  fn serialize_i16(self, v: i16) -> Result<Initializer> {
+    self.int(v as i128)
+  }
+
+  fn serialize_i32(self, v: i32) -> Result<Initializer> {
+    self.int(v as i128)
+  }
+
+  fn serialize_i64(self, v: i64) -> Result<Initializer> {
+    self.int(v as i128)
+  }
+
+  fn serialize_i128(self, v: i128) -> Result<Initializer> {
+    self.int(v)
+  }
+
+  fn serialize_u8(self, v: u8) -> Result<Initializer> {
+    self.int(v as i128)
+  }
+
+  fn serialize_u16(self, v: u16) -> Result<Initializer> {
+    self.int(v as i128)
+  }
+
+  fn serialize_u32(self, v: u32) -> Result<Initializer> {
+    self.int(v as i128)
+  }
+
+  fn serialize_u64(self, v: u64) -> Result<Initializer> {
+    self.int(v as i128)
+  }
+
+  fn serialize_u128(self, v: u128) -> Result<Initializer> {
+    self.int(i128::try_from(v).map_err(|_| unsupported("a u128 above i128::MAX"))?)
+  }
+
+  fn serialize_f32(self, v: f32) -> Result<Initializer> {
+    float_literal(v as f64, CType::Float).map(Initializer::Scalar)
+  }
+
+  fn serialize_f64(self, v: f64) -> Result<Initializer> {
+    float_literal(v, CType::Double).map(Initializer::Scalar)
+  }
+
+  fn serialize_char(self, v: char) -> Result<Initializer> {
+    c_char_literal(v).map(Initializer::Scalar)
+  }
+
+  fn serialize_str(self, v: &str) -> Result<Initializer> {
+    Ok(Initializer::Scalar(c_string_literal(v)))
+  }
+
+  fn serialize_bytes(self, v: &[u8]) -> Result<Initializer> {
+    let items = v
+      .iter()
+      .map(|b| self.int(*b as i128))
+      .collect::<Result<Vec<_>>>()?;
+    Ok(Initializer::List(items))
+  }
+
+  fn serialize_none(self) -> Result<Initializer> {
+    Err(unsupported("None"))
+  }
+
+  fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<Initializer> {
+    value.serialize(self)
+  }
+
+  fn serialize_unit(self) -> Result<Initializer> {
+    Err(unsupported("()"))
+  }
+
+  fn serialize_unit_struct(self, name: &'static str) -> Result<Initializer> {
+    Err(unsupported(&format!("unit struct {}", name)))
+  }
+
+  fn serialize_unit_variant(
+    self,
+    name: &'static str,
+    _variant_index: u32,
+    variant: &'static str,
+  ) -> Result<Initializer> {
+    let enumerator = if self.options.prefix_variants {
+      format!("{} {}", name, variant)
+    } else {
+      variant.to_string()
+    };
+    Ok(Initializer::Scalar(
+      self.options.variant_naming.apply(&enumerator),
+    ))
+  }
+
+  fn serialize_newtype_struct<T: Serialize + ?Sized>(
+    self,
+    _name: &'static str,
+    value: &T,
+  ) -> Result<Initializer> {
+    value.serialize(self)
+  }
+
+  fn serialize_newtype_variant<T: Serialize + ?Sized>(
+    self,
+    name: &'static str,
+    _variant_index: u32,
+    variant: &'static str,
+    _value: &T,
+  ) -> Result<Initializer> {
+    Err(unsupported(&format!(
+      "enum variant {}::{} with data",
+      name, variant
+    )))
+  }
+
+  fn serialize_seq(self, len: Option<usize>) -> Result<ListBuilder<'a>> {
+    Ok(ListBuilder {
+      serializer: self,
+      items: Vec::with_capacity(len.unwrap_or(0)),
+    })
+  }
+
+  fn serialize_tuple(self, len: usize) -> Result<ListBuilder<'a>> {
+    self.serialize_seq(Some(len))
+  }
+
+  fn serialize_tuple_struct(self, _name: &'static str, len: usize) -> Result<ListBuilder<'a>> {
+    self.serialize_seq(Some(len))
+  }
+
+  fn serialize_tuple_variant(
+    self,
+    name: &'static str,
+    _variant_index: u32,
+    variant: &'static str,
+    _len: usize,
+  ) -> Result<Self::SerializeTupleVariant> {
+    Err(unsupported(&format!(
+      "enum variant {}::{} with data",
+      name, variant
+    )))
+  }
+
+  fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap> {
+    Err(unsupported("a map"))
+  }
+
+  fn serialize_struct(self, _name: &'static str, len: usize) -> Result<StructBuilder<'a>> {
+    Ok(StructBuilder {
+      serializer: self,
+      fields: Vec::with_capacity(len),
+    })
+  }
+
+  fn serialize_struct_variant(
+    self,
+    name: &'static str,
+    _variant_index: u32,
+    variant: &'static str,
+    _len: usize,
+  ) -> Result<Self::SerializeStructVariant> {
+    Err(unsupported(&format!(
+      "enum variant {}::{} with data",
+      name, variant
+    )))
+  }
+}
+
+impl ser::SerializeSeq for ListBuilder<'_> {
+  type Ok = Initializer;
+  type Error = CodeGenError;
+
+  fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+    self.items.push(value.serialize(self.serializer)?);
+    Ok(())
+  }
+
+  fn end(self) -> Result<Initializer> {
+    Ok(Initializer::List(self.items))
+  }
+}
+
+impl ser::SerializeTuple for ListBuilder<'_> {
+  type Ok = Initializer;
+  type Error = CodeGenError;
+
+  fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+    ser::SerializeSeq::serialize_element(self, value)
+  }
+
+  fn end(self) -> Result<Initializer> {
+    ser::SerializeSeq::end(self)
+  }
+}
+
+impl ser::SerializeTupleStruct for ListBuilder<'_> {
+  type Ok = Initializer;
+  type Error = CodeGenError;
+
+  fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+    ser::SerializeSeq::serialize_element(self, value)
+  }
+
+  fn end(self) -> Result<Initializer> {
+    ser::SerializeSeq::end(self)
+  }
+}
+
+impl ser::SerializeStruct for StructBuilder<'_> {
+  type Ok = Initializer;
+  type Error = CodeGenError;
+
+  fn serialize_field<T: Serialize + ?Sized>(&mut self, key: &'static str, value: &T) -> Result<()> {
+    self
+      .fields
+      .push((key.to_string(), value.serialize(self.serializer)?));
+    Ok(())
+  }
+
+  fn end(self) -> Result<Initializer> {
+    Ok(Initializer::Struct(self.fields))
+  }
+}
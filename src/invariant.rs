@@ -0,0 +1,173 @@
+use crate::literal::c_string_literal;
+use crate::naming::sanitize_identifier;
+use crate::standard::LanguageStandard;
+
+/// How compile-time assertions are spelled
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AssertStyle {
+  /// C11 `_Static_assert(cond, "msg");`
+  StaticAssertC11,
+  /// C++11 `static_assert(cond, "msg");`
+  StaticAssertCpp,
+  /// `typedef char name[(cond) ? 1 : -1];`, which fails to compile when false (C89/C99)
+  NegativeArray,
+}
+
+impl AssertStyle {
+  /// Get the assertion style available in a language standard
+  pub fn for_standard(standard: LanguageStandard) -> Self {
+    match standard {
+      LanguageStandard::C89 | LanguageStandard::C99 => AssertStyle::NegativeArray,
+      LanguageStandard::C11 | LanguageStandard::C17 | LanguageStandard::C23 => {
+        AssertStyle::StaticAssertC11
+      }
+      _ => AssertStyle::StaticAssertCpp,
+    }
+  }
+}
+
+/// A compile-time check of the generated code against the model
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Invariant {
+  /// `sizeof(type_name) == size`
+  SizeOf { type_name: String, size: usize },
+  /// `offsetof(type_name, member) == offset`
+  OffsetOf {
+    type_name: String,
+    member: String,
+    offset: usize,
+  },
+  /// An enum's count constant (e.g. `COLOR_COUNT`) equals the number of variants
+  EnumCount {
+    enum_name: String,
+    constant: String,
+    count: usize,
+  },
+  /// A macro expands to the expected integer value
+  MacroValue { name: String, value: i128 },
+  /// Any constant expression
+  Custom { condition: String, message: String },
+}
+
+impl Invariant {
+  /// Create a size check
+  pub fn size_of(type_name: &str, size: usize) -> Self {
+    Invariant::SizeOf {
+      type_name: type_name.to_string(),
+      size,
+    }
+  }
+
+  /// Create a member offset check
+  pub fn offset_of(type_name: &str, member: &str, offset: usize) -> Self {
+    Invariant::OffsetOf {
+      type_name: type_name.to_string(),
+      member: member.to_string(),
+      offset,
+    }
+  }
+
+  /// Create an enum count check
+  pub fn enum_count(enum_name: &str, constant: &str, count: usize) -> Self {
+    Invariant::EnumCount {
+      enum_name: enum_name.to_string(),
+      constant: constant.to_string(),
+      count,
+    }
+  }
+
+  /// Create a macro value check
+  pub fn macro_value(name: &str, value: i128) -> Self {
+    Invariant::MacroValue {
+      name: name.to_string(),
+      value,
+    }
+  }
+
+  /// Create a check of an arbitrary constant expression
+  pub fn custom(condition: &str, message: &str) -> Self {
+    Invariant::Custom {
+      condition: condition.to_string(),
+      message: message.to_string(),
+    }
+  }
+
+  /// Get the asserted constant expression
+  pub fn condition(&self) -> String {
+    match self {
+      Invariant::SizeOf { type_name, size } => format!("sizeof({}) == {}", type_name, size),
+      Invariant::OffsetOf {
+        type_name,
+        member,
+        offset,
+      } => format!("offsetof({}, {}) == {}", type_name, member, offset),
+      Invariant::EnumCount {
+        constant, count, ..
+      } => format!("{} == {}", constant, count),
+      Invariant::MacroValue { name, value } if *value < 0 => format!("{} == ({})", name, value),
+      Invariant::MacroValue { name, value } => format!("{} == {}", name, value),
+      Invariant::Custom { condition, .. } => condition.clone(),
+    }
+  }
+
+  /// Get the diagnostic shown when the check fails
+  pub fn message(&self) -> String {
+    match self {
+      Invariant::SizeOf { type_name, size } => format!("{} must be {} bytes", type_name, size),
+      Invariant::OffsetOf {
+        type_name,
+        member,
+        offset,
+      } => format!("{} must be at offset {} in {}", member, offset, type_name),
+      Invariant::EnumCount {
+        enum_name, count, ..
+      } => format!("{} must have {} values", enum_name, count),
+      Invariant::MacroValue { name, value } => format!("{} must be {}", name, value),
+      Invariant::Custom { message, .. } => message.clone(),
+    }
+  }
+
+  /// Get a name for the typedef of the negative-array fallback
+  ///
+  /// The label alone may repeat, e.g. when two checks share a custom message,
+  /// and C89/C99 reject a repeated typedef, so `id` is appended.
+  fn typedef_name(&self, id: &str) -> String {
+    let label = match self {
+      Invariant::SizeOf { type_name, .. } => format!("sizeof_{}", type_name),
+      Invariant::OffsetOf {
+        type_name, member, ..
+      } => format!("offsetof_{}_{}", type_name, member),
+      Invariant::EnumCount { constant, .. } => format!("count_{}", constant),
+      Invariant::MacroValue { name, .. } => format!("value_{}", name),
+      Invariant::Custom { message, .. } => message.clone(),
+    };
+    sanitize_identifier(
+      &format!("static_assert_{}_{}", label, id),
+      LanguageStandard::C89,
+    )
+  }
+
+  /// Render the assertion as a single declaration
+  ///
+  /// `id` must differ between the assertions of one translation unit; it
+  /// only shows up in the typedef name of [`AssertStyle::NegativeArray`].
+  pub fn render(&self, style: AssertStyle, id: &str) -> String {
+    match style {
+      AssertStyle::StaticAssertC11 => format!(
+        "_Static_assert({}, {});",
+        self.condition(),
+        c_string_literal(&self.message())
+      ),
+      AssertStyle::StaticAssertCpp => format!(
+        "static_assert({}, {});",
+        self.condition(),
+        c_string_literal(&self.message())
+      ),
+      AssertStyle::NegativeArray => format!(
+        "typedef char {}[({}) ? 1 : -1];",
+        self.typedef_name(id),
+        self.condition()
+      ),
+    }
+  }
+}
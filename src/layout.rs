@@ -0,0 +1,456 @@
+use std::io::Write;
+
+use crate::attribute::Attribute;
+use crate::code_writer::CodeWriter;
+use crate::error::{CodeGenError, Result};
+use crate::invariant::Invariant;
+use crate::types::{AggregateKind, FieldDef, StructDef, TypeDef, TypeGraph, TypeRef};
+
+/// Size and alignment of a type in bytes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScalarLayout {
+  pub size: usize,
+  pub align: usize,
+}
+
+impl ScalarLayout {
+  const fn new(size: usize, align: usize) -> Self {
+    Self { size, align }
+  }
+}
+
+/// Data model of a compilation target, used to compute struct layouts
+///
+/// `char` is always one byte. Fixed-width types from `<stdint.h>` map to the
+/// standard type of the same width.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TargetAbi {
+  pub short: ScalarLayout,
+  pub int: ScalarLayout,
+  pub long: ScalarLayout,
+  pub long_long: ScalarLayout,
+  pub pointer: ScalarLayout,
+  pub float: ScalarLayout,
+  pub double: ScalarLayout,
+  pub long_double: ScalarLayout,
+  pub bool: ScalarLayout,
+  /// Maximum member alignment, as set by `#pragma pack(n)`
+  pub pack: Option<usize>,
+}
+
+impl TargetAbi {
+  /// 32-bit ARM with the AAPCS procedure call standard (Cortex-M, Cortex-A32)
+  pub fn ilp32_arm_eabi() -> Self {
+    Self {
+      short: ScalarLayout::new(2, 2),
+      int: ScalarLayout::new(4, 4),
+      long: ScalarLayout::new(4, 4),
+      long_long: ScalarLayout::new(8, 8),
+      pointer: ScalarLayout::new(4, 4),
+      float: ScalarLayout::new(4, 4),
+      double: ScalarLayout::new(8, 8),
+      long_double: ScalarLayout::new(8, 8),
+      bool: ScalarLayout::new(1, 1),
+      pack: None,
+    }
+  }
+
+  /// 64-bit x86 with the System V ABI (Linux, macOS)
+  pub fn lp64_x86_64() -> Self {
+    Self {
+      short: ScalarLayout::new(2, 2),
+      int: ScalarLayout::new(4, 4),
+      long: ScalarLayout::new(8, 8),
+      long_long: ScalarLayout::new(8, 8),
+      pointer: ScalarLayout::new(8, 8),
+      float: ScalarLayout::new(4, 4),
+      double: ScalarLayout::new(8, 8),
+      long_double: ScalarLayout::new(16, 16),
+      bool: ScalarLayout::new(1, 1),
+      pack: None,
+    }
+  }
+
+  /// Limit member alignment as `#pragma pack(n)` does, returning the ABI for chaining
+  pub fn with_pack(mut self, pack: usize) -> Self {
+    self.pack = Some(pack.max(1));
+    self
+  }
+
+  /// Get the layout of a scalar type by name, if it is known
+  pub fn scalar(&self, name: &str) -> Option<ScalarLayout> {
+    let one = ScalarLayout::new(1, 1);
+    let layout = match name {
+      "char" | "signed char" | "unsigned char" | "int8_t" | "uint8_t" => one,
+      "bool" | "_Bool" => self.bool,
+      "short" | "unsigned short" | "int16_t" | "uint16_t" => self.short,
+      "int" | "unsigned" | "unsigned int" | "int32_t" | "uint32_t" => self.int,
+      "long" | "unsigned long" => self.long,
+      "long long" | "unsigned long long" | "int64_t" | "uint64_t" => self.long_long,
+      "size_t" | "ptrdiff_t" | "intptr_t" | "uintptr_t" => self.pointer,
+      "float" => self.float,
+      "double" => self.double,
+      "long double" => self.long_double,
+      _ if name.starts_with("enum ") => self.int,
+      _ => return None,
+    };
+    Some(layout)
+  }
+
+  /// Apply the pack limit to a member alignment
+  fn member_align(&self, align: usize) -> usize {
+    match self.pack {
+      Some(pack) => align.min(pack),
+      None => align,
+    }
+  }
+
+  /// Compute the layout of a type referenced by a member or typedef
+  fn type_layout(
+    &self,
+    graph: &TypeGraph,
+    ty: &TypeRef,
+    visiting: &mut Vec<String>,
+  ) -> Result<ScalarLayout> {
+    if ty.is_pointer() {
+      return Ok(self.pointer);
+    }
+    let name = ty.base_name();
+    if let Some(layout) = self.scalar(name) {
+      return Ok(layout);
+    }
+    match graph.get(name) {
+      Some(TypeDef::Aggregate(def)) => {
+        let layout = self.aggregate_layout(graph, def, visiting)?;
+        Ok(ScalarLayout::new(layout.size, layout.align))
+      }
+      Some(TypeDef::Typedef { target, .. }) => self.type_layout(graph, target, visiting),
+      None => Err(CodeGenError::InvalidDefinition(format!(
+        "size of type '{}' is unknown",
+        ty.name
+      ))),
+    }
+  }
+
+  /// Compute the layout of a struct or union defined in `graph`
+  pub fn layout(&self, graph: &TypeGraph, name: &str) -> Result<StructLayout> {
+    let def = self.find_aggregate(graph, name)?;
+    self.aggregate_layout(graph, def, &mut Vec::new())
+  }
+
+  /// Look up a struct or union in the type graph
+  fn find_aggregate<'a>(&self, graph: &'a TypeGraph, name: &str) -> Result<&'a StructDef> {
+    match graph.get(name) {
+      Some(TypeDef::Aggregate(def)) => Ok(def),
+      _ => Err(CodeGenError::InvalidDefinition(format!(
+        "'{}' is not a struct or union of the type graph",
+        name
+      ))),
+    }
+  }
+
+  /// Compute the layout of a struct or union definition
+  ///
+  /// Bit-fields are allocated from containers of their declared type and
+  /// never straddle a container boundary, as in both the AAPCS and the
+  /// System V ABI. Bit-fields in packed structs are compiler specific and
+  /// rejected.
+  pub fn aggregate_layout(
+    &self,
+    graph: &TypeGraph,
+    def: &StructDef,
+    visiting: &mut Vec<String>,
+  ) -> Result<StructLayout> {
+    if visiting.contains(&def.name) {
+      visiting.push(def.name.clone());
+      return Err(CodeGenError::DependencyCycle(visiting.join(" -> ")));
+    }
+    visiting.push(def.name.clone());
+
+    let packed = def.has_attribute(&Attribute::Packed);
+    let mut fields = Vec::with_capacity(def.fields.len());
+    let mut bit_pos = 0;
+    let mut size = 0;
+    let mut align = 1;
+    for field in &def.fields {
+      let ty = self.type_layout(graph, &field.ty, visiting)?;
+      let field_align = if packed {
+        1
+      } else {
+        self.member_align(ty.align)
+      };
+      align = align.max(field_align);
+      let count = array_count(field)?;
+
+      let layout = match field.bit_width {
+        Some(width) => {
+          let width = width as usize;
+          if self.pack.is_some() || packed {
+            return Err(CodeGenError::InvalidDefinition(format!(
+              "bit-field '{}.{}' in a packed struct",
+              def.name, field.name
+            )));
+          }
+          if field.array_len.is_some() || width == 0 || width > ty.size * 8 {
+            return Err(CodeGenError::InvalidDefinition(format!(
+              "bit-field '{}.{}' has an invalid width of {}",
+              def.name, field.name, width
+            )));
+          }
+          let start = if def.kind == AggregateKind::Union {
+            0
+          } else {
+            let container = bit_pos / (field_align * 8) * field_align * 8;
+            if bit_pos + width > container + ty.size * 8 {
+              round_up(bit_pos, field_align * 8)
+            } else {
+              bit_pos
+            }
+          };
+          bit_pos = start + width;
+          size = size.max(bit_pos.div_ceil(8));
+          FieldLayout {
+            name: field.name.clone(),
+            offset: start / 8,
+            size: ty.size,
+            bit_offset: Some(start % 8),
+            bit_width: Some(width),
+          }
+        }
+        None => {
+          let offset = match def.kind {
+            AggregateKind::Struct => round_up(bit_pos.div_ceil(8), field_align),
+            AggregateKind::Union => 0,
+          };
+          let field_size = ty.size * count;
+          bit_pos = (offset + field_size) * 8;
+          size = size.max(offset + field_size);
+          FieldLayout {
+            name: field.name.clone(),
+            offset,
+            size: field_size,
+            bit_offset: None,
+            bit_width: None,
+          }
+        }
+      };
+      if def.kind == AggregateKind::Union {
+        bit_pos = 0;
+      }
+      fields.push(layout);
+    }
+
+    for attribute in &def.attributes {
+      if let Attribute::Aligned(n) = attribute {
+        align = align.max(*n);
+      }
+    }
+
+    visiting.pop();
+    Ok(StructLayout {
+      name: def.name.clone(),
+      kind: def.kind,
+      size: round_up(size, align),
+      align,
+      fields,
+    })
+  }
+
+  /// Write a struct or union with the requested layout annotations
+  ///
+  /// Under a pack limit the definition is wrapped in `#pragma pack`.
+  /// `<stdint.h>` and `<stddef.h>` are registered when an include point is
+  /// reserved.
+  pub fn write_struct<W: Write>(
+    &self,
+    writer: &mut CodeWriter<W>,
+    graph: &TypeGraph,
+    name: &str,
+    options: &LayoutOptions,
+  ) -> Result<StructLayout> {
+    let def = self.find_aggregate(graph, name)?;
+    let mut layout = self.aggregate_layout(graph, def, &mut Vec::new())?;
+    let def = if options.padding {
+      let padded = layout.pad_struct(def);
+      layout = self.aggregate_layout(graph, &padded, &mut Vec::new())?;
+      padded
+    } else {
+      def.clone()
+    };
+    if writer.has_reserved_includes() {
+      if options.padding {
+        writer.require_include("stdint.h", true);
+      }
+      if options.static_asserts {
+        writer.require_include("stddef.h", true);
+      }
+    }
+
+    if let Some(pack) = self.pack {
+      writer.writeln(&format!("#pragma pack(push, {})", pack))?;
+    }
+    let comments = if options.offset_comments {
+      layout.offset_comments()
+    } else {
+      Vec::new()
+    };
+    def.write_annotated(writer, false, &comments)?;
+    if self.pack.is_some() {
+      writer.writeln("#pragma pack(pop)")?;
+    }
+
+    if options.static_asserts {
+      layout.write_static_asserts(writer)?;
+    }
+    Ok(layout)
+  }
+}
+
+/// Get the element count of a member
+fn array_count(field: &FieldDef) -> Result<usize> {
+  match field.array_len {
+    Some(ref len) => len.trim().parse().map_err(|_| {
+      CodeGenError::InvalidDefinition(format!(
+        "array length '{}' of '{}' is not a number",
+        len, field.name
+      ))
+    }),
+    None => Ok(1),
+  }
+}
+
+/// Round `value` up to a multiple of `align`
+fn round_up(value: usize, align: usize) -> usize {
+  value.div_ceil(align) * align
+}
+
+/// Position of a member within its struct or union
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldLayout {
+  /// The member name
+  pub name: String,
+  /// Offset in bytes (of the byte holding the first bit, for bit-fields)
+  pub offset: usize,
+  /// Size in bytes (of the container, for bit-fields)
+  pub size: usize,
+  /// First bit within the byte at `offset`, for bit-fields
+  pub bit_offset: Option<usize>,
+  /// Width in bits, for bit-fields
+  pub bit_width: Option<usize>,
+}
+
+/// Computed size, alignment and member offsets of a struct or union
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StructLayout {
+  /// The tag name
+  pub name: String,
+  /// Struct or union
+  pub kind: AggregateKind,
+  /// Size in bytes, including tail padding
+  pub size: usize,
+  /// Alignment in bytes
+  pub align: usize,
+  /// The members, in declaration order
+  pub fields: Vec<FieldLayout>,
+}
+
+impl StructLayout {
+  /// Get the layout of a member by name
+  pub fn field(&self, name: &str) -> Option<&FieldLayout> {
+    self.fields.iter().find(|f| f.name == name)
+  }
+
+  /// Get the type as written in `sizeof`/`offsetof`, e.g. `struct packet`
+  pub fn type_name(&self) -> String {
+    format!("{} {}", self.kind.keyword(), self.name)
+  }
+
+  /// Return a copy of a struct with explicit `uint8_t padN[k]` members for all implicit padding
+  ///
+  /// Unions are returned unchanged.
+  pub fn pad_struct(&self, def: &StructDef) -> StructDef {
+    if def.kind == AggregateKind::Union {
+      return def.clone();
+    }
+
+    let mut padded = StructDef {
+      fields: Vec::with_capacity(def.fields.len()),
+      ..def.clone()
+    };
+    let mut next_pad = 0;
+    let mut add_padding = |padded: &mut StructDef, len: usize| {
+      while def
+        .fields
+        .iter()
+        .any(|f| f.name == format!("pad{}", next_pad))
+      {
+        next_pad += 1;
+      }
+      padded.add_field(
+        FieldDef::new(&format!("pad{}", next_pad), TypeRef::new("uint8_t"))
+          .with_array_len(&len.to_string()),
+      );
+      next_pad += 1;
+    };
+
+    let mut end = 0;
+    for (field, layout) in def.fields.iter().zip(&self.fields) {
+      match (layout.bit_offset, layout.bit_width) {
+        (Some(bit), Some(width)) => {
+          end = end.max((layout.offset * 8 + bit + width).div_ceil(8));
+        }
+        _ => {
+          if layout.offset > end {
+            add_padding(&mut padded, layout.offset - end);
+          }
+          end = layout.offset + layout.size;
+        }
+      }
+      padded.add_field(field.clone());
+    }
+    if self.size > end {
+      add_padding(&mut padded, self.size - end);
+    }
+    padded
+  }
+
+  /// Get a comment per member describing its position, e.g. `offset 4` or `offset 4, bit 3`
+  pub fn offset_comments(&self) -> Vec<String> {
+    self
+      .fields
+      .iter()
+      .map(|f| match f.bit_offset {
+        Some(bit) => format!("offset {}, bit {}", f.offset, bit),
+        None => format!("offset {}", f.offset),
+      })
+      .collect()
+  }
+
+  /// Get the invariants checking the size and every member offset
+  ///
+  /// Bit-fields are skipped since `offsetof` cannot be applied to them.
+  pub fn invariants(&self) -> Vec<Invariant> {
+    let type_name = self.type_name();
+    let mut invariants = vec![Invariant::size_of(&type_name, self.size)];
+    for field in self.fields.iter().filter(|f| f.bit_width.is_none()) {
+      invariants.push(Invariant::offset_of(&type_name, &field.name, field.offset));
+    }
+    invariants
+  }
+
+  /// Write static assertions of the size and every member offset
+  pub fn write_static_asserts<W: Write>(&self, writer: &mut CodeWriter<W>) -> Result<()> {
+    writer.write_static_asserts(&self.invariants())
+  }
+}
+
+/// Annotations written by `TargetAbi::write_struct`
+#[derive(Debug, Clone, Default)]
+pub struct LayoutOptions {
+  /// Insert explicit padding members
+  pub padding: bool,
+  /// Comment each member with its offset
+  pub offset_comments: bool,
+  /// Follow the definition with static assertions of size and offsets, in the writer's assert style
+  pub static_asserts: bool,
+}
@@ -1,16 +1,81 @@
 // Code generation module for generating code with user-modifiable sections
 
+mod attribute;
 mod code_writer;
+mod comment;
+mod compilation_unit;
+mod cpp;
+mod data_table;
+mod doc_comment;
+mod embed;
+mod enum_table;
 mod error;
 pub mod examples;
+mod expr;
+mod function_macro;
 mod generated_code;
+mod header;
+mod includes;
+mod initializer;
+mod invariant;
+mod layout;
+mod literal;
+mod naming;
+mod numeric;
+mod preprocessor;
+mod register;
+mod schema;
+mod standard;
+mod svd;
+mod symbols;
 #[cfg(test)]
 mod tests;
+mod types;
 mod user_section;
 mod utils;
 
+pub use attribute::{
+  write_portability_macros, Attribute, AttributeTarget, Compiler, RenderedAttributes,
+};
 pub use code_writer::CodeWriter;
+pub use comment::CommentPolicy;
+pub use compilation_unit::{CompilationUnit, FunctionBody, FunctionDef, StorageClass};
+pub use cpp::{AccessSpecifier, Method, MethodBody};
+pub use data_table::{StructValue, TableOptions, TableSize, TableValue};
+pub use doc_comment::{DocComment, DocStyle};
+pub use embed::BinaryEmbed;
+pub use enum_table::{EnumTable, EnumVariant, LookupStyle};
 pub use error::{CodeGenError, Result};
 pub use examples::{generate_example_header, generate_example_source};
+pub use expr::{BinaryOp, Expr, UnaryOp};
+pub use function_macro::FunctionMacro;
 pub use generated_code::GeneratedCodeManager;
+pub use header::{HeaderScaffold, IncludeGuard};
+pub use includes::IncludeSet;
+pub use initializer::{
+  Initializer, InitializerOptions, InitializerSerializer, ListBuilder, StructBuilder,
+};
+pub use invariant::{AssertStyle, Invariant};
+pub use layout::{FieldLayout, LayoutOptions, ScalarLayout, StructLayout, TargetAbi};
+pub use literal::{
+  c_char_literal, c_string_literal, c_string_literal_with, escape_c_string, split_string_literal,
+  NonAsciiMode,
+};
+pub use naming::{
+  is_reserved_identifier, sanitize_identifier, split_words, to_lower_camel_case,
+  to_screaming_snake_case, to_snake_case, to_upper_camel_case, Case, NamingConvention,
+};
+pub use numeric::{float_literal, int_literal, CType, NumberFormat};
+pub use preprocessor::PpExpr;
+pub use register::{AccessorOptions, BitField, RegisterAccess, RegisterDef};
+pub use schema::{
+  ArrayLen, ConstantSpec, ConstantValue, EnumSpec, EnumValueSpec, FieldSpec, FunctionSpec,
+  HeaderSpec, ParamSpec, Schema, StructSpec,
+};
+pub use standard::LanguageStandard;
+pub use svd::{
+  FieldStyle, RegisterMapOptions, SvdCluster, SvdDevice, SvdField, SvdPeripheral, SvdRegister,
+};
+pub use symbols::{SymbolEntry, SymbolKind, SymbolOrigin, SymbolRegistry, SymbolRole};
+pub use types::{AggregateKind, FieldDef, StructDef, TypeDef, TypeGraph, TypeRef};
 pub use user_section::{UserSection, UserSectionManager};
@@ -0,0 +1,144 @@
+use crate::error::{CodeGenError, Result};
+
+/// How non-ASCII characters are written in string literals
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NonAsciiMode {
+  /// Each UTF-8 byte as a `\xNN` escape in a plain literal
+  HexEscape,
+  /// Raw UTF-8 in a `u8"..."` literal
+  Utf8Prefix,
+}
+
+/// Escape a single character for use inside a string literal
+///
+/// `prev` is the previous character, used to break up `??` trigraph
+/// sequences.
+fn escape_char(c: char, prev: Option<char>, mode: NonAsciiMode) -> String {
+  match c {
+    '\x07' => "\\a".to_string(),
+    '\x08' => "\\b".to_string(),
+    '\x0C' => "\\f".to_string(),
+    '\n' => "\\n".to_string(),
+    '\r' => "\\r".to_string(),
+    '\t' => "\\t".to_string(),
+    '\x0B' => "\\v".to_string(),
+    '\\' => "\\\\".to_string(),
+    '"' => "\\\"".to_string(),
+    '?' if prev == Some('?') => "\\?".to_string(),
+    // Three-digit octal escapes cannot absorb a following digit
+    c if c.is_ascii_control() => format!("\\{:03o}", c as u32),
+    c if c.is_ascii() => c.to_string(),
+    c => match mode {
+      NonAsciiMode::Utf8Prefix => c.to_string(),
+      NonAsciiMode::HexEscape => {
+        let mut buf = [0u8; 4];
+        c.encode_utf8(&mut buf)
+          .bytes()
+          .map(|b| format!("\\x{:02X}", b))
+          .collect()
+      }
+    },
+  }
+}
+
+/// Escape a string into tokens, one per source character
+fn escape_tokens(s: &str, mode: NonAsciiMode) -> Vec<String> {
+  let mut tokens = Vec::new();
+  let mut prev = None;
+  for c in s.chars() {
+    tokens.push(escape_char(c, prev, mode));
+    prev = Some(c);
+  }
+  tokens
+}
+
+/// Join escaped tokens, separating a `\x` escape from a following hex digit
+fn join_tokens(tokens: &[String]) -> String {
+  let mut out = String::new();
+  let mut after_hex_escape = false;
+  for token in tokens {
+    if after_hex_escape && token.starts_with(|c: char| c.is_ascii_hexdigit()) {
+      out.push_str("\"\"");
+    }
+    out.push_str(token);
+    after_hex_escape = token.starts_with("\\x");
+  }
+  out
+}
+
+/// Get the literal prefix for a string in the given mode
+fn prefix(s: &str, mode: NonAsciiMode) -> &'static str {
+  if mode == NonAsciiMode::Utf8Prefix && !s.is_ascii() {
+    "u8"
+  } else {
+    ""
+  }
+}
+
+/// Escape a string for use between the quotes of a C string literal
+pub fn escape_c_string(s: &str, mode: NonAsciiMode) -> String {
+  join_tokens(&escape_tokens(s, mode))
+}
+
+/// Format a string as a C string literal, writing non-ASCII characters as `\x` escapes
+pub fn c_string_literal(s: &str) -> String {
+  c_string_literal_with(s, NonAsciiMode::HexEscape)
+}
+
+/// Format a string as a C string literal
+pub fn c_string_literal_with(s: &str, mode: NonAsciiMode) -> String {
+  format!("{}\"{}\"", prefix(s, mode), escape_c_string(s, mode))
+}
+
+/// Split a string into literals of at most `max_width` columns each
+///
+/// Pieces are also broken after each `\n` so that multi-line text reads
+/// naturally. Escape sequences are never split.
+pub fn split_string_literal(s: &str, max_width: usize, mode: NonAsciiMode) -> Vec<String> {
+  let prefix = prefix(s, mode);
+  let budget = max_width.saturating_sub(prefix.len() + 2).max(1);
+
+  let mut pieces = Vec::new();
+  let mut current: Vec<String> = Vec::new();
+  let mut current_len = 0;
+  for token in escape_tokens(s, mode) {
+    if !current.is_empty() && current_len + token.len() > budget {
+      pieces.push(std::mem::take(&mut current));
+      current_len = 0;
+    }
+    current_len += token.len();
+    let ends_line = token == "\\n";
+    current.push(token);
+    if ends_line {
+      pieces.push(std::mem::take(&mut current));
+      current_len = 0;
+    }
+  }
+  if !current.is_empty() || pieces.is_empty() {
+    pieces.push(current);
+  }
+
+  pieces
+    .iter()
+    .map(|tokens| format!("{}\"{}\"", prefix, join_tokens(tokens)))
+    .collect()
+}
+
+/// Format a character as a C character literal
+///
+/// Only ASCII characters fit in a plain `char` literal.
+pub fn c_char_literal(c: char) -> Result<String> {
+  match c {
+    '\'' => Ok("'\\''".to_string()),
+    '"' => Ok("'\"'".to_string()),
+    '?' => Ok("'?'".to_string()),
+    c if c.is_ascii() => Ok(format!(
+      "'{}'",
+      escape_char(c, None, NonAsciiMode::HexEscape)
+    )),
+    c => Err(CodeGenError::InvalidDefinition(format!(
+      "character '{}' (U+{:04X}) does not fit in a char literal",
+      c, c as u32
+    ))),
+  }
+}
@@ -0,0 +1,239 @@
+use crate::error::{CodeGenError, Result};
+use crate::standard::LanguageStandard;
+
+/// Letter case convention for generated identifiers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Case {
+  /// Keep the words of the name as given
+  Preserve,
+  /// `snake_case`
+  Snake,
+  /// `UpperCamelCase`
+  UpperCamel,
+  /// `lowerCamelCase`
+  LowerCamel,
+  /// `SCREAMING_SNAKE_CASE`
+  ScreamingSnake,
+}
+
+/// Split a name into words at separators and case boundaries
+///
+/// Any character other than an ASCII letter or digit separates words.
+/// `HTTPServer2Config` splits into `HTTP`, `Server2` and `Config`.
+pub fn split_words(name: &str) -> Vec<String> {
+  let chars: Vec<char> = name.chars().collect();
+  let mut words = Vec::new();
+  let mut current = String::new();
+
+  for (i, &c) in chars.iter().enumerate() {
+    if !c.is_ascii_alphanumeric() {
+      if !current.is_empty() {
+        words.push(std::mem::take(&mut current));
+      }
+      continue;
+    }
+
+    if c.is_ascii_uppercase() && !current.is_empty() {
+      let prev = chars[i - 1];
+      let next_is_lower = chars.get(i + 1).is_some_and(|n| n.is_ascii_lowercase());
+      if prev.is_ascii_lowercase()
+        || prev.is_ascii_digit()
+        || (prev.is_ascii_uppercase() && next_is_lower)
+      {
+        words.push(std::mem::take(&mut current));
+      }
+    }
+    current.push(c);
+  }
+  if !current.is_empty() {
+    words.push(current);
+  }
+
+  words
+}
+
+/// Capitalize the first letter of a word and lowercase the rest
+fn capitalize(word: &str) -> String {
+  let lower = word.to_ascii_lowercase();
+  let mut chars = lower.chars();
+  match chars.next() {
+    Some(first) => first.to_ascii_uppercase().to_string() + chars.as_str(),
+    None => String::new(),
+  }
+}
+
+/// Convert a name to `snake_case`
+pub fn to_snake_case(name: &str) -> String {
+  let words: Vec<String> = split_words(name)
+    .iter()
+    .map(|w| w.to_ascii_lowercase())
+    .collect();
+  words.join("_")
+}
+
+/// Convert a name to `SCREAMING_SNAKE_CASE`
+pub fn to_screaming_snake_case(name: &str) -> String {
+  let words: Vec<String> = split_words(name)
+    .iter()
+    .map(|w| w.to_ascii_uppercase())
+    .collect();
+  words.join("_")
+}
+
+/// Convert a name to `UpperCamelCase`
+pub fn to_upper_camel_case(name: &str) -> String {
+  split_words(name).iter().map(|w| capitalize(w)).collect()
+}
+
+/// Convert a name to `lowerCamelCase`
+pub fn to_lower_camel_case(name: &str) -> String {
+  split_words(name)
+    .iter()
+    .enumerate()
+    .map(|(i, w)| {
+      if i == 0 {
+        w.to_ascii_lowercase()
+      } else {
+        capitalize(w)
+      }
+    })
+    .collect()
+}
+
+/// Check whether an identifier is reserved for the implementation
+///
+/// Names starting with an underscore and an uppercase letter are reserved
+/// everywhere. A double underscore is reserved at the start of a C name and
+/// anywhere in a C++ name.
+pub fn is_reserved_identifier(name: &str, standard: LanguageStandard) -> bool {
+  let bytes = name.as_bytes();
+  if bytes.len() >= 2 && bytes[0] == b'_' && (bytes[1].is_ascii_uppercase() || bytes[1] == b'_') {
+    return true;
+  }
+  standard.is_cpp() && name.contains("__")
+}
+
+/// Get the reason `name` cannot be declared in C, or in C++ too if `cpp` is set
+///
+/// Used to validate names taken from input files, which are kept as given
+/// rather than sanitized.
+pub(crate) fn identifier_problem(name: &str, cpp: bool) -> Option<String> {
+  let valid = name
+    .chars()
+    .next()
+    .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+    && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+    && !LanguageStandard::C99.is_keyword(name);
+  let standard = if cpp {
+    LanguageStandard::Cpp20
+  } else {
+    LanguageStandard::C99
+  };
+  if !valid {
+    Some(format!("'{}' is not a valid C identifier", name))
+  } else if cpp && standard.is_keyword(name) {
+    Some(format!("'{}' is a C++ keyword", name))
+  } else if is_reserved_identifier(name, standard) {
+    Some(format!("'{}' is reserved for the implementation", name))
+  } else {
+    None
+  }
+}
+
+/// Turn arbitrary text into a valid, non-reserved identifier
+///
+/// Only ASCII letters, digits and underscores are kept; anything else becomes
+/// an underscore. Runs of underscores are collapsed and leading underscores
+/// are dropped, which also avoids names reserved at file scope. A leading
+/// digit is prefixed with `n`, an empty result becomes `unnamed`, and a
+/// keyword of the standard gets a trailing underscore (`int` becomes `int_`).
+pub fn sanitize_identifier(name: &str, standard: LanguageStandard) -> String {
+  let mut result = String::with_capacity(name.len());
+  for c in name.chars() {
+    let c = if c.is_ascii_alphanumeric() { c } else { '_' };
+    if c == '_' && (result.is_empty() || result.ends_with('_')) {
+      continue;
+    }
+    result.push(c);
+  }
+
+  if result.is_empty() {
+    return "unnamed".to_string();
+  }
+  if result.starts_with(|c: char| c.is_ascii_digit()) {
+    result.insert(0, 'n');
+  }
+  if standard.is_keyword(&result) {
+    result.push('_');
+  }
+
+  result
+}
+
+/// Derive an identifier from a file or asset name, e.g. `logo.png` becomes `logo_png`
+///
+/// Fails instead of falling back to `unnamed` when the name has no ASCII
+/// letters or digits, since the result would not be recognizable.
+pub(crate) fn identifier_from_name(name: &str) -> Result<String> {
+  if !name.chars().any(|c| c.is_ascii_alphanumeric()) {
+    return Err(CodeGenError::InvalidDefinition(format!(
+      "cannot derive an identifier from '{}'",
+      name
+    )));
+  }
+  Ok(sanitize_identifier(name, LanguageStandard::C99))
+}
+
+/// Rules for mapping source names (e.g. from a schema) to C symbols
+///
+/// The same input always produces the same symbol.
+#[derive(Debug, Clone)]
+pub struct NamingConvention {
+  /// Case applied to the words of the name
+  pub case: Case,
+  /// Text placed before the converted name (e.g. `MYLIB_`)
+  pub prefix: String,
+  /// Text placed after the converted name (e.g. `_t`)
+  pub suffix: String,
+  /// Standard whose keywords are avoided
+  pub standard: LanguageStandard,
+}
+
+impl NamingConvention {
+  /// Create a convention without prefix or suffix
+  pub fn new(case: Case, standard: LanguageStandard) -> Self {
+    Self {
+      case,
+      prefix: String::new(),
+      suffix: String::new(),
+      standard,
+    }
+  }
+
+  /// Set the prefix, returning the convention for chaining
+  pub fn with_prefix(mut self, prefix: &str) -> Self {
+    self.prefix = prefix.to_string();
+    self
+  }
+
+  /// Set the suffix, returning the convention for chaining
+  pub fn with_suffix(mut self, suffix: &str) -> Self {
+    self.suffix = suffix.to_string();
+    self
+  }
+
+  /// Map a name to a symbol following the convention
+  pub fn apply(&self, name: &str) -> String {
+    let converted = match self.case {
+      Case::Preserve => name.to_string(),
+      Case::Snake => to_snake_case(name),
+      Case::UpperCamel => to_upper_camel_case(name),
+      Case::LowerCamel => to_lower_camel_case(name),
+      Case::ScreamingSnake => to_screaming_snake_case(name),
+    };
+    sanitize_identifier(
+      &format!("{}{}{}", self.prefix, converted, self.suffix),
+      self.standard,
+    )
+  }
+}
@@ -0,0 +1,175 @@
+use crate::error::{CodeGenError, Result};
+
+/// How integer values are rendered
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NumberFormat {
+  /// Plain decimal
+  Decimal,
+  /// `0x`-prefixed uppercase hex, zero padded to `width` digits
+  Hex { width: usize },
+}
+
+/// An arithmetic C type that a literal is written for
+///
+/// `long` is assumed to be at least 32 bits wide, as guaranteed by the
+/// standard, so that range checks hold on every target.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CType {
+  Int8,
+  UInt8,
+  Int16,
+  UInt16,
+  Int32,
+  UInt32,
+  Long,
+  ULong,
+  Int64,
+  UInt64,
+  Float,
+  Double,
+}
+
+impl CType {
+  /// Get the C spelling of the type
+  pub fn name(&self) -> &'static str {
+    match self {
+      CType::Int8 => "int8_t",
+      CType::UInt8 => "uint8_t",
+      CType::Int16 => "int16_t",
+      CType::UInt16 => "uint16_t",
+      CType::Int32 => "int32_t",
+      CType::UInt32 => "uint32_t",
+      CType::Long => "long",
+      CType::ULong => "unsigned long",
+      CType::Int64 => "int64_t",
+      CType::UInt64 => "uint64_t",
+      CType::Float => "float",
+      CType::Double => "double",
+    }
+  }
+
+  /// Look up a type by its C spelling, e.g. `uint16_t`
+  pub fn from_name(name: &str) -> Option<Self> {
+    const ALL: [CType; 12] = [
+      CType::Int8,
+      CType::UInt8,
+      CType::Int16,
+      CType::UInt16,
+      CType::Int32,
+      CType::UInt32,
+      CType::Long,
+      CType::ULong,
+      CType::Int64,
+      CType::UInt64,
+      CType::Float,
+      CType::Double,
+    ];
+    ALL.into_iter().find(|ty| ty.name() == name)
+  }
+
+  /// Check whether the type is a floating point type
+  pub fn is_float(&self) -> bool {
+    matches!(self, CType::Float | CType::Double)
+  }
+
+  /// Get the inclusive value range of an integer type
+  pub fn int_range(&self) -> Option<(i128, i128)> {
+    match self {
+      CType::Int8 => Some((i8::MIN as i128, i8::MAX as i128)),
+      CType::UInt8 => Some((0, u8::MAX as i128)),
+      CType::Int16 => Some((i16::MIN as i128, i16::MAX as i128)),
+      CType::UInt16 => Some((0, u16::MAX as i128)),
+      CType::Int32 | CType::Long => Some((i32::MIN as i128, i32::MAX as i128)),
+      CType::UInt32 | CType::ULong => Some((0, u32::MAX as i128)),
+      CType::Int64 => Some((i64::MIN as i128, i64::MAX as i128)),
+      CType::UInt64 => Some((0, u64::MAX as i128)),
+      CType::Float | CType::Double => None,
+    }
+  }
+
+  /// Get the literal suffix of an integer type
+  fn int_suffix(&self) -> &'static str {
+    match self {
+      CType::UInt8 | CType::UInt16 | CType::UInt32 => "U",
+      CType::Long => "L",
+      CType::ULong => "UL",
+      CType::Int64 => "LL",
+      CType::UInt64 => "ULL",
+      _ => "",
+    }
+  }
+}
+
+/// Render an integer as a C literal of the given type
+///
+/// The most negative value of a signed type cannot be written directly
+/// (`-2147483648` is the negation of an out-of-range constant), so it is
+/// written as `(-2147483647 - 1)`.
+pub fn int_literal(value: i128, ty: CType, format: NumberFormat) -> Result<String> {
+  let (min, max) = ty.int_range().ok_or_else(|| {
+    CodeGenError::InvalidDefinition(format!("{} is not an integer type", ty.name()))
+  })?;
+  if value < min || value > max {
+    return Err(CodeGenError::ValueOutOfRange {
+      value: value.to_string(),
+      type_name: ty.name().to_string(),
+    });
+  }
+
+  let suffix = ty.int_suffix();
+  if value == min && min < 0 {
+    return Ok(format!("(-{}{} - 1)", max, suffix));
+  }
+
+  Ok(format!("{}{}", format_int(value, format), suffix))
+}
+
+/// Render an integer in the given format without type suffix or range check
+pub(crate) fn format_int(value: i128, format: NumberFormat) -> String {
+  let magnitude = value.unsigned_abs();
+  let sign = if value < 0 { "-" } else { "" };
+  match format {
+    NumberFormat::Decimal => format!("{}{}", sign, magnitude),
+    NumberFormat::Hex { width } => format!("{}0x{:0width$X}", sign, magnitude, width = width),
+  }
+}
+
+/// Render a floating point value as the shortest C literal that round-trips
+///
+/// Values that overflow the type, or nonzero values that underflow to zero,
+/// are rejected as out of range.
+pub fn float_literal(value: f64, ty: CType) -> Result<String> {
+  let out_of_range = || CodeGenError::ValueOutOfRange {
+    value: value.to_string(),
+    type_name: ty.name().to_string(),
+  };
+  if !value.is_finite() {
+    return Err(out_of_range());
+  }
+
+  let (text, suffix) = match ty {
+    CType::Float => {
+      let narrowed = value as f32;
+      // Overflow becomes infinity and underflow zero
+      if !narrowed.is_finite() || (narrowed == 0.0 && value != 0.0) {
+        return Err(out_of_range());
+      }
+      (format!("{:?}", narrowed), "f")
+    }
+    CType::Double => (format!("{:?}", value), ""),
+    _ => {
+      return Err(CodeGenError::InvalidDefinition(format!(
+        "{} is not a floating point type",
+        ty.name()
+      )))
+    }
+  };
+
+  // Keep the literal floating point even when it is integral (`1e20` stays as is)
+  let text = if text.contains(['.', 'e', 'E']) {
+    text
+  } else {
+    format!("{}.0", text)
+  };
+  Ok(format!("{}{}", text, suffix))
+}
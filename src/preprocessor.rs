@@ -0,0 +1,102 @@
+use std::fmt;
+
+use crate::expr::BinaryOp;
+
+/// An expression of a `#if` / `#elif` directive
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PpExpr {
+  /// `defined(NAME)`
+  Defined(String),
+  /// A macro name
+  Ident(String),
+  /// An integer constant
+  Int(i64),
+  /// Logical negation
+  Not(Box<PpExpr>),
+  /// A binary operation
+  Binary(Box<PpExpr>, BinaryOp, Box<PpExpr>),
+}
+
+impl PpExpr {
+  /// Create a `defined(NAME)` expression
+  pub fn defined(name: &str) -> Self {
+    PpExpr::Defined(name.to_string())
+  }
+
+  /// Create a macro name expression
+  pub fn ident(name: &str) -> Self {
+    PpExpr::Ident(name.to_string())
+  }
+
+  /// Create an integer constant expression
+  pub fn int(value: i64) -> Self {
+    PpExpr::Int(value)
+  }
+
+  /// Create a binary operation
+  pub fn binary(lhs: PpExpr, op: BinaryOp, rhs: PpExpr) -> Self {
+    PpExpr::Binary(Box::new(lhs), op, Box::new(rhs))
+  }
+
+  /// Combine with another expression using `op`
+  pub fn compare(self, op: BinaryOp, rhs: PpExpr) -> Self {
+    Self::binary(self, op, rhs)
+  }
+
+  /// Combine with another expression using `&&`
+  pub fn and(self, rhs: PpExpr) -> Self {
+    Self::binary(self, BinaryOp::And, rhs)
+  }
+
+  /// Combine with another expression using `||`
+  pub fn or(self, rhs: PpExpr) -> Self {
+    Self::binary(self, BinaryOp::Or, rhs)
+  }
+
+  /// Negate the expression
+  #[allow(clippy::should_implement_trait)]
+  pub fn not(self) -> Self {
+    PpExpr::Not(Box::new(self))
+  }
+
+  fn fmt_operand(
+    &self,
+    f: &mut fmt::Formatter<'_>,
+    parent: BinaryOp,
+    is_right: bool,
+  ) -> fmt::Result {
+    let needs_parens = match self {
+      PpExpr::Binary(_, op, _) => {
+        parent.operand_needs_parens(*op, is_right)
+          // Spell out non-trivial operands of && and || for readability
+          || (matches!(parent, BinaryOp::And | BinaryOp::Or) && *op != parent)
+      }
+      _ => false,
+    };
+
+    if needs_parens {
+      write!(f, "({})", self)
+    } else {
+      write!(f, "{}", self)
+    }
+  }
+}
+
+impl fmt::Display for PpExpr {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      PpExpr::Defined(name) => write!(f, "defined({})", name),
+      PpExpr::Ident(name) => write!(f, "{}", name),
+      PpExpr::Int(value) => write!(f, "{}", value),
+      PpExpr::Not(inner) => match **inner {
+        PpExpr::Binary(..) => write!(f, "!({})", inner),
+        _ => write!(f, "!{}", inner),
+      },
+      PpExpr::Binary(lhs, op, rhs) => {
+        lhs.fmt_operand(f, *op, false)?;
+        write!(f, " {} ", op.as_str())?;
+        rhs.fmt_operand(f, *op, true)
+      }
+    }
+  }
+}
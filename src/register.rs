@@ -0,0 +1,367 @@
+use std::collections::HashSet;
+use std::io::Write;
+
+use crate::code_writer::CodeWriter;
+use crate::compilation_unit::{FunctionDef, StorageClass};
+use crate::doc_comment::DocComment;
+use crate::error::{CodeGenError, Result};
+use crate::expr::{BinaryOp, Expr, UnaryOp};
+use crate::naming::{Case, NamingConvention};
+use crate::numeric::{int_literal, CType, NumberFormat};
+use crate::standard::LanguageStandard;
+use crate::user_section::UserSectionManager;
+
+/// Access permissions of a register or bit-field
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegisterAccess {
+  ReadOnly,
+  WriteOnly,
+  ReadWrite,
+  WriteOnce,
+  ReadWriteOnce,
+}
+
+impl RegisterAccess {
+  /// Look up an access by its SVD spelling, e.g. `read-only`
+  pub fn from_name(name: &str) -> Option<Self> {
+    match name {
+      "read-only" => Some(RegisterAccess::ReadOnly),
+      "write-only" => Some(RegisterAccess::WriteOnly),
+      "read-write" => Some(RegisterAccess::ReadWrite),
+      "writeOnce" => Some(RegisterAccess::WriteOnce),
+      "read-writeOnce" => Some(RegisterAccess::ReadWriteOnce),
+      _ => None,
+    }
+  }
+
+  /// Check whether the value can be read
+  pub fn is_readable(&self) -> bool {
+    !matches!(self, RegisterAccess::WriteOnly | RegisterAccess::WriteOnce)
+  }
+
+  /// Check whether the value can be written
+  pub fn is_writable(&self) -> bool {
+    *self != RegisterAccess::ReadOnly
+  }
+}
+
+/// A bit-field of a register
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BitField {
+  /// The field name
+  pub name: String,
+  /// Position of the least significant bit
+  pub offset: u32,
+  /// Width in bits
+  pub width: u32,
+  /// Access permissions
+  pub access: RegisterAccess,
+  /// Optional documentation comment
+  pub doc: Option<String>,
+}
+
+impl BitField {
+  /// Create a new read-write field
+  pub fn new(name: &str, offset: u32, width: u32) -> Self {
+    Self {
+      name: name.to_string(),
+      offset,
+      width,
+      access: RegisterAccess::ReadWrite,
+      doc: None,
+    }
+  }
+
+  /// Set the access permissions, returning the field for chaining
+  pub fn with_access(mut self, access: RegisterAccess) -> Self {
+    self.access = access;
+    self
+  }
+
+  /// Set the documentation comment, returning the field for chaining
+  pub fn with_doc(mut self, doc: &str) -> Self {
+    self.doc = Some(doc.to_string());
+    self
+  }
+
+  /// Get the mask of the field value before shifting, e.g. `0x3` for two bits
+  pub fn value_mask(&self) -> u64 {
+    u64::MAX >> (64 - self.width.clamp(1, 64))
+  }
+}
+
+/// Options for writing register accessors
+#[derive(Debug, Clone)]
+pub struct AccessorOptions {
+  /// Naming of the `_Pos`/`_Msk` macros, applied to `register_field`
+  pub macro_naming: NamingConvention,
+  /// Naming of the accessor functions, applied to `register_field_get` etc.
+  pub function_naming: NamingConvention,
+  /// Whether `static inline` get/set/modify functions are written
+  pub functions: bool,
+}
+
+impl Default for AccessorOptions {
+  fn default() -> Self {
+    Self {
+      macro_naming: NamingConvention::new(Case::ScreamingSnake, LanguageStandard::C99),
+      function_naming: NamingConvention::new(Case::Snake, LanguageStandard::C99),
+      functions: true,
+    }
+  }
+}
+
+/// A register of 8, 16, 32 or 64 bits made of bit-fields
+///
+/// Accessors shift and mask register values instead of using C bit-fields,
+/// so they behave the same regardless of the compiler's bit-field order or
+/// the target's endianness.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RegisterDef {
+  /// The register name
+  pub name: String,
+  /// Width in bits
+  pub width: u32,
+  /// Access permissions of the register as a whole
+  pub access: RegisterAccess,
+  /// The bit-fields
+  pub fields: Vec<BitField>,
+}
+
+impl RegisterDef {
+  /// Create a new read-write register
+  pub fn new(name: &str, width: u32) -> Self {
+    Self {
+      name: name.to_string(),
+      width,
+      access: RegisterAccess::ReadWrite,
+      fields: Vec::new(),
+    }
+  }
+
+  /// Set the access permissions, returning the register for chaining
+  pub fn with_access(mut self, access: RegisterAccess) -> Self {
+    self.access = access;
+    self
+  }
+
+  /// Add a field, returning the register for chaining
+  pub fn with_field(mut self, field: BitField) -> Self {
+    self.fields.push(field);
+    self
+  }
+
+  /// Get the C type holding the register value
+  pub fn value_type(&self) -> CType {
+    match self.width {
+      8 => CType::UInt8,
+      16 => CType::UInt16,
+      32 => CType::UInt32,
+      _ => CType::UInt64,
+    }
+  }
+
+  /// Get the C type of mask literals, `unsigned long` up to 32 bits as in the CMSIS headers
+  fn mask_type(&self) -> CType {
+    if self.width <= 32 {
+      CType::ULong
+    } else {
+      CType::UInt64
+    }
+  }
+
+  /// Check the width and that fields are uniquely named, fit and do not overlap
+  pub fn validate(&self) -> Result<()> {
+    let invalid = |message: String| {
+      CodeGenError::InvalidDefinition(format!("register '{}': {}", self.name, message))
+    };
+    if ![8, 16, 32, 64].contains(&self.width) {
+      return Err(invalid(format!("unsupported width {}", self.width)));
+    }
+    let mut names = HashSet::new();
+    let mut used = 0u64;
+    for field in &self.fields {
+      if !names.insert(field.name.as_str()) {
+        return Err(invalid(format!("field '{}' is defined twice", field.name)));
+      }
+      if field.width == 0 || field.offset + field.width > self.width {
+        return Err(invalid(format!(
+          "field '{}' (bits {}..{}) does not fit in {} bits",
+          field.name,
+          field.offset,
+          field.offset + field.width,
+          self.width
+        )));
+      }
+      let bits = field.value_mask() << field.offset;
+      if used & bits != 0 {
+        return Err(invalid(format!(
+          "field '{}' overlaps another field",
+          field.name
+        )));
+      }
+      used |= bits;
+    }
+    Ok(())
+  }
+
+  /// Get the (position, mask) macro names of a field
+  fn macro_names(&self, field: &BitField, naming: &NamingConvention) -> (String, String) {
+    let base = naming.apply(&format!("{}_{}", self.name, field.name));
+    (format!("{}_Pos", base), format!("{}_Msk", base))
+  }
+
+  /// Write the `_Pos` and `_Msk` macros of every field
+  pub fn write_macros<W: Write>(
+    &self,
+    writer: &mut CodeWriter<W>,
+    naming: &NamingConvention,
+  ) -> Result<()> {
+    self.validate()?;
+    for field in &self.fields {
+      let (position, mask) = self.macro_names(field, naming);
+      if let Some(ref doc) = field.doc {
+        writer.write_doc_comment(&DocComment::new(doc))?;
+      }
+      writer.write_int_define(
+        &position,
+        field.offset as i128,
+        CType::UInt32,
+        NumberFormat::Decimal,
+      )?;
+      let literal = int_literal(
+        field.value_mask() as i128,
+        self.mask_type(),
+        NumberFormat::Hex { width: 0 },
+      )?;
+      let value = Expr::literal(&literal).op(BinaryOp::Shl, Expr::ident(&position));
+      writer.write_define_expr(&mask, &value)?;
+    }
+    Ok(())
+  }
+
+  /// Get the accessor functions of every field
+  ///
+  /// `get` extracts a field from a register value and exists for readable
+  /// fields of a readable register; `set` returns a register value with the
+  /// field replaced and exists for writable fields of a writable register.
+  /// `modify` performs a read-modify-write through a pointer and exists only
+  /// where both do. It is left out for every field
+  /// when any field is write-only, as reading back its bits is undefined.
+  pub fn accessors(&self, options: &AccessorOptions) -> Vec<FunctionDef> {
+    let value_type = self.value_type().name();
+    let mut functions = Vec::new();
+    let has_write_only_fields = self.fields.iter().any(|f| !f.access.is_readable());
+    for field in &self.fields {
+      let (position, mask) = self.macro_names(field, &options.macro_naming);
+      let name = |operation: &str| {
+        options
+          .function_naming
+          .apply(&format!("{}_{}_{}", self.name, field.name, operation))
+      };
+      // (value << POS) & MSK
+      let inserted = Expr::ident("value")
+        .op(BinaryOp::Shl, Expr::ident(&position))
+        .op(BinaryOp::BitAnd, Expr::ident(&mask));
+
+      let readable = self.access.is_readable() && field.access.is_readable();
+      let writable = self.access.is_writable() && field.access.is_writable();
+      if readable {
+        let extracted = Expr::ident("reg")
+          .op(BinaryOp::BitAnd, Expr::ident(&mask))
+          .op(BinaryOp::Shr, Expr::ident(&position))
+          .cast(value_type);
+        let body = format!("return {};", extracted);
+        functions.push(
+          FunctionDef::new(value_type, &name("get"), &[(value_type, "reg")])
+            .with_storage(StorageClass::StaticInline)
+            .with_body(&[&body])
+            .with_doc(
+              DocComment::new(&format!(
+                "Get the {} field of a {} value",
+                field.name, self.name
+              ))
+              .with_param("reg", "Register value")
+              .with_returns("Field value"),
+            ),
+        );
+      }
+
+      if writable {
+        let replaced = Expr::ident("reg")
+          .op(
+            BinaryOp::BitAnd,
+            Expr::unary(UnaryOp::BitNot, Expr::ident(&mask)),
+          )
+          .op(BinaryOp::BitOr, inserted)
+          .cast(value_type);
+        let body = format!("return {};", replaced);
+        functions.push(
+          FunctionDef::new(
+            value_type,
+            &name("set"),
+            &[(value_type, "reg"), (value_type, "value")],
+          )
+          .with_storage(StorageClass::StaticInline)
+          .with_body(&[&body])
+          .with_doc(
+            DocComment::new(&format!(
+              "Replace the {} field of a {} value",
+              field.name, self.name
+            ))
+            .with_param("reg", "Register value")
+            .with_param("value", "New field value")
+            .with_returns("Updated register value"),
+          ),
+        );
+      }
+
+      if readable && writable && !has_write_only_fields {
+        let body = format!("*reg = {}(*reg, value);", name("set"));
+        let pointer = format!("volatile {} *", value_type);
+        functions.push(
+          FunctionDef::new(
+            "void",
+            &name("modify"),
+            &[(pointer.as_str(), "reg"), (value_type, "value")],
+          )
+          .with_storage(StorageClass::StaticInline)
+          .with_body(&[&body])
+          .with_doc(
+            DocComment::new(&format!(
+              "Update the {} field of the {} register in place",
+              field.name, self.name
+            ))
+            .with_param("reg", "Register address")
+            .with_param("value", "New field value"),
+          ),
+        );
+      }
+    }
+    functions
+  }
+
+  /// Write the field macros and, if enabled, the accessor functions
+  pub fn write_accessors<W: Write>(
+    &self,
+    writer: &mut CodeWriter<W>,
+    options: &AccessorOptions,
+  ) -> Result<()> {
+    if writer.has_reserved_includes() {
+      writer.require_include("stdint.h", true);
+    }
+    self.write_macros(writer, &options.macro_naming)?;
+    if !options.functions {
+      return Ok(());
+    }
+    let user_sections = UserSectionManager::new();
+    for function in self.accessors(options) {
+      writer.newline()?;
+      if let Some(ref doc) = function.doc {
+        writer.write_doc_comment(doc)?;
+      }
+      function.write_definition(writer, &user_sections)?;
+    }
+    Ok(())
+  }
+}
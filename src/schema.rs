@@ -0,0 +1,567 @@
+use anyhow::Context as AnyhowContext;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+use crate::code_writer::CodeWriter;
+use crate::compilation_unit::FunctionDef;
+use crate::doc_comment::DocComment;
+use crate::error::{CodeGenError, Result};
+use crate::header::{HeaderScaffold, IncludeGuard};
+use crate::naming::identifier_problem;
+use crate::numeric::{int_literal, CType, NumberFormat};
+use crate::types::{FieldDef, StructDef, TypeGraph, TypeRef};
+use crate::user_section::UserSectionManager;
+
+/// Header-wide settings of a schema
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HeaderSpec {
+  /// Include guard macro; derived from the file name if absent
+  #[serde(default)]
+  pub guard: Option<String>,
+  /// Whether declarations are wrapped in `extern "C"`
+  #[serde(default = "default_true")]
+  pub extern_c: bool,
+  /// Included headers; `<name>` is a system include, anything else a local one
+  #[serde(default)]
+  pub includes: Vec<String>,
+}
+
+impl Default for HeaderSpec {
+  fn default() -> Self {
+    Self {
+      guard: None,
+      extern_c: true,
+      includes: Vec::new(),
+    }
+  }
+}
+
+fn default_true() -> bool {
+  true
+}
+
+fn default_void() -> String {
+  "void".to_string()
+}
+
+/// The value of a constant: an integer or a verbatim expression
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(untagged)]
+pub enum ConstantValue {
+  Int(i64),
+  Expr(String),
+}
+
+/// A `#define` constant
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ConstantSpec {
+  pub name: String,
+  pub value: ConstantValue,
+  /// Integer type the value is checked against and suffixed for, e.g. `uint32_t`
+  #[serde(default, rename = "type")]
+  pub ty: Option<String>,
+  #[serde(default)]
+  pub doc: Option<String>,
+}
+
+/// An enumerator
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EnumValueSpec {
+  pub name: String,
+  #[serde(default)]
+  pub value: Option<i64>,
+  #[serde(default)]
+  pub doc: Option<String>,
+}
+
+/// An enum
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EnumSpec {
+  pub name: String,
+  pub values: Vec<EnumValueSpec>,
+  /// Also write `typedef enum name name;`
+  #[serde(default)]
+  pub typedef: bool,
+  #[serde(default)]
+  pub doc: Option<String>,
+}
+
+/// An array length: a number or a constant name
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(untagged)]
+pub enum ArrayLen {
+  Count(u64),
+  Expr(String),
+}
+
+/// A struct or union member
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FieldSpec {
+  pub name: String,
+  /// The C type, e.g. `const char *`
+  #[serde(rename = "type")]
+  pub ty: String,
+  #[serde(default)]
+  pub array: Option<ArrayLen>,
+  #[serde(default)]
+  pub bits: Option<u32>,
+  #[serde(default)]
+  pub doc: Option<String>,
+}
+
+/// A struct or union
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StructSpec {
+  pub name: String,
+  pub fields: Vec<FieldSpec>,
+  #[serde(default)]
+  pub union: bool,
+  /// Also write `typedef struct name name;`
+  #[serde(default)]
+  pub typedef: bool,
+  #[serde(default)]
+  pub doc: Option<String>,
+}
+
+/// A function parameter
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ParamSpec {
+  pub name: String,
+  #[serde(rename = "type")]
+  pub ty: String,
+  #[serde(default)]
+  pub doc: Option<String>,
+}
+
+/// A function prototype
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FunctionSpec {
+  pub name: String,
+  #[serde(default = "default_void")]
+  pub returns: String,
+  #[serde(default)]
+  pub params: Vec<ParamSpec>,
+  #[serde(default)]
+  pub doc: Option<String>,
+  /// Description of the return value
+  #[serde(default)]
+  pub returns_doc: Option<String>,
+}
+
+/// A header described as data, loaded from TOML or JSON
+///
+/// Declarations are written in a fixed order: includes, constants, enums,
+/// structs (in dependency order) and function prototypes.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Schema {
+  #[serde(default)]
+  pub header: HeaderSpec,
+  #[serde(default)]
+  pub constants: Vec<ConstantSpec>,
+  #[serde(default)]
+  pub enums: Vec<EnumSpec>,
+  #[serde(default)]
+  pub structs: Vec<StructSpec>,
+  #[serde(default)]
+  pub functions: Vec<FunctionSpec>,
+}
+
+fn schema_error(location: &str, message: impl Into<String>) -> CodeGenError {
+  CodeGenError::Schema {
+    location: location.to_string(),
+    message: message.into(),
+  }
+}
+
+/// Check that `name` is usable as a C identifier, and as a C++ one if `cpp` is set
+fn check_identifier(location: &str, name: &str, cpp: bool) -> Result<()> {
+  match identifier_problem(name, cpp) {
+    Some(problem) => Err(schema_error(location, problem)),
+    None => Ok(()),
+  }
+}
+
+fn parse_type(location: &str, spelling: &str) -> Result<TypeRef> {
+  TypeRef::parse(spelling)
+    .map_err(|_| schema_error(location, format!("invalid type '{}'", spelling)))
+}
+
+impl Schema {
+  /// Parse a TOML description
+  pub fn from_toml_str(text: &str) -> Result<Self> {
+    let schema: Self = toml::from_str(text).map_err(|e| {
+      let location = match e.span() {
+        Some(span) => line_column(text, span.start),
+        None => "TOML".to_string(),
+      };
+      schema_error(&location, e.message())
+    })?;
+    schema.validate()?;
+    Ok(schema)
+  }
+
+  /// Parse a JSON description
+  pub fn from_json_str(text: &str) -> Result<Self> {
+    let schema: Self = serde_json::from_str(text).map_err(|e| {
+      let message = e.to_string();
+      // serde_json appends " at line L column C"; keep it as the location instead
+      let message = match message.rfind(" at line ") {
+        Some(pos) => message[..pos].to_string(),
+        None => message,
+      };
+      schema_error(
+        &format!("line {}, column {}", e.line(), e.column()),
+        message,
+      )
+    })?;
+    schema.validate()?;
+    Ok(schema)
+  }
+
+  /// Load a description, choosing the format from the `.toml` or `.json` extension
+  pub fn load(path: &Path) -> Result<Self> {
+    let text = fs::read_to_string(path)
+      .with_context(|| format!("Failed to read file: {}", path.display()))?;
+    let parsed = match path.extension().and_then(|e| e.to_str()) {
+      Some("toml") => Self::from_toml_str(&text),
+      Some("json") => Self::from_json_str(&text),
+      _ => {
+        return Err(schema_error(
+          &path.display().to_string(),
+          "expected a .toml or .json file",
+        ))
+      }
+    };
+    parsed.map_err(|e| match e {
+      CodeGenError::Schema { location, message } => CodeGenError::Schema {
+        location: format!("{}, {}", path.display(), location),
+        message,
+      },
+      other => other,
+    })
+  }
+
+  /// Check names, types and values, reporting the first violation with its location
+  pub fn validate(&self) -> Result<()> {
+    // Macros, enumerators, typedefs and functions share one namespace, tags
+    // another. An `extern "C"` header is also compiled as C++.
+    let cpp = self.header.extern_c;
+    let mut ordinary = Names::new(cpp);
+    let mut tags = Names::new(cpp);
+
+    if let Some(ref guard) = self.header.guard {
+      check_identifier("header.guard", guard, cpp)?;
+    }
+
+    for (i, constant) in self.constants.iter().enumerate() {
+      let location = format!("constants[{}]", i);
+      ordinary.declare(&location, &constant.name)?;
+      match (&constant.value, &constant.ty) {
+        (ConstantValue::Int(value), Some(ty)) => {
+          let ty = integer_type(&format!("{}.type", location), ty)?;
+          int_literal(*value as i128, ty, NumberFormat::Decimal)
+            .map_err(|e| schema_error(&format!("{}.value", location), e.to_string()))?;
+        }
+        (ConstantValue::Expr(_), Some(_)) => {
+          return Err(schema_error(
+            &format!("{}.type", location),
+            "a type can only be given for integer values",
+          ))
+        }
+        (ConstantValue::Expr(expr), None) if expr.trim().is_empty() => {
+          return Err(schema_error(
+            &format!("{}.value", location),
+            "expression is empty",
+          ))
+        }
+        _ => {}
+      }
+    }
+
+    for (i, spec) in self.enums.iter().enumerate() {
+      let location = format!("enums[{}]", i);
+      tags.declare(&location, &spec.name)?;
+      if spec.typedef {
+        ordinary.declare(&location, &spec.name)?;
+      }
+      if spec.values.is_empty() {
+        return Err(schema_error(
+          &format!("{}.values", location),
+          "an enum needs at least one value",
+        ));
+      }
+      for (j, value) in spec.values.iter().enumerate() {
+        let location = format!("{}.values[{}]", location, j);
+        ordinary.declare(&location, &value.name)?;
+        if let Some(v) = value.value {
+          if i32::try_from(v).is_err() {
+            return Err(schema_error(
+              &format!("{}.value", location),
+              format!("{} does not fit in an int", v),
+            ));
+          }
+        }
+      }
+    }
+
+    for (i, spec) in self.structs.iter().enumerate() {
+      let location = format!("structs[{}]", i);
+      tags.declare(&location, &spec.name)?;
+      if spec.typedef {
+        ordinary.declare(&location, &spec.name)?;
+      }
+      if spec.fields.is_empty() {
+        return Err(schema_error(
+          &format!("{}.fields", location),
+          "a struct needs at least one field",
+        ));
+      }
+      let mut members = Names::new(cpp);
+      for (j, field) in spec.fields.iter().enumerate() {
+        let location = format!("{}.fields[{}]", location, j);
+        members.declare(&location, &field.name)?;
+        parse_type(&format!("{}.type", location), &field.ty)?;
+        match (&field.array, field.bits) {
+          (Some(_), Some(_)) => {
+            return Err(schema_error(
+              &location,
+              "a field cannot be both an array and a bit-field",
+            ))
+          }
+          (Some(ArrayLen::Count(0)), None) => {
+            return Err(schema_error(
+              &format!("{}.array", location),
+              "array length must be positive",
+            ))
+          }
+          (None, Some(0)) => {
+            return Err(schema_error(
+              &format!("{}.bits", location),
+              "bit-field width must be positive",
+            ))
+          }
+          _ => {}
+        }
+      }
+    }
+
+    for (i, function) in self.functions.iter().enumerate() {
+      let location = format!("functions[{}]", i);
+      ordinary.declare(&location, &function.name)?;
+      parse_type(&format!("{}.returns", location), &function.returns)?;
+      let mut params = Names::new(cpp);
+      for (j, param) in function.params.iter().enumerate() {
+        let location = format!("{}.params[{}]", location, j);
+        params.declare(&location, &param.name)?;
+        parse_type(&format!("{}.type", location), &param.ty)?;
+      }
+    }
+    Ok(())
+  }
+
+  /// Build the type graph of the described structs and unions
+  pub fn type_graph(&self) -> Result<TypeGraph> {
+    let mut graph = TypeGraph::new();
+    for (i, spec) in self.structs.iter().enumerate() {
+      let mut def = if spec.union {
+        StructDef::union(&spec.name)
+      } else {
+        StructDef::new(&spec.name)
+      };
+      def.typedef = spec.typedef;
+      def.doc = spec.doc.clone();
+      for (j, field) in spec.fields.iter().enumerate() {
+        let location = format!("structs[{}].fields[{}].type", i, j);
+        let mut member = FieldDef::new(&field.name, parse_type(&location, &field.ty)?);
+        member.array_len = field.array.as_ref().map(|len| match len {
+          ArrayLen::Count(n) => n.to_string(),
+          ArrayLen::Expr(expr) => expr.clone(),
+        });
+        member.bit_width = field.bits;
+        member.doc = field.doc.clone();
+        def.add_field(member);
+      }
+      graph.add_struct(def)?;
+    }
+    Ok(graph)
+  }
+
+  /// Build the described function prototypes
+  pub fn functions(&self) -> Vec<FunctionDef> {
+    self
+      .functions
+      .iter()
+      .map(|spec| {
+        let params: Vec<(&str, &str)> = spec
+          .params
+          .iter()
+          .map(|p| (p.ty.as_str(), p.name.as_str()))
+          .collect();
+        let mut function = FunctionDef::new(&spec.returns, &spec.name, &params);
+        let documented = spec.params.iter().any(|p| p.doc.is_some()) || spec.returns_doc.is_some();
+        if spec.doc.is_some() || documented {
+          let mut doc = DocComment::new(spec.doc.as_deref().unwrap_or(""));
+          for param in &spec.params {
+            if let Some(ref text) = param.doc {
+              doc = doc.with_param(&param.name, text);
+            }
+          }
+          if let Some(ref text) = spec.returns_doc {
+            doc = doc.with_returns(text);
+          }
+          function = function.with_doc(doc);
+        }
+        function
+      })
+      .collect()
+  }
+
+  /// Get the header scaffold, with the guard derived from `header_path` unless one is given
+  pub fn scaffold(&self, header_path: &Path) -> Result<HeaderScaffold> {
+    let mut scaffold = match self.header.guard {
+      Some(ref guard) => HeaderScaffold::new(IncludeGuard::Define(guard.clone())),
+      None => HeaderScaffold::for_file(header_path)?,
+    };
+    scaffold.set_extern_c(self.header.extern_c);
+    Ok(scaffold)
+  }
+
+  /// Write the described declarations
+  pub fn write_declarations<W: Write>(&self, writer: &mut CodeWriter<W>) -> Result<()> {
+    for include in &self.header.includes {
+      match include.strip_prefix('<').and_then(|i| i.strip_suffix('>')) {
+        Some(header) => writer.write_include(header, true)?,
+        None => writer.write_include(include, false)?,
+      }
+    }
+
+    for constant in &self.constants {
+      if let Some(ref doc) = constant.doc {
+        writer.write_doc_comment(&DocComment::new(doc))?;
+      }
+      match (&constant.value, &constant.ty) {
+        (ConstantValue::Int(value), Some(ty)) => {
+          let ty = integer_type(&constant.name, ty)?;
+          writer.write_int_define(&constant.name, *value as i128, ty, NumberFormat::Decimal)?
+        }
+        (ConstantValue::Int(value), None) if *value < 0 => {
+          writer.write_define(&constant.name, Some(&format!("({})", value)))?
+        }
+        (ConstantValue::Int(value), None) => {
+          writer.write_define(&constant.name, Some(&value.to_string()))?
+        }
+        (ConstantValue::Expr(expr), _) => writer.write_define(&constant.name, Some(expr))?,
+      }
+    }
+    if !self.constants.is_empty() {
+      writer.newline()?;
+    }
+
+    for spec in &self.enums {
+      if let Some(ref doc) = spec.doc {
+        writer.write_doc_comment(&DocComment::new(doc))?;
+      }
+      writer.begin_enum(&spec.name)?;
+      for value in &spec.values {
+        let literal = value.value.map(|v| v.to_string());
+        match value.doc {
+          Some(ref doc) => {
+            writer.write_documented_enum_member(&value.name, literal.as_deref(), doc)?
+          }
+          None => writer.write_enum_member(&value.name, literal.as_deref())?,
+        }
+      }
+      writer.end_enum()?;
+      if spec.typedef {
+        writer.writeln(&format!("typedef enum {} {};", spec.name, spec.name))?;
+      }
+      writer.newline()?;
+    }
+
+    if !self.structs.is_empty() {
+      self.type_graph()?.write(writer)?;
+      writer.newline()?;
+    }
+
+    for (i, function) in self.functions().iter().enumerate() {
+      if i > 0 {
+        writer.newline()?;
+      }
+      if let Some(ref doc) = function.doc {
+        writer.write_doc_comment(doc)?;
+      }
+      function.write_declaration(writer)?;
+    }
+    Ok(())
+  }
+
+  /// Generate the header, preserving the user sections of an existing file
+  pub fn generate(&self, header_path: &Path) -> Result<()> {
+    let mut user_sections = UserSectionManager::new();
+    self
+      .scaffold(header_path)?
+      .generate(header_path, &mut user_sections, |w| {
+        self.write_declarations(w)
+      })
+  }
+}
+
+/// Names declared so far in one namespace, with the location of their declaration
+struct Names {
+  seen: HashMap<String, String>,
+  /// Whether the names must also be valid in C++
+  cpp: bool,
+}
+
+impl Names {
+  fn new(cpp: bool) -> Self {
+    Self {
+      seen: HashMap::new(),
+      cpp,
+    }
+  }
+
+  fn declare(&mut self, location: &str, name: &str) -> Result<()> {
+    let name_location = format!("{}.name", location);
+    check_identifier(&name_location, name, self.cpp)?;
+    if let Some(first) = self.seen.get(name) {
+      return Err(schema_error(
+        &name_location,
+        format!("'{}' is already defined at {}", name, first),
+      ));
+    }
+    self.seen.insert(name.to_string(), location.to_string());
+    Ok(())
+  }
+}
+
+/// Look up an integer type by name
+fn integer_type(location: &str, name: &str) -> Result<CType> {
+  match CType::from_name(name) {
+    Some(ty) if !ty.is_float() => Ok(ty),
+    _ => Err(schema_error(
+      location,
+      format!("'{}' is not a supported integer type", name),
+    )),
+  }
+}
+
+/// Convert a byte offset into a `line L, column C` location
+fn line_column(text: &str, offset: usize) -> String {
+  let before = &text[..offset.min(text.len())];
+  let line = before.matches('\n').count() + 1;
+  let column = before.len() - before.rfind('\n').map_or(0, |p| p + 1) + 1;
+  format!("line {}, column {}", line, column)
+}
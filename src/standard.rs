@@ -0,0 +1,242 @@
+/// A C or C++ language standard targeted by the generated code
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum LanguageStandard {
+  C89,
+  C99,
+  C11,
+  C17,
+  C23,
+  Cpp11,
+  Cpp14,
+  Cpp17,
+  Cpp20,
+}
+
+const C89_KEYWORDS: &[&str] = &[
+  "auto", "break", "case", "char", "const", "continue", "default", "do", "double", "else", "enum",
+  "extern", "float", "for", "goto", "if", "int", "long", "register", "return", "short", "signed",
+  "sizeof", "static", "struct", "switch", "typedef", "union", "unsigned", "void", "volatile",
+  "while",
+];
+
+const C99_KEYWORDS: &[&str] = &["inline", "restrict", "_Bool", "_Complex", "_Imaginary"];
+
+const C11_KEYWORDS: &[&str] = &[
+  "_Alignas",
+  "_Alignof",
+  "_Atomic",
+  "_Generic",
+  "_Noreturn",
+  "_Static_assert",
+  "_Thread_local",
+];
+
+const C23_KEYWORDS: &[&str] = &[
+  "alignas",
+  "alignof",
+  "bool",
+  "constexpr",
+  "false",
+  "nullptr",
+  "static_assert",
+  "thread_local",
+  "true",
+  "typeof",
+  "typeof_unqual",
+  "_BitInt",
+  "_Decimal128",
+  "_Decimal32",
+  "_Decimal64",
+];
+
+const CPP11_KEYWORDS: &[&str] = &[
+  "alignas",
+  "alignof",
+  "and",
+  "and_eq",
+  "asm",
+  "auto",
+  "bitand",
+  "bitor",
+  "bool",
+  "break",
+  "case",
+  "catch",
+  "char",
+  "char16_t",
+  "char32_t",
+  "class",
+  "compl",
+  "const",
+  "constexpr",
+  "const_cast",
+  "continue",
+  "decltype",
+  "default",
+  "delete",
+  "do",
+  "double",
+  "dynamic_cast",
+  "else",
+  "enum",
+  "explicit",
+  "export",
+  "extern",
+  "false",
+  "float",
+  "for",
+  "friend",
+  "goto",
+  "if",
+  "inline",
+  "int",
+  "long",
+  "mutable",
+  "namespace",
+  "new",
+  "noexcept",
+  "not",
+  "not_eq",
+  "nullptr",
+  "operator",
+  "or",
+  "or_eq",
+  "private",
+  "protected",
+  "public",
+  "register",
+  "reinterpret_cast",
+  "return",
+  "short",
+  "signed",
+  "sizeof",
+  "static",
+  "static_assert",
+  "static_cast",
+  "struct",
+  "switch",
+  "template",
+  "this",
+  "thread_local",
+  "throw",
+  "true",
+  "try",
+  "typedef",
+  "typeid",
+  "typename",
+  "union",
+  "unsigned",
+  "using",
+  "virtual",
+  "void",
+  "volatile",
+  "wchar_t",
+  "while",
+  "xor",
+  "xor_eq",
+];
+
+const CPP20_KEYWORDS: &[&str] = &[
+  "char8_t",
+  "concept",
+  "consteval",
+  "constinit",
+  "co_await",
+  "co_return",
+  "co_yield",
+  "requires",
+];
+
+impl LanguageStandard {
+  /// Check whether the standard is a C++ standard
+  pub fn is_cpp(&self) -> bool {
+    *self >= LanguageStandard::Cpp11
+  }
+
+  /// Get a short display name such as `C11` or `C++17`
+  pub fn name(&self) -> &'static str {
+    match self {
+      LanguageStandard::C89 => "C89",
+      LanguageStandard::C99 => "C99",
+      LanguageStandard::C11 => "C11",
+      LanguageStandard::C17 => "C17",
+      LanguageStandard::C23 => "C23",
+      LanguageStandard::Cpp11 => "C++11",
+      LanguageStandard::Cpp14 => "C++14",
+      LanguageStandard::Cpp17 => "C++17",
+      LanguageStandard::Cpp20 => "C++20",
+    }
+  }
+
+  /// Check whether a word is a keyword of the standard
+  pub fn is_keyword(&self, word: &str) -> bool {
+    let lists: &[&[&str]] = match self {
+      LanguageStandard::C89 => &[C89_KEYWORDS],
+      LanguageStandard::C99 => &[C89_KEYWORDS, C99_KEYWORDS],
+      LanguageStandard::C11 | LanguageStandard::C17 => &[C89_KEYWORDS, C99_KEYWORDS, C11_KEYWORDS],
+      LanguageStandard::C23 => &[C89_KEYWORDS, C99_KEYWORDS, C11_KEYWORDS, C23_KEYWORDS],
+      LanguageStandard::Cpp11 | LanguageStandard::Cpp14 | LanguageStandard::Cpp17 => {
+        &[CPP11_KEYWORDS]
+      }
+      LanguageStandard::Cpp20 => &[CPP11_KEYWORDS, CPP20_KEYWORDS],
+    };
+    lists.iter().any(|list| list.contains(&word))
+  }
+
+  /// Check whether `//` comments are available
+  pub fn has_line_comments(&self) -> bool {
+    *self != LanguageStandard::C89
+  }
+
+  /// Check whether declarations may follow statements in a block
+  pub fn has_mixed_declarations(&self) -> bool {
+    *self != LanguageStandard::C89
+  }
+
+  /// Check whether the `inline` function specifier is available
+  pub fn has_inline(&self) -> bool {
+    *self != LanguageStandard::C89
+  }
+
+  /// Check whether member designators (`.f = v`) are available
+  pub fn has_designated_initializers(&self) -> bool {
+    match self {
+      LanguageStandard::C89 => false,
+      _ if self.is_cpp() => *self >= LanguageStandard::Cpp20,
+      _ => true,
+    }
+  }
+
+  /// Check whether array designators (`[i] = v`) are available, which C++ lacks
+  pub fn has_array_designators(&self) -> bool {
+    !self.is_cpp() && *self != LanguageStandard::C89
+  }
+
+  /// Check whether unnamed struct and union members are available
+  pub fn has_anonymous_unions(&self) -> bool {
+    *self >= LanguageStandard::C11
+  }
+
+  /// Check whether `namespace a::b` is available
+  pub fn has_nested_namespaces(&self) -> bool {
+    *self >= LanguageStandard::Cpp17
+  }
+
+  /// Get the boolean type, if there is one
+  pub fn bool_type(&self) -> Option<&'static str> {
+    match self {
+      LanguageStandard::C89 => None,
+      LanguageStandard::C99 | LanguageStandard::C11 | LanguageStandard::C17 => Some("_Bool"),
+      _ => Some("bool"),
+    }
+  }
+
+  /// Get the null pointer constant
+  pub fn null_pointer(&self) -> &'static str {
+    if self.is_cpp() || *self == LanguageStandard::C23 {
+      "nullptr"
+    } else {
+      "NULL"
+    }
+  }
+}
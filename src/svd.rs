@@ -0,0 +1,1042 @@
+use anyhow::Context as AnyhowContext;
+use roxmltree::{Document, Node};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+use crate::code_writer::CodeWriter;
+use crate::doc_comment::DocComment;
+use crate::error::{CodeGenError, Result};
+use crate::expr::Expr;
+use crate::header::HeaderScaffold;
+use crate::invariant::Invariant;
+use crate::naming::{identifier_problem, Case, NamingConvention};
+use crate::numeric::{CType, NumberFormat};
+use crate::register::{BitField, RegisterAccess, RegisterDef};
+use crate::standard::LanguageStandard;
+use crate::types::{FieldDef, StructDef, TypeRef};
+use crate::user_section::UserSectionManager;
+
+/// A bit-field of a register
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SvdField {
+  pub name: String,
+  pub description: Option<String>,
+  /// Position of the least significant bit
+  pub bit_offset: u32,
+  pub bit_width: u32,
+  pub access: RegisterAccess,
+}
+
+/// A register of a peripheral
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SvdRegister {
+  pub name: String,
+  pub description: Option<String>,
+  /// Byte offset from the peripheral base address
+  pub address_offset: u64,
+  /// Width in bits
+  pub size: u32,
+  pub access: RegisterAccess,
+  pub reset_value: Option<u64>,
+  /// Element count if the register is an array (`NAME[%s]`)
+  pub dim: Option<u32>,
+  /// Whether the register is another view (`alternateRegister`/`alternateGroup`) of the one at its offset
+  pub alternate: bool,
+  /// The bit-fields, in document order
+  pub fields: Vec<SvdField>,
+}
+
+impl SvdRegister {
+  /// Get the number of bytes the register (or register array) occupies
+  pub fn byte_len(&self) -> u64 {
+    (self.size / 8) as u64 * self.dim.unwrap_or(1) as u64
+  }
+
+  /// Get the C type holding the register value
+  fn value_type(&self) -> CType {
+    match self.size {
+      8 => CType::UInt8,
+      16 => CType::UInt16,
+      32 => CType::UInt32,
+      _ => CType::UInt64,
+    }
+  }
+
+  /// Get the register description named `{prefix}_{register}`, e.g. for accessor generation
+  ///
+  /// The prefix is the peripheral name, followed by the cluster type names
+  /// for registers in clusters (`DMA_CH`).
+  pub fn register_def(&self, prefix: &str) -> RegisterDef {
+    let mut def =
+      RegisterDef::new(&format!("{}_{}", prefix, self.name), self.size).with_access(self.access);
+    for field in &self.fields {
+      let mut bit_field =
+        BitField::new(&field.name, field.bit_offset, field.bit_width).with_access(field.access);
+      bit_field.doc = field.description.clone();
+      def = def.with_field(bit_field);
+    }
+    def
+  }
+}
+
+/// A group of registers, written as a nested register block
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SvdCluster {
+  pub name: String,
+  pub description: Option<String>,
+  /// Name of the block type within the peripheral, from `headerStructName` or the name without `%s`
+  pub struct_name: String,
+  /// Byte offset from the enclosing block
+  pub address_offset: u64,
+  /// Element count if the cluster is an array (`NAME[%s]`)
+  pub dim: Option<u32>,
+  /// Distance between array elements in bytes, which the block type is padded to
+  pub dim_increment: Option<u64>,
+  /// The registers, in document order
+  pub registers: Vec<SvdRegister>,
+  /// Nested clusters, in document order
+  pub clusters: Vec<SvdCluster>,
+}
+
+/// A peripheral instance
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SvdPeripheral {
+  pub name: String,
+  pub description: Option<String>,
+  pub group_name: Option<String>,
+  pub base_address: u64,
+  /// Peripheral whose registers are reused when this one has none
+  pub derived_from: Option<String>,
+  pub registers: Vec<SvdRegister>,
+  pub clusters: Vec<SvdCluster>,
+}
+
+impl SvdPeripheral {
+  /// Get the name of the generated register block type
+  pub fn type_name(&self) -> String {
+    format!("{}_Type", self.name)
+  }
+
+  /// Check whether the peripheral has a register block of its own
+  fn has_registers(&self) -> bool {
+    !self.registers.is_empty() || !self.clusters.is_empty()
+  }
+}
+
+/// A device described by a CMSIS-SVD file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SvdDevice {
+  pub name: String,
+  pub description: Option<String>,
+  pub peripherals: Vec<SvdPeripheral>,
+}
+
+/// How the bit-fields of registers are exposed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FieldStyle {
+  /// `_Pos`/`_Msk` macros for every field
+  MaskShift,
+  /// A union of the raw value (`reg`) and a bit-field struct (`bit`) per register
+  ///
+  /// Bit-field order is implementation-defined; the layout matches GCC, Clang,
+  /// IAR and ARMCC on little-endian targets.
+  Bitfields,
+}
+
+/// Options for generating a register map
+#[derive(Debug, Clone)]
+pub struct RegisterMapOptions {
+  pub field_style: FieldStyle,
+  /// Whether static assertions check every register offset and block size
+  pub layout_checks: bool,
+}
+
+impl Default for RegisterMapOptions {
+  fn default() -> Self {
+    Self {
+      field_style: FieldStyle::MaskShift,
+      layout_checks: true,
+    }
+  }
+}
+
+fn svd_error(location: &str, message: impl Into<String>) -> CodeGenError {
+  CodeGenError::Svd {
+    location: location.to_string(),
+    message: message.into(),
+  }
+}
+
+/// Get the `line L, column C` location of an element
+fn node_location(node: Node) -> String {
+  let pos = node.document().text_pos_at(node.range().start);
+  format!("line {}, column {}", pos.row, pos.col)
+}
+
+fn child<'a, 'input>(node: Node<'a, 'input>, name: &str) -> Option<Node<'a, 'input>> {
+  node.children().find(|c| c.has_tag_name(name))
+}
+
+fn child_text<'a>(node: Node<'a, '_>, name: &str) -> Option<&'a str> {
+  child(node, name).and_then(|c| c.text()).map(str::trim)
+}
+
+fn required_text<'a>(node: Node<'a, '_>, name: &str) -> Result<&'a str> {
+  child_text(node, name)
+    .filter(|text| !text.is_empty())
+    .ok_or_else(|| {
+      svd_error(
+        &node_location(node),
+        format!("<{}> is missing <{}>", node.tag_name().name(), name),
+      )
+    })
+}
+
+/// Get a description with its whitespace runs collapsed
+fn description(node: Node) -> Option<String> {
+  child_text(node, "description").map(|text| text.split_whitespace().collect::<Vec<_>>().join(" "))
+}
+
+/// Parse an SVD scaled integer: decimal, `0x` hex or `#` binary
+///
+/// A leading `+` is allowed, and a `k`, `M`, `G` or `T` suffix multiplies by
+/// a power of 1024. Don't-care bits (`x`) of binary values read as 0.
+fn parse_number(text: &str) -> Option<u64> {
+  let text = text.trim();
+  let text = text.strip_prefix('+').unwrap_or(text);
+  let (text, scale) = match text.char_indices().last() {
+    Some((end, suffix)) => match suffix.to_ascii_lowercase() {
+      'k' => (&text[..end], 1u64 << 10),
+      'm' => (&text[..end], 1 << 20),
+      'g' => (&text[..end], 1 << 30),
+      't' => (&text[..end], 1 << 40),
+      _ => (text, 1),
+    },
+    None => (text, 1),
+  };
+  let value = if let Some(hex) = text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+    u64::from_str_radix(hex, 16).ok()
+  } else if let Some(binary) = text.strip_prefix('#') {
+    u64::from_str_radix(&binary.replace(['x', 'X'], "0"), 2).ok()
+  } else {
+    text.parse().ok()
+  }?;
+  value.checked_mul(scale)
+}
+
+fn number(node: Node, name: &str) -> Result<Option<u64>> {
+  match child(node, name) {
+    None => Ok(None),
+    Some(element) => {
+      let text = element.text().unwrap_or("").trim();
+      parse_number(text).map(Some).ok_or_else(|| {
+        svd_error(
+          &node_location(element),
+          format!("<{}> is not a number: '{}'", name, text),
+        )
+      })
+    }
+  }
+}
+
+fn required_number(node: Node, name: &str) -> Result<u64> {
+  required_text(node, name)?;
+  Ok(number(node, name)?.unwrap_or_default())
+}
+
+fn narrow(node: Node, name: &str, value: u64) -> Result<u32> {
+  u32::try_from(value).map_err(|_| {
+    svd_error(
+      &node_location(node),
+      format!("<{}> is out of range: {}", name, value),
+    )
+  })
+}
+
+/// Register properties inherited from the device, peripheral and register level
+#[derive(Debug, Clone, Copy, Default)]
+struct Properties {
+  size: Option<u32>,
+  access: Option<RegisterAccess>,
+  reset_value: Option<u64>,
+}
+
+impl Properties {
+  /// Override the inherited properties with those given on `node`
+  fn inherit(&self, node: Node) -> Result<Self> {
+    let mut properties = *self;
+    if let Some(size) = number(node, "size")? {
+      properties.size = Some(narrow(node, "size", size)?);
+    }
+    if let Some(access) = access(node)? {
+      properties.access = Some(access);
+    }
+    if let Some(reset_value) = number(node, "resetValue")? {
+      properties.reset_value = Some(reset_value);
+    }
+    Ok(properties)
+  }
+}
+
+fn access(node: Node) -> Result<Option<RegisterAccess>> {
+  match child_text(node, "access") {
+    None => Ok(None),
+    Some(text) => RegisterAccess::from_name(text)
+      .map(Some)
+      .ok_or_else(|| svd_error(&node_location(node), format!("unknown access '{}'", text))),
+  }
+}
+
+/// Get the `dimIndex` substitutions of a `dim` element, `0..dim` by default
+fn dim_indices(node: Node, dim: u32) -> Result<Vec<String>> {
+  let indices: Vec<String> = match child_text(node, "dimIndex") {
+    None => (0..dim).map(|i| i.to_string()).collect(),
+    Some(text) => match text.split_once('-') {
+      Some((first, last)) if !text.contains(',') => {
+        match (first.trim().parse::<u32>(), last.trim().parse::<u32>()) {
+          (Ok(first), Ok(last)) if first <= last => (first..=last).map(|i| i.to_string()).collect(),
+          _ => {
+            return Err(svd_error(
+              &node_location(node),
+              format!("invalid <dimIndex> '{}'", text),
+            ))
+          }
+        }
+      }
+      _ => text.split(',').map(|i| i.trim().to_string()).collect(),
+    },
+  };
+  if indices.len() != dim as usize {
+    return Err(svd_error(
+      &node_location(node),
+      format!(
+        "<dimIndex> lists {} indices but <dim> is {}",
+        indices.len(),
+        dim
+      ),
+    ));
+  }
+  Ok(indices)
+}
+
+/// Get the offset and width of a field from `bitOffset`/`bitWidth`, `lsb`/`msb` or `bitRange`
+fn bit_range(node: Node) -> Result<(u32, u32)> {
+  let location = node_location(node);
+  if let Some(offset) = number(node, "bitOffset")? {
+    let width = number(node, "bitWidth")?.unwrap_or(1);
+    return Ok((
+      narrow(node, "bitOffset", offset)?,
+      narrow(node, "bitWidth", width)?,
+    ));
+  }
+  if let (Some(lsb), Some(msb)) = (number(node, "lsb")?, number(node, "msb")?) {
+    if msb < lsb {
+      return Err(svd_error(&location, "<msb> is below <lsb>"));
+    }
+    return Ok((
+      narrow(node, "lsb", lsb)?,
+      narrow(node, "msb", msb - lsb + 1)?,
+    ));
+  }
+  if let Some(range) = child_text(node, "bitRange") {
+    let invalid = || svd_error(&location, format!("invalid <bitRange> '{}'", range));
+    let (msb, lsb) = range
+      .strip_prefix('[')
+      .and_then(|r| r.strip_suffix(']'))
+      .and_then(|r| r.split_once(':'))
+      .ok_or_else(invalid)?;
+    let msb: u32 = msb.trim().parse().map_err(|_| invalid())?;
+    let lsb: u32 = lsb.trim().parse().map_err(|_| invalid())?;
+    if msb < lsb {
+      return Err(invalid());
+    }
+    return Ok((lsb, msb - lsb + 1));
+  }
+  Err(svd_error(&location, "<field> has no bit position"))
+}
+
+fn parse_field(node: Node, register_access: RegisterAccess) -> Result<SvdField> {
+  let (bit_offset, bit_width) = bit_range(node)?;
+  Ok(SvdField {
+    name: required_text(node, "name")?.to_string(),
+    description: description(node),
+    bit_offset,
+    bit_width,
+    access: access(node)?.unwrap_or(register_access),
+  })
+}
+
+/// Get the element count and address increment of a repeated element
+fn dimension(node: Node) -> Result<Option<(u32, u64)>> {
+  match number(node, "dim")? {
+    None => Ok(None),
+    Some(dim) => Ok(Some((
+      narrow(node, "dim", dim)?,
+      required_number(node, "dimIncrement")?,
+    ))),
+  }
+}
+
+/// Parse a register, expanding `dim` lists into one register per index
+fn parse_registers(node: Node, inherited: &Properties) -> Result<Vec<SvdRegister>> {
+  let properties = inherited.inherit(node)?;
+  let access = properties.access.unwrap_or(RegisterAccess::ReadWrite);
+  let mut fields = Vec::new();
+  if let Some(list) = child(node, "fields") {
+    for field in list.children().filter(|c| c.has_tag_name("field")) {
+      fields.push(parse_field(field, access)?);
+    }
+  }
+  let group = child_text(node, "alternateGroup");
+  let register = SvdRegister {
+    name: required_text(node, "name")?.to_string(),
+    description: description(node),
+    address_offset: required_number(node, "addressOffset")?,
+    size: properties.size.unwrap_or(32),
+    access,
+    reset_value: properties.reset_value,
+    dim: None,
+    alternate: group.is_some() || child(node, "alternateRegister").is_some(),
+    fields,
+  };
+
+  let mut registers = match dimension(node)? {
+    None => vec![register],
+    Some((dim, increment)) => {
+      if let Some(name) = register.name.strip_suffix("[%s]") {
+        if increment != (register.size / 8) as u64 {
+          return Err(svd_error(
+            &node_location(node),
+            "register arrays must be contiguous (<dimIncrement> equal to the register size)",
+          ));
+        }
+        vec![SvdRegister {
+          name: name.to_string(),
+          dim: Some(dim),
+          ..register
+        }]
+      } else if register.name.contains("%s") {
+        dim_indices(node, dim)?
+          .iter()
+          .enumerate()
+          .map(|(i, index)| SvdRegister {
+            name: register.name.replace("%s", index),
+            description: register
+              .description
+              .as_ref()
+              .map(|d| d.replace("%s", index)),
+            address_offset: register.address_offset + i as u64 * increment,
+            ..register.clone()
+          })
+          .collect()
+      } else {
+        return Err(svd_error(
+          &node_location(node),
+          format!(
+            "register '{}' has <dim> but no %s in its name",
+            register.name
+          ),
+        ));
+      }
+    }
+  };
+  // Registers of an alternate group are named NAME_GROUP, as in the CMSIS headers
+  if let Some(group) = group {
+    for register in &mut registers {
+      register.name = format!("{}_{}", register.name, group);
+    }
+  }
+  Ok(registers)
+}
+
+/// Parse the registers and clusters listed under `node`
+fn parse_block(node: Node, properties: &Properties) -> Result<(Vec<SvdRegister>, Vec<SvdCluster>)> {
+  let mut registers = Vec::new();
+  let mut clusters = Vec::new();
+  for element in node.children() {
+    if element.has_tag_name("register") {
+      registers.extend(parse_registers(element, properties)?);
+    } else if element.has_tag_name("cluster") {
+      clusters.extend(parse_clusters(element, properties)?);
+    }
+  }
+  Ok((registers, clusters))
+}
+
+/// Parse a cluster, expanding `dim` lists into one cluster per index
+fn parse_clusters(node: Node, inherited: &Properties) -> Result<Vec<SvdCluster>> {
+  let properties = inherited.inherit(node)?;
+  let (registers, clusters) = parse_block(node, &properties)?;
+  let name = required_text(node, "name")?;
+  let cluster = SvdCluster {
+    name: name.to_string(),
+    description: description(node),
+    struct_name: match child_text(node, "headerStructName") {
+      Some(struct_name) => struct_name.to_string(),
+      None => name.replace("[%s]", "").replace("%s", ""),
+    },
+    address_offset: required_number(node, "addressOffset")?,
+    dim: None,
+    dim_increment: None,
+    registers,
+    clusters,
+  };
+
+  let (dim, increment) = match dimension(node)? {
+    None => return Ok(vec![cluster]),
+    Some(dimension) => dimension,
+  };
+  if let Some(name) = cluster.name.strip_suffix("[%s]") {
+    return Ok(vec![SvdCluster {
+      name: name.to_string(),
+      dim: Some(dim),
+      dim_increment: Some(increment),
+      ..cluster
+    }]);
+  }
+  if !cluster.name.contains("%s") {
+    return Err(svd_error(
+      &node_location(node),
+      format!("cluster '{}' has <dim> but no %s in its name", cluster.name),
+    ));
+  }
+  Ok(
+    dim_indices(node, dim)?
+      .iter()
+      .enumerate()
+      .map(|(i, index)| SvdCluster {
+        name: cluster.name.replace("%s", index),
+        description: cluster.description.as_ref().map(|d| d.replace("%s", index)),
+        address_offset: cluster.address_offset + i as u64 * increment,
+        ..cluster.clone()
+      })
+      .collect(),
+  )
+}
+
+fn parse_peripheral(node: Node, inherited: &Properties) -> Result<SvdPeripheral> {
+  let properties = inherited.inherit(node)?;
+  let (registers, clusters) = match child(node, "registers") {
+    Some(list) => parse_block(list, &properties)?,
+    None => (Vec::new(), Vec::new()),
+  };
+  Ok(SvdPeripheral {
+    name: required_text(node, "name")?.to_string(),
+    description: description(node),
+    group_name: child_text(node, "groupName").map(String::from),
+    base_address: required_number(node, "baseAddress")?,
+    derived_from: node.attribute("derivedFrom").map(String::from),
+    registers,
+    clusters,
+  })
+}
+
+/// Check that `name` is usable as an identifier of the generated header, which is also compiled as C++
+fn check_identifier(location: &str, name: &str) -> Result<()> {
+  match identifier_problem(name, true) {
+    Some(problem) => Err(svd_error(location, problem)),
+    None => Ok(()),
+  }
+}
+
+/// Check the registers and clusters of one block, with `path` locating the block
+fn validate_block(path: &str, registers: &[SvdRegister], clusters: &[SvdCluster]) -> Result<()> {
+  let mut names = HashSet::new();
+  for register in registers {
+    let location = format!("{}.{}", path, register.name);
+    check_identifier(&location, &register.name)?;
+    if !names.insert(&register.name) {
+      return Err(svd_error(&location, "register is defined twice"));
+    }
+    if ![8, 16, 32, 64].contains(&register.size) {
+      return Err(svd_error(
+        &location,
+        format!("unsupported register size {}", register.size),
+      ));
+    }
+
+    let mut used = 0u64;
+    for field in &register.fields {
+      let location = format!("{}.{}", location, field.name);
+      check_identifier(&location, &field.name)?;
+      if field.bit_width == 0 || field.bit_offset + field.bit_width > register.size {
+        return Err(svd_error(
+          &location,
+          format!(
+            "bits {}..{} do not fit in a {}-bit register",
+            field.bit_offset,
+            field.bit_offset + field.bit_width,
+            register.size
+          ),
+        ));
+      }
+      let bits = (u64::MAX >> (64 - field.bit_width)) << field.bit_offset;
+      if used & bits != 0 {
+        return Err(svd_error(&location, "field overlaps another field"));
+      }
+      used |= bits;
+    }
+  }
+
+  for cluster in clusters {
+    let location = format!("{}.{}", path, cluster.name);
+    check_identifier(&location, &cluster.name)?;
+    check_identifier(&location, &cluster.struct_name)?;
+    if !names.insert(&cluster.name) {
+      return Err(svd_error(&location, "cluster is defined twice"));
+    }
+    if cluster.registers.is_empty() && cluster.clusters.is_empty() {
+      return Err(svd_error(&location, "cluster has no registers"));
+    }
+    validate_block(&location, &cluster.registers, &cluster.clusters)?;
+  }
+  Ok(())
+}
+
+impl SvdDevice {
+  /// Parse an SVD document
+  pub fn parse(text: &str) -> Result<Self> {
+    let document = Document::parse(text).map_err(|e| {
+      let pos = e.pos();
+      let message = e.to_string();
+      // roxmltree appends " at L:C"; keep it as the location instead
+      let message = match message.rfind(" at ") {
+        Some(end) => message[..end].to_string(),
+        None => message,
+      };
+      svd_error(&format!("line {}, column {}", pos.row, pos.col), message)
+    })?;
+    let root = document.root_element();
+    if !root.has_tag_name("device") {
+      return Err(svd_error(
+        &node_location(root),
+        format!("expected <device>, found <{}>", root.tag_name().name()),
+      ));
+    }
+
+    let properties = Properties::default().inherit(root)?;
+    let mut peripherals = Vec::new();
+    if let Some(list) = child(root, "peripherals") {
+      for peripheral in list.children().filter(|c| c.has_tag_name("peripheral")) {
+        peripherals.push(parse_peripheral(peripheral, &properties)?);
+      }
+    }
+    let device = Self {
+      name: required_text(root, "name")?.to_string(),
+      description: description(root),
+      peripherals,
+    };
+    device.validate()?;
+    Ok(device)
+  }
+
+  /// Load and parse an SVD file
+  pub fn load(path: &Path) -> Result<Self> {
+    let text = fs::read_to_string(path)
+      .with_context(|| format!("Failed to read file: {}", path.display()))?;
+    Self::parse(&text).map_err(|e| match e {
+      CodeGenError::Svd { location, message } => CodeGenError::Svd {
+        location: format!("{}, {}", path.display(), location),
+        message,
+      },
+      other => other,
+    })
+  }
+
+  /// Check names, sizes and bit positions, reporting the first violation as `PERIPHERAL.REGISTER.FIELD`
+  pub fn validate(&self) -> Result<()> {
+    let mut peripherals = HashMap::new();
+    for peripheral in &self.peripherals {
+      check_identifier(&peripheral.name, &peripheral.name)?;
+      if peripherals.insert(&peripheral.name, peripheral).is_some() {
+        return Err(svd_error(&peripheral.name, "peripheral is defined twice"));
+      }
+      validate_block(
+        &peripheral.name,
+        &peripheral.registers,
+        &peripheral.clusters,
+      )?;
+    }
+
+    for peripheral in &self.peripherals {
+      if let Some(ref source) = peripheral.derived_from {
+        if !peripherals.contains_key(source) {
+          return Err(svd_error(
+            &peripheral.name,
+            format!("derived from unknown peripheral '{}'", source),
+          ));
+        }
+      }
+    }
+    Ok(())
+  }
+
+  /// Look up a peripheral by name
+  pub fn peripheral(&self, name: &str) -> Option<&SvdPeripheral> {
+    self.peripherals.iter().find(|p| p.name == name)
+  }
+
+  /// Get the peripheral whose register block `peripheral` uses
+  ///
+  /// A derived peripheral without registers of its own shares the block of
+  /// the one it is derived from.
+  pub fn register_source<'a>(&'a self, peripheral: &'a SvdPeripheral) -> &'a SvdPeripheral {
+    let mut current = peripheral;
+    let mut steps = 0;
+    while !current.has_registers() && steps < self.peripherals.len() {
+      match current
+        .derived_from
+        .as_deref()
+        .and_then(|n| self.peripheral(n))
+      {
+        Some(source) => current = source,
+        None => break,
+      }
+      steps += 1;
+    }
+    current
+  }
+
+  /// Write the register block types, base addresses and peripheral pointers
+  pub fn write_register_map<W: Write>(
+    &self,
+    writer: &mut CodeWriter<W>,
+    options: &RegisterMapOptions,
+  ) -> Result<()> {
+    if writer.has_reserved_includes() {
+      writer.require_include("stdint.h", true);
+      if options.layout_checks {
+        writer.require_include("stddef.h", true);
+      }
+    }
+
+    for peripheral in self.peripherals.iter().filter(|p| p.has_registers()) {
+      let block = Block {
+        prefix: peripheral.name.clone(),
+        path: peripheral.name.clone(),
+        doc: match peripheral.description {
+          Some(ref description) => format!("{} ({})", description, peripheral.name),
+          None => format!("{} registers", peripheral.name),
+        },
+        registers: &peripheral.registers,
+        clusters: &peripheral.clusters,
+        padded_size: None,
+      };
+      write_register_block(writer, &block, options)?;
+      writer.newline()?;
+    }
+
+    for peripheral in &self.peripherals {
+      let ty = if peripheral.base_address > u32::MAX as u64 {
+        CType::UInt64
+      } else {
+        CType::ULong
+      };
+      writer.write_int_define(
+        &format!("{}_BASE", peripheral.name),
+        peripheral.base_address as i128,
+        ty,
+        NumberFormat::Hex { width: 8 },
+      )?;
+    }
+    writer.newline()?;
+    for peripheral in &self.peripherals {
+      let source = self.register_source(peripheral);
+      if !source.has_registers() {
+        continue;
+      }
+      let pointer = Expr::ident(&format!("{}_BASE", peripheral.name))
+        .cast(&format!("{} *", source.type_name()));
+      writer.write_define_expr(&peripheral.name, &pointer)?;
+    }
+    Ok(())
+  }
+
+  /// Get the header scaffold, with the guard derived from `header_path`
+  pub fn scaffold(&self, header_path: &Path) -> Result<HeaderScaffold> {
+    HeaderScaffold::for_file(header_path)
+  }
+
+  /// Generate the register map header, preserving the user sections of an existing file
+  pub fn generate(&self, header_path: &Path, options: &RegisterMapOptions) -> Result<()> {
+    let mut user_sections = UserSectionManager::new();
+    self
+      .scaffold(header_path)?
+      .generate(header_path, &mut user_sections, |w| {
+        self.write_register_map(w, options)
+      })
+  }
+}
+
+/// The registers and clusters of a peripheral or cluster, written as one struct
+struct Block<'a> {
+  /// Prefix of the generated names, e.g. `DMA_CH` for the `DMA_CH_Type` struct
+  prefix: String,
+  /// Location of the block in errors, e.g. `DMA.CH`
+  path: String,
+  doc: String,
+  registers: &'a [SvdRegister],
+  clusters: &'a [SvdCluster],
+  /// Size the struct is padded to, e.g. the `dimIncrement` of a cluster array
+  padded_size: Option<u64>,
+}
+
+/// A member of a register block struct
+struct BlockMember {
+  /// Byte offset in the block
+  offset: u64,
+  /// Number of bytes taken
+  len: u64,
+  /// A single member, or alternate views written as an anonymous union
+  fields: Vec<FieldDef>,
+  /// Whether the member holds alternate registers
+  alternate: bool,
+}
+
+/// Format a struct member with its offset comment and trailing documentation
+fn member_line<W: Write>(
+  writer: &CodeWriter<W>,
+  field: &FieldDef,
+  comment: Option<&str>,
+) -> Result<String> {
+  let mut line = field.declaration();
+  if let Some(comment) = comment {
+    line.push_str(&format!(" /* {} */", comment));
+  }
+  if let Some(ref doc) = field.doc {
+    line.push_str(&format!(" {}", writer.trailing_doc(doc)?));
+  }
+  Ok(line)
+}
+
+/// Write the types of one register block, its layout checks and field macros, returning its size
+///
+/// Cluster types are written first, once per type name. Alternate registers
+/// share an anonymous union with the register at their offset.
+fn write_register_block<W: Write>(
+  writer: &mut CodeWriter<W>,
+  block: &Block,
+  options: &RegisterMapOptions,
+) -> Result<u64> {
+  let type_name = format!("{}_Type", block.prefix);
+
+  let mut cluster_sizes: HashMap<&str, u64> = HashMap::new();
+  for cluster in block.clusters {
+    if cluster_sizes.contains_key(cluster.struct_name.as_str()) {
+      continue;
+    }
+    let nested = Block {
+      prefix: format!("{}_{}", block.prefix, cluster.struct_name),
+      path: format!("{}.{}", block.path, cluster.name),
+      doc: match cluster.description {
+        Some(ref description) => description.clone(),
+        None => format!("{} registers", cluster.struct_name),
+      },
+      registers: &cluster.registers,
+      clusters: &cluster.clusters,
+      padded_size: cluster.dim.and(cluster.dim_increment),
+    };
+    let size = write_register_block(writer, &nested, options)?;
+    writer.newline()?;
+    cluster_sizes.insert(&cluster.struct_name, size);
+  }
+
+  // Registers and clusters by offset, in document order at the same offset
+  let mut registers: Vec<&SvdRegister> = block.registers.iter().collect();
+  registers.sort_by_key(|r| r.address_offset);
+  let mut entries: Vec<(u64, Option<&SvdRegister>, Option<&SvdCluster>)> = registers
+    .iter()
+    .map(|r| (r.address_offset, Some(*r), None))
+    .chain(
+      block
+        .clusters
+        .iter()
+        .map(|c| (c.address_offset, None, Some(c))),
+    )
+    .collect();
+  entries.sort_by_key(|(offset, ..)| *offset);
+
+  let mut members: Vec<BlockMember> = Vec::new();
+  let mut invariants = Vec::new();
+  for (offset, register, cluster) in entries {
+    let (name, field, len, alternate) = match (register, cluster) {
+      (Some(register), _) => {
+        let value_type =
+          if options.field_style == FieldStyle::Bitfields && !register.fields.is_empty() {
+            let union_name = write_bitfield_union(writer, &block.prefix, register)?;
+            writer.newline()?;
+            union_name
+          } else {
+            register.value_type().name().to_string()
+          };
+        let mut ty = TypeRef::new(&value_type).with_volatile();
+        if register.access == RegisterAccess::ReadOnly {
+          ty = ty.with_const();
+        }
+        let mut field = FieldDef::new(&register.name, ty);
+        if let Some(dim) = register.dim {
+          field = field.with_array_len(&dim.to_string());
+        }
+        if let Some(ref description) = register.description {
+          field = field.with_doc(description);
+        }
+        (
+          &register.name,
+          field,
+          register.byte_len(),
+          register.alternate,
+        )
+      }
+      (None, Some(cluster)) => {
+        let cluster_type = format!("{}_{}_Type", block.prefix, cluster.struct_name);
+        let mut field = FieldDef::new(&cluster.name, TypeRef::new(&cluster_type));
+        if let Some(dim) = cluster.dim {
+          field = field.with_array_len(&dim.to_string());
+        }
+        if let Some(ref description) = cluster.description {
+          field = field.with_doc(description);
+        }
+        let len = cluster_sizes[cluster.struct_name.as_str()] * cluster.dim.unwrap_or(1) as u64;
+        (&cluster.name, field, len, false)
+      }
+      (None, None) => unreachable!(),
+    };
+
+    match members.last_mut() {
+      Some(last) if offset == last.offset && (alternate || last.alternate) => {
+        last.fields.push(field);
+        last.len = last.len.max(len);
+        last.alternate = true;
+      }
+      Some(last) if offset < last.offset + last.len => {
+        return Err(svd_error(
+          &format!("{}.{}", block.path, name),
+          format!(
+            "register at offset 0x{:X} overlaps the previous register",
+            offset
+          ),
+        ));
+      }
+      _ => members.push(BlockMember {
+        offset,
+        len,
+        fields: vec![field],
+        alternate,
+      }),
+    }
+    invariants.push(Invariant::offset_of(&type_name, name, offset as usize));
+  }
+
+  let mut size = members.last().map_or(0, |m| m.offset + m.len);
+  if let Some(padded_size) = block.padded_size {
+    if size > padded_size {
+      return Err(svd_error(
+        &block.path,
+        format!(
+          "cluster takes {} bytes but <dimIncrement> is {}",
+          size, padded_size
+        ),
+      ));
+    }
+    size = padded_size;
+  }
+  if members.iter().any(|m| m.fields.len() > 1) {
+    writer.check_standard("anonymous unions", |s| s.has_anonymous_unions())?;
+  }
+
+  writer.write_doc_comment(&DocComment::new(&block.doc))?;
+  writer.begin_struct(&type_name)?;
+  writer.indent();
+  let mut position = 0;
+  let mut reserved = 0;
+  let mut write_gap = |writer: &mut CodeWriter<W>, position: u64, end: u64| -> Result<()> {
+    if end > position {
+      let gap = FieldDef::new(&format!("RESERVED{}", reserved), TypeRef::new("uint8_t"))
+        .with_array_len(&(end - position).to_string());
+      reserved += 1;
+      let comment = format!("offset 0x{:03X}", position);
+      writer.writeln(&member_line(writer, &gap, Some(&comment))?)?;
+    }
+    Ok(())
+  };
+  for member in &members {
+    write_gap(writer, position, member.offset)?;
+    let comment = format!("offset 0x{:03X}", member.offset);
+    if let [field] = member.fields.as_slice() {
+      writer.writeln(&member_line(writer, field, Some(&comment))?)?;
+    } else {
+      writer.writeln(&format!("union {{ /* {} */", comment))?;
+      writer.indent();
+      for field in &member.fields {
+        writer.writeln(&member_line(writer, field, None)?)?;
+      }
+      writer.dedent();
+      writer.writeln("};")?;
+    }
+    position = member.offset + member.len;
+  }
+  write_gap(writer, position, size)?;
+  writer.dedent();
+  writer.end_struct()?;
+  writer.writeln(&format!("typedef struct {} {};", type_name, type_name))?;
+
+  if options.layout_checks {
+    invariants.push(Invariant::size_of(&type_name, size as usize));
+    writer.write_static_asserts(&invariants)?;
+  }
+
+  if options.field_style == FieldStyle::MaskShift {
+    for register in registers.iter().filter(|r| !r.fields.is_empty()) {
+      writer.newline()?;
+      // SVD names are kept as given, as in the vendor headers
+      register.register_def(&block.prefix).write_macros(
+        writer,
+        &NamingConvention::new(Case::Preserve, LanguageStandard::C99),
+      )?;
+    }
+  }
+  Ok(size)
+}
+
+/// Write the bit-field struct and value union of a register, returning the union's name
+fn write_bitfield_union<W: Write>(
+  writer: &mut CodeWriter<W>,
+  block_prefix: &str,
+  register: &SvdRegister,
+) -> Result<String> {
+  let prefix = format!("{}_{}", block_prefix, register.name);
+  let value_type = TypeRef::new(register.value_type().name());
+
+  let mut fields: Vec<&SvdField> = register.fields.iter().collect();
+  fields.sort_by_key(|f| f.bit_offset);
+  let mut bits = StructDef::new(&format!("{}_Bits", prefix)).with_typedef();
+  let mut position = 0;
+  for field in fields {
+    if field.bit_offset > position {
+      bits.add_field(
+        FieldDef::new("", value_type.clone()).with_bit_width(field.bit_offset - position),
+      );
+    }
+    let mut member = FieldDef::new(&field.name, value_type.clone()).with_bit_width(field.bit_width);
+    if let Some(ref description) = field.description {
+      member = member.with_doc(description);
+    }
+    bits.add_field(member);
+    position = field.bit_offset + field.bit_width;
+  }
+  if position < register.size {
+    bits.add_field(FieldDef::new("", value_type.clone()).with_bit_width(register.size - position));
+  }
+  bits.write_definition(writer, false)?;
+  writer.newline()?;
+
+  let union_name = format!("{}_Type", prefix);
+  StructDef::union(&union_name)
+    .with_typedef()
+    .with_field(FieldDef::new("bit", TypeRef::new(&bits.name)))
+    .with_field(FieldDef::new("reg", value_type))
+    .write_definition(writer, false)?;
+  Ok(union_name)
+}
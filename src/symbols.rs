@@ -0,0 +1,130 @@
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::rc::Rc;
+
+use crate::error::{CodeGenError, Result};
+
+/// The kind of a registered symbol
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymbolKind {
+  /// A `#define`, object-like or function-like
+  Macro,
+  /// A function with external linkage
+  Function,
+  /// A struct or union tag
+  Struct,
+  /// An enumeration constant
+  EnumMember,
+}
+
+/// Whether a symbol occurrence declares or defines it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymbolRole {
+  Declaration,
+  Definition,
+}
+
+/// Where a symbol was written
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SymbolOrigin {
+  /// Label of the generated file, usually its path
+  pub file: String,
+  /// 1-based line number in the generated output
+  pub line: usize,
+}
+
+impl fmt::Display for SymbolOrigin {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}:{}", self.file, self.line)
+  }
+}
+
+/// A recorded occurrence of a symbol
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SymbolEntry {
+  pub kind: SymbolKind,
+  pub role: SymbolRole,
+  pub origin: SymbolOrigin,
+}
+
+impl SymbolEntry {
+  /// Check whether this occurrence may coexist with another one from a different file
+  ///
+  /// Tags live in their own namespace, so a tag never clashes with an
+  /// ordinary identifier. A function may be declared any number of times and
+  /// defined once; everything else must be unique.
+  fn compatible_with(&self, other: &SymbolEntry) -> bool {
+    if (self.kind == SymbolKind::Struct) != (other.kind == SymbolKind::Struct) {
+      return true;
+    }
+    self.kind == SymbolKind::Function
+      && other.kind == SymbolKind::Function
+      && (self.role == SymbolRole::Declaration || other.role == SymbolRole::Declaration)
+  }
+}
+
+/// Symbols emitted during a generation run, shared between writers
+///
+/// Cloning the registry yields another handle to the same set of symbols, so
+/// one registry can be attached to the writer of every generated file.
+/// Repeated symbols within one file are accepted, since they usually sit in
+/// alternative preprocessor branches.
+#[derive(Debug, Clone, Default)]
+pub struct SymbolRegistry {
+  symbols: Rc<RefCell<BTreeMap<String, Vec<SymbolEntry>>>>,
+}
+
+impl SymbolRegistry {
+  /// Create an empty registry
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Record a symbol, failing if it collides with one written by another file
+  pub fn register(
+    &self,
+    name: &str,
+    kind: SymbolKind,
+    role: SymbolRole,
+    origin: SymbolOrigin,
+  ) -> Result<()> {
+    let entry = SymbolEntry { kind, role, origin };
+    let mut symbols = self.symbols.borrow_mut();
+    let entries = symbols.entry(name.to_string()).or_default();
+
+    if let Some(first) = entries
+      .iter()
+      .find(|e| e.origin.file != entry.origin.file && !e.compatible_with(&entry))
+    {
+      return Err(CodeGenError::SymbolCollision {
+        name: name.to_string(),
+        first: first.origin.to_string(),
+        second: entry.origin.to_string(),
+      });
+    }
+
+    entries.push(entry);
+    Ok(())
+  }
+
+  /// Get all recorded occurrences of a symbol
+  pub fn lookup(&self, name: &str) -> Vec<SymbolEntry> {
+    self.symbols.borrow().get(name).cloned().unwrap_or_default()
+  }
+
+  /// Check whether a symbol has been recorded
+  pub fn contains(&self, name: &str) -> bool {
+    self.symbols.borrow().contains_key(name)
+  }
+
+  /// Get the number of distinct symbols
+  pub fn len(&self) -> usize {
+    self.symbols.borrow().len()
+  }
+
+  /// Check whether no symbols have been recorded
+  pub fn is_empty(&self) -> bool {
+    self.symbols.borrow().is_empty()
+  }
+}
@@ -1,10 +1,41 @@
 #[cfg(test)]
 mod tests {
+  use serde::Serialize;
   use std::fs;
   use std::io::Cursor;
   use tempfile::tempdir;
 
+  use super::super::attribute::{write_portability_macros, Attribute, AttributeTarget, Compiler};
   use super::super::code_writer::CodeWriter;
+  use super::super::comment::CommentPolicy;
+  use super::super::compilation_unit::{CompilationUnit, FunctionDef, StorageClass};
+  use super::super::cpp::{AccessSpecifier, Method, MethodBody};
+  use super::super::data_table::{StructValue, TableOptions, TableSize};
+  use super::super::doc_comment::{DocComment, DocStyle};
+  use super::super::embed::BinaryEmbed;
+  use super::super::enum_table::{EnumTable, LookupStyle};
+  use super::super::error::CodeGenError;
+  use super::super::expr::{BinaryOp, Expr, UnaryOp};
+  use super::super::function_macro::FunctionMacro;
+  use super::super::header::{HeaderScaffold, IncludeGuard};
+  use super::super::initializer::{Initializer, InitializerOptions};
+  use super::super::invariant::{AssertStyle, Invariant};
+  use super::super::layout::{LayoutOptions, TargetAbi};
+  use super::super::literal::{
+    c_char_literal, c_string_literal, c_string_literal_with, split_string_literal, NonAsciiMode,
+  };
+  use super::super::naming::{
+    is_reserved_identifier, sanitize_identifier, split_words, to_lower_camel_case,
+    to_screaming_snake_case, to_snake_case, to_upper_camel_case, Case, NamingConvention,
+  };
+  use super::super::numeric::{float_literal, int_literal, CType, NumberFormat};
+  use super::super::preprocessor::PpExpr;
+  use super::super::register::{AccessorOptions, BitField, RegisterAccess, RegisterDef};
+  use super::super::schema::Schema;
+  use super::super::standard::LanguageStandard;
+  use super::super::svd::{FieldStyle, RegisterMapOptions, SvdDevice};
+  use super::super::symbols::{SymbolKind, SymbolRegistry, SymbolRole};
+  use super::super::types::{FieldDef, StructDef, TypeDef, TypeGraph, TypeRef};
   use super::super::user_section::UserSectionManager;
 
   #[test]
@@ -328,4 +359,2478 @@ int user_variable = 42;
     let result = manager.capture_from_string(content, std::path::Path::new("test.c"));
     assert!(result.is_err());
   }
+
+  #[test]
+  fn test_enum_table_switch_lookup() {
+    let mut table = EnumTable::new("Color");
+    table.add_variant("COLOR_RED", Some(0));
+    table.add_variant_with_string("COLOR_GREEN", None, "green");
+
+    let mut buffer = Cursor::new(Vec::new());
+    let mut writer = CodeWriter::new(&mut buffer);
+    table.write_all(&mut writer).unwrap();
+
+    let output = String::from_utf8(buffer.into_inner()).unwrap();
+    assert!(output.contains("enum Color {\n    COLOR_RED = 0,\n    COLOR_GREEN,\n};\n"));
+    assert!(output.contains("const char * Color_to_string(enum Color value);"));
+    assert!(output.contains("    case COLOR_GREEN: return \"green\";\n"));
+    assert!(
+      output.contains("    if (strcmp(str, \"green\") == 0) {\n        return COLOR_GREEN;\n")
+    );
+    assert!(output.contains("    return (enum Color)-1;\n}\n"));
+
+    // COLOR_GREEN implicitly has value 1, so two case labels would collide
+    table.add_variant("COLOR_BLUE", Some(1));
+    let mut writer = CodeWriter::new(Cursor::new(Vec::new()));
+    assert_eq!(
+      table.write_definitions(&mut writer).unwrap_err().to_string(),
+      "Invalid definition: enum 'Color' cannot use a switch lookup: 'COLOR_GREEN' and 'COLOR_BLUE' both have value 1"
+    );
+  }
+
+  #[test]
+  fn test_enum_table_array_lookup() {
+    let mut table = EnumTable::new("Mode");
+    table.add_variant("MODE_OFF", None);
+    table.add_variant("MODE_ON", Some(1));
+    table.set_lookup_style(LookupStyle::Array);
+    table.set_not_found("MODE_OFF");
+
+    let mut buffer = Cursor::new(Vec::new());
+    let mut writer = CodeWriter::new(&mut buffer);
+    table.write_definitions(&mut writer).unwrap();
+
+    let output = String::from_utf8(buffer.into_inner()).unwrap();
+    assert!(output.starts_with(
+      "static const char *const Mode_names[] = {\n    \"MODE_OFF\",\n    \"MODE_ON\",\n};\n"
+    ));
+    assert!(output.contains("    return Mode_names[value];\n"));
+    assert!(output.contains("    return MODE_OFF;\n}\n"));
+
+    // Gaps in the values cannot be indexed
+    table.add_variant("MODE_AUTO", Some(5));
+    let mut writer = CodeWriter::new(Cursor::new(Vec::new()));
+    assert!(table.write_definitions(&mut writer).is_err());
+  }
+
+  #[test]
+  fn test_write_array_hex_with_index_comments() {
+    let mut buffer = Cursor::new(Vec::new());
+    let mut writer = CodeWriter::new(&mut buffer);
+    let options = TableOptions {
+      per_line: 4,
+      format: NumberFormat::Hex { width: 2 },
+      index_comments: true,
+      ..Default::default()
+    };
+    let values: Vec<u8> = (0..10).map(|i| i * 17).collect();
+    writer
+      .write_array("uint8_t", "table", &values, &options)
+      .unwrap();
+
+    let output = String::from_utf8(buffer.into_inner()).unwrap();
+    let expected = "static const uint8_t table[10] = {\n    /* 0 */ 0x00, 0x11, 0x22, 0x33,\n    /* 4 */ 0x44, 0x55, 0x66, 0x77,\n    /* 8 */ 0x88, 0x99,\n};\n";
+    assert_eq!(output, expected);
+  }
+
+  #[test]
+  fn test_write_array_alignment_and_designated() {
+    let mut buffer = Cursor::new(Vec::new());
+    let mut writer = CodeWriter::new(&mut buffer);
+    let options = TableOptions {
+      per_line: 3,
+      size: TableSize::Unsized,
+      ..Default::default()
+    };
+    writer
+      .write_array("int16_t", "curve", &[1i16, -20, 300, 4], &options)
+      .unwrap();
+
+    let points = vec![
+      StructValue::new()
+        .with_field("x", 1u32)
+        .with_field("y", 0.5f32),
+      StructValue::new()
+        .with_field("x", 2u32)
+        .with_field("y", 1.25f32),
+    ];
+    let options = TableOptions {
+      per_line: 1,
+      designated_indices: true,
+      designated_fields: true,
+      ..Default::default()
+    };
+    writer
+      .write_array("struct Point", "points", &points, &options)
+      .unwrap();
+
+    let output = String::from_utf8(buffer.into_inner()).unwrap();
+    let expected = "static const int16_t curve[] = {\n      1, -20, 300,\n      4,\n};\nstatic const struct Point points[2] = {\n    [0] = { .x = 1, .y = 0.5f },\n    [1] = { .x = 2, .y = 1.25f },\n};\n";
+    assert_eq!(output, expected);
+
+    let mut writer = CodeWriter::new(Cursor::new(Vec::new()));
+    let empty: [u8; 0] = [];
+    assert!(writer
+      .write_array("uint8_t", "empty", &empty, &TableOptions::default())
+      .is_err());
+  }
+
+  #[test]
+  fn test_binary_embed_definition() {
+    let mut embed = BinaryEmbed::new("logo.png", vec![0x89, 0x50, 0x4E, 0x47, 0x0D]).unwrap();
+    embed.set_alignment(4);
+    embed.set_section(".rodata.assets");
+    embed.set_bytes_per_line(4);
+
+    let mut buffer = Cursor::new(Vec::new());
+    let mut writer = CodeWriter::new(&mut buffer);
+    embed.write_declarations(&mut writer).unwrap();
+    embed.write_definition(&mut writer).unwrap();
+
+    let output = String::from_utf8(buffer.into_inner()).unwrap();
+    let expected = "#define LOGO_PNG_LEN 5u\n#define LOGO_PNG_COMPRESSED 0\nextern const uint8_t logo_png[LOGO_PNG_LEN];\n__attribute__((aligned(4), section(\".rodata.assets\"))) const uint8_t logo_png[LOGO_PNG_LEN] = {\n    0x89, 0x50, 0x4E, 0x47,\n    0x0D,\n};\n";
+    assert_eq!(output, expected);
+  }
+
+  #[test]
+  fn test_binary_embed_generate_preserves_sections() {
+    let dir = tempdir().unwrap();
+    let blob_path = dir.path().join("blob.bin");
+    let header_path = dir.path().join("blob.h");
+    let source_path = dir.path().join("blob.c");
+    fs::write(&blob_path, [1u8, 2, 3]).unwrap();
+
+    let embed = BinaryEmbed::from_file("blob", &blob_path).unwrap();
+    embed.generate(&header_path, &source_path).unwrap();
+
+    let source = fs::read_to_string(&source_path).unwrap();
+    assert!(source.contains("#include \"blob.h\"\n"));
+    let edited = source.replace(
+      "/* USER CODE BEGIN Definitions */\n",
+      "/* USER CODE BEGIN Definitions */\nint blob_checksum;\n",
+    );
+    fs::write(&source_path, edited).unwrap();
+
+    embed.generate(&header_path, &source_path).unwrap();
+    let source = fs::read_to_string(&source_path).unwrap();
+    assert!(source.contains("int blob_checksum;\n/* USER CODE END Definitions */"));
+    let header = fs::read_to_string(&header_path).unwrap();
+    assert!(header.contains("#ifndef BLOB_H\n"));
+    assert!(header.contains("#include <stddef.h>\n#include <stdint.h>\n"));
+    assert!(header.contains("extern \"C\" {\n#endif\n\n#define BLOB_LEN 3u\n"));
+    assert!(header.contains("extern const uint8_t blob[BLOB_LEN];\n"));
+
+    let empty_path = dir.path().join("empty.bin");
+    fs::write(&empty_path, []).unwrap();
+    assert_eq!(
+      BinaryEmbed::from_file("empty", &empty_path)
+        .unwrap_err()
+        .to_string(),
+      format!(
+        "Invalid definition: cannot embed '{}': the file is empty",
+        empty_path.display()
+      )
+    );
+    assert_eq!(BinaryEmbed::new("ä.bin", vec![1]).unwrap().name(), "bin");
+    assert!(BinaryEmbed::new("..", vec![1]).is_err());
+  }
+
+  #[test]
+  fn test_cpp_class_with_user_section() {
+    let mut manager = UserSectionManager::new();
+    manager.define_section_with_default("Members", None, "int extra_;\n");
+
+    let mut buffer = Cursor::new(Vec::new());
+    let mut writer = CodeWriter::new(&mut buffer);
+
+    writer.begin_namespace("app::io").unwrap();
+    writer.write_template(&["typename T"]).unwrap();
+    writer
+      .begin_class("Reader", &[(AccessSpecifier::Public, "Base")])
+      .unwrap();
+    writer.indent();
+    writer
+      .write_access_specifier(AccessSpecifier::Public)
+      .unwrap();
+    let ctor = Method {
+      is_explicit: true,
+      ..Method::constructor("Reader", &[("T", "source")])
+    };
+    writer.write_method_declaration(&ctor).unwrap();
+    let copy = Method {
+      body: MethodBody::Delete,
+      ..Method::constructor("Reader", &[("const Reader &", "other")])
+    };
+    writer.write_method_declaration(&copy).unwrap();
+    let read = Method {
+      is_virtual: true,
+      is_const: true,
+      is_override: true,
+      ..Method::new("int", "read", &[])
+    };
+    writer.write_method_declaration(&read).unwrap();
+    writer
+      .write_access_specifier(AccessSpecifier::Private)
+      .unwrap();
+    writer.writeln("T source_;").unwrap();
+    manager.write_section(&mut writer, "Members").unwrap();
+    writer.dedent();
+    writer.end_class().unwrap();
+    writer.end_namespace("app::io").unwrap();
+
+    let output = String::from_utf8(buffer.into_inner()).unwrap();
+    let expected = "namespace app::io {\ntemplate <typename T>\nclass Reader : public Base {\npublic:\n    explicit Reader(T source);\n    Reader(const Reader & other) = delete;\n    virtual int read() const override;\nprivate:\n    T source_;\n    /* USER CODE BEGIN Members */\n    int extra_;\n    /* USER CODE END Members */\n};\n} // namespace app::io\n";
+    assert_eq!(output, expected);
+  }
+
+  #[test]
+  fn test_cpp_constructor_initializer_list() {
+    let mut buffer = Cursor::new(Vec::new());
+    let mut writer = CodeWriter::new(&mut buffer);
+
+    let ctor = Method::constructor("Point", &[("int", "x"), ("int", "y")]);
+    writer
+      .begin_constructor(Some("Point"), &ctor, &[("x_", "x"), ("y_", "y")])
+      .unwrap();
+    writer.end_method().unwrap();
+
+    let size = Method {
+      is_const: true,
+      ..Method::new("int", "size", &[])
+    };
+    writer.begin_method(Some("Point"), &size).unwrap();
+    writer.end_method().unwrap();
+
+    let output = String::from_utf8(buffer.into_inner()).unwrap();
+    let expected =
+      "Point::Point(int x, int y)\n    : x_(x), y_(y) {\n}\nint Point::size() const {\n}\n";
+    assert_eq!(output, expected);
+  }
+
+  #[test]
+  fn test_extern_c_block() {
+    let mut buffer = Cursor::new(Vec::new());
+    let mut writer = CodeWriter::new(&mut buffer);
+    writer
+      .write_extern_c(|w| w.write_function_declaration("void", "init", &[]))
+      .unwrap();
+
+    let output = String::from_utf8(buffer.into_inner()).unwrap();
+    let expected = "#ifdef __cplusplus\nextern \"C\" {\n#endif\n\nvoid init(void);\n\n#ifdef __cplusplus\n}\n#endif\n";
+    assert_eq!(output, expected);
+  }
+
+  #[test]
+  fn test_header_scaffold_generate() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("my_api.h");
+
+    let scaffold = HeaderScaffold::for_file(&path).unwrap();
+    assert_eq!(
+      scaffold.guard(),
+      &IncludeGuard::Define("MY_API_H".to_string())
+    );
+    assert!(HeaderScaffold::for_file(std::path::Path::new("/")).is_err());
+
+    let mut user_sections = UserSectionManager::new();
+    scaffold
+      .generate(&path, &mut user_sections, |w| {
+        w.write_function_declaration("int", "api_call", &[("int", "x")])?;
+        w.set_assert_style(AssertStyle::NegativeArray);
+        w.write_static_assert(&Invariant::custom("X > 0", "x"))
+      })
+      .unwrap();
+
+    let output = fs::read_to_string(&path).unwrap();
+    assert!(output.contains("#ifndef MY_API_H\n#define MY_API_H\n"));
+    assert!(output.contains("extern \"C\" {\n#endif\n\nint api_call(int x);\n"));
+    assert!(output.contains("typedef char static_assert_x_MY_API_H_1[(X > 0) ? 1 : -1];\n"));
+    assert!(output.contains("/* USER CODE BEGIN Declarations */"));
+    assert!(output.ends_with("#endif\n\n#endif // MY_API_H\n"));
+
+    // User edits survive regeneration
+    fs::write(
+      &path,
+      output.replace(
+        "/* USER CODE BEGIN Includes */\n",
+        "/* USER CODE BEGIN Includes */\n#include \"extra.h\"\n",
+      ),
+    )
+    .unwrap();
+    let mut user_sections = UserSectionManager::new();
+    scaffold
+      .generate(&path, &mut user_sections, |_| Ok(()))
+      .unwrap();
+    let output = fs::read_to_string(&path).unwrap();
+    assert!(output.contains("#include \"extra.h\"\n/* USER CODE END Includes */"));
+
+    let mut buffer = Cursor::new(Vec::new());
+    let mut writer = CodeWriter::new(&mut buffer);
+    let mut scaffold = HeaderScaffold::new(IncludeGuard::PragmaOnce);
+    scaffold.set_extern_c(false);
+    let mut user_sections = UserSectionManager::new();
+    scaffold.define_sections(&mut user_sections);
+    scaffold
+      .write(&mut writer, &user_sections, |_| Ok(()))
+      .unwrap();
+    let output = String::from_utf8(buffer.into_inner()).unwrap();
+    assert!(output.contains("#pragma once\n"));
+    assert!(!output.contains("__cplusplus"));
+  }
+
+  #[test]
+  fn test_reserved_includes_are_grouped_and_sorted() {
+    let mut buffer = Cursor::new(Vec::new());
+    let mut writer = CodeWriter::new(&mut buffer);
+
+    writer.writeln("// top").unwrap();
+    writer
+      .write_with_includes(|w| {
+        w.write_include("stdio.h", true)?;
+        w.writeln("int a;")?;
+        w.require_include("b.h", false);
+        w.require_include("a.h", false);
+        w.write_include("stdint.h", true)?;
+        assert!(w.flush().is_err());
+        w.write_include("stdio.h", true)
+      })
+      .unwrap();
+    assert!(!writer.has_reserved_includes());
+
+    // A second include point starts with an empty set
+    writer
+      .write_with_includes(|w| {
+        w.require_include("c.h", false);
+        w.writeln("int b;")
+      })
+      .unwrap();
+    writer.flush().unwrap();
+
+    let output = String::from_utf8(buffer.into_inner()).unwrap();
+    let expected = "// top\n#include <stdint.h>\n#include <stdio.h>\n\n#include \"a.h\"\n#include \"b.h\"\n\nint a;\n#include \"c.h\"\n\nint b;\n";
+    assert_eq!(output, expected);
+  }
+
+  #[test]
+  fn test_header_scaffold_collects_includes() {
+    let mut buffer = Cursor::new(Vec::new());
+    let mut writer = CodeWriter::new(&mut buffer);
+    let mut scaffold = HeaderScaffold::new(IncludeGuard::PragmaOnce);
+    scaffold.set_extern_c(false);
+    let mut user_sections = UserSectionManager::new();
+    scaffold.define_sections(&mut user_sections);
+    scaffold
+      .write(&mut writer, &user_sections, |w| {
+        w.write_include("stdint.h", true)?;
+        w.writeln("uint32_t value(void);")?;
+        w.write_include("stdint.h", true)
+      })
+      .unwrap();
+
+    let output = String::from_utf8(buffer.into_inner()).unwrap();
+    assert_eq!(output.matches("#include <stdint.h>").count(), 1);
+    assert!(output.contains("#pragma once\n\n#include <stdint.h>\n\n/* Additional includes"));
+  }
+
+  #[test]
+  fn test_preprocessor_expression_rendering() {
+    let expr = PpExpr::defined("X").and(PpExpr::ident("VER").compare(BinaryOp::Ge, PpExpr::int(3)));
+    assert_eq!(expr.to_string(), "defined(X) && (VER >= 3)");
+
+    let expr = PpExpr::ident("A").compare(
+      BinaryOp::BitOr,
+      PpExpr::ident("B").compare(BinaryOp::Shl, PpExpr::int(2)),
+    );
+    assert_eq!(expr.to_string(), "A | B << 2");
+
+    let expr = PpExpr::ident("A").compare(
+      BinaryOp::Sub,
+      PpExpr::ident("B").compare(BinaryOp::Sub, PpExpr::int(1)),
+    );
+    assert_eq!(expr.to_string(), "A - (B - 1)");
+
+    let expr = PpExpr::defined("A")
+      .or(PpExpr::defined("B"))
+      .and(PpExpr::defined("C"))
+      .not();
+    assert_eq!(
+      expr.to_string(),
+      "!((defined(A) || defined(B)) && defined(C))"
+    );
+  }
+
+  #[test]
+  fn test_preprocessor_conditional_blocks() {
+    let mut buffer = Cursor::new(Vec::new());
+    let mut writer = CodeWriter::new(&mut buffer);
+
+    writer.begin_if(&PpExpr::defined("USE_DMA")).unwrap();
+    writer
+      .write_if(
+        &PpExpr::ident("DMA_CHANNELS").compare(BinaryOp::Gt, PpExpr::int(4)),
+        |w| w.write_define("DMA_WIDE", None),
+      )
+      .unwrap();
+    writer.write_elif(&PpExpr::defined("USE_IRQ")).unwrap();
+    writer.write_else().unwrap();
+    assert!(writer.write_elif(&PpExpr::int(1)).is_err());
+    writer.end_if().unwrap();
+    assert_eq!(writer.conditional_depth(), 0);
+    writer.flush().unwrap();
+
+    let output = String::from_utf8(buffer.into_inner()).unwrap();
+    let expected = "#if defined(USE_DMA)\n#if DMA_CHANNELS > 4\n#define DMA_WIDE\n#endif // DMA_CHANNELS > 4\n#elif defined(USE_IRQ)\n#else\n#endif // defined(USE_DMA)\n";
+    assert_eq!(output, expected);
+  }
+
+  #[test]
+  fn test_preprocessor_unbalanced_errors() {
+    let mut writer = CodeWriter::new(Cursor::new(Vec::new()));
+    assert!(writer.write_endif(None).is_err());
+    assert!(writer.write_else().is_err());
+
+    writer.write_ifndef("GUARD_H").unwrap();
+    assert!(matches!(
+      writer.flush(),
+      Err(CodeGenError::UnbalancedConditional(_))
+    ));
+    writer.write_endif(Some("GUARD_H")).unwrap();
+    writer.flush().unwrap();
+  }
+
+  #[test]
+  fn test_function_macro_single_line_and_undef() {
+    let mut buffer = Cursor::new(Vec::new());
+    let mut writer = CodeWriter::new(&mut buffer);
+
+    let max = FunctionMacro::new("MAX", &["a", "b"], "((a) > (b) ? (a) : (b))");
+    writer.write_macro(&max).unwrap();
+    let log = FunctionMacro {
+      variadic: true,
+      ..FunctionMacro::new("LOG", &["fmt"], "printf(fmt, __VA_ARGS__)")
+    };
+    writer.write_macro(&log).unwrap();
+    writer.write_undef("MAX").unwrap();
+
+    let output = String::from_utf8(buffer.into_inner()).unwrap();
+    let expected = "#define MAX(a, b) ((a) > (b) ? (a) : (b))\n#define LOG(fmt, ...) printf(fmt, __VA_ARGS__)\n#undef MAX\n";
+    assert_eq!(output, expected);
+  }
+
+  #[test]
+  fn test_function_macro_multi_line_do_while() {
+    let mut buffer = Cursor::new(Vec::new());
+    let mut writer = CodeWriter::new(&mut buffer);
+
+    let write_reg = FunctionMacro {
+      do_while: true,
+      ..FunctionMacro::new("REG_WRITE", &["reg", "val"], "(reg) = (val);\n__DSB();")
+    };
+    writer.write_macro(&write_reg).unwrap();
+
+    let output = String::from_utf8(buffer.into_inner()).unwrap();
+    let expected = concat!(
+      "#define REG_WRITE(reg, val) \\\n",
+      "    do {                    \\\n",
+      "        (reg) = (val);      \\\n",
+      "        __DSB();            \\\n",
+      "    } while (0)\n",
+    );
+    assert_eq!(output, expected);
+
+    // Alignment counts characters, not bytes
+    let greet = FunctionMacro::new("GREET", &[], "puts(\"h\u{e9}llo\");\nputs(\"hi\");");
+    assert_eq!(
+      greet.render_lines("  "),
+      vec![
+        "#define GREET()  \\",
+        "  puts(\"h\u{e9}llo\"); \\",
+        "  puts(\"hi\");",
+      ]
+    );
+
+    // A line comment would swallow the continuation
+    let commented = FunctionMacro::new("BAD", &[], "a(); // first\nb();");
+    let mut writer = CodeWriter::new(Cursor::new(Vec::new()));
+    assert!(writer.write_macro(&commented).is_err());
+    let url = FunctionMacro::new("URL", &[], "puts(\"http://x\");\nb(); // last");
+    assert!(url.validate().is_ok());
+  }
+
+  #[test]
+  fn test_doxygen_function_and_file_docs() {
+    let mut buffer = Cursor::new(Vec::new());
+    let mut writer = CodeWriter::new(&mut buffer);
+
+    writer.write_file_doc("uart.h", "UART driver").unwrap();
+    let doc = DocComment::new("Send a byte")
+      .with_param("byte", "Byte to send")
+      .with_returns("0 on success");
+    writer
+      .write_documented_function_declaration(&doc, "int", "uart_send", &[("uint8_t", "byte")])
+      .unwrap();
+
+    let output = String::from_utf8(buffer.into_inner()).unwrap();
+    let expected = "/**\n * @file uart.h\n * @brief UART driver\n */\n/**\n * @brief Send a byte\n *\n * @param byte Byte to send\n * @return 0 on success\n */\nint uart_send(uint8_t byte);\n";
+    assert_eq!(output, expected);
+
+    let doc = DocComment::new("Bad").with_param("missing", "Not a parameter");
+    let mut writer = CodeWriter::new(Cursor::new(Vec::new()));
+    assert!(writer
+      .write_documented_function_declaration(&doc, "void", "f", &[])
+      .is_err());
+  }
+
+  #[test]
+  fn test_doc_styles_for_members() {
+    let mut buffer = Cursor::new(Vec::new());
+    let mut writer = CodeWriter::new(&mut buffer);
+
+    writer
+      .write_documented_variable("int", "id", "Identifier")
+      .unwrap();
+    writer.set_doc_style(DocStyle::Javadoc);
+    writer
+      .write_documented_enum_member("MODE_ON", Some("1"), "Enabled")
+      .unwrap();
+    writer
+      .write_doc_comment(&DocComment::new("Summary"))
+      .unwrap();
+    writer.set_doc_style(DocStyle::Plain);
+    writer
+      .write_doc_comment(&DocComment::new("Reset").with_param("hard", "Full reset"))
+      .unwrap();
+
+    let output = String::from_utf8(buffer.into_inner()).unwrap();
+    let expected = "int id; ///< Identifier\n    MODE_ON = 1, /**< Enabled */\n/**\n * Summary\n */\n/*\n * Reset\n *\n * Parameters:\n *   hard - Full reset\n */\n";
+    assert_eq!(output, expected);
+  }
+
+  #[test]
+  fn test_comment_text_is_sanitized() {
+    let mut buffer = Cursor::new(Vec::new());
+    let mut writer = CodeWriter::new(&mut buffer);
+
+    writer.write_comment("path C:\\temp\\").unwrap();
+    writer.write_comment("a */ b\nc /* d").unwrap();
+    writer.write_separator("x */ y", 0).unwrap();
+    writer
+      .write_documented_variable("int", "flags", "ends with \\")
+      .unwrap();
+    writer
+      .write_doc_comment(&DocComment::new("stop */ here"))
+      .unwrap();
+
+    let output = String::from_utf8(buffer.into_inner()).unwrap();
+    let expected = "/* path C:\\temp\\ */\n/*\n * a *\\/ b\n * c /\\* d\n */\n/* x *\\/ y */\nint flags; /**< ends with \\ */\n/**\n * @brief stop *\\/ here\n */\n";
+    assert_eq!(output, expected);
+  }
+
+  #[test]
+  fn test_comment_text_is_rejected() {
+    let mut writer = CodeWriter::new(Cursor::new(Vec::new()));
+    writer.set_comment_policy(CommentPolicy::Reject);
+
+    assert!(matches!(
+      writer.write_comment("trailing \\"),
+      Err(CodeGenError::InvalidComment(_))
+    ));
+    assert!(matches!(
+      writer.write_separator("a */ b", 80),
+      Err(CodeGenError::InvalidComment(_))
+    ));
+    writer.write_ifdef("X").unwrap();
+    assert!(writer.write_endif(Some("X ??/")).is_err());
+
+    let mut manager = UserSectionManager::new();
+    manager.define_section_with_description("Body", "Ends early */");
+    assert!(matches!(
+      manager.write_section(&mut writer, "Body"),
+      Err(CodeGenError::InvalidComment(_))
+    ));
+  }
+
+  #[test]
+  fn test_section_content_with_own_marker_is_rejected() {
+    let mut manager = UserSectionManager::new();
+    manager.define_section_with_default("Body", None, "int x;\n/* USER CODE END Body */\nint y;\n");
+    manager.define_section_with_default("Other", None, "/* USER CODE END Body */\n");
+
+    let mut buffer = Cursor::new(Vec::new());
+    let mut writer = CodeWriter::new(&mut buffer);
+    assert!(matches!(
+      manager.write_section(&mut writer, "Body"),
+      Err(CodeGenError::MarkerInSectionContent(_))
+    ));
+    assert!(!manager.is_section_written("Body"));
+    manager.write_section(&mut writer, "Other").unwrap();
+    assert!(manager
+      .write_partial_section(&mut writer, 3, Some("//!end 3\n"))
+      .is_err());
+    manager
+      .write_partial_section(&mut writer, 3, Some("//!end 30\n"))
+      .unwrap();
+
+    // A failed write is not recorded, so fixing the content and retrying works
+    manager.define_section_with_default("Body", None, "int x;\n");
+    manager.write_section(&mut writer, "Body").unwrap();
+    assert!(manager.is_section_written("Body"));
+
+    let output = String::from_utf8(buffer.into_inner()).unwrap();
+    assert!(output.starts_with("/* USER CODE BEGIN Other */"));
+    assert!(output.contains("/* USER CODE BEGIN Body */\nint x;\n/* USER CODE END Body */\n"));
+  }
+
+  #[test]
+  fn test_c_string_literal_escaping() {
+    assert_eq!(
+      c_string_literal("say \"hi\"\n\tC:\\"),
+      "\"say \\\"hi\\\"\\n\\tC:\\\\\""
+    );
+    assert_eq!(c_string_literal("what??!"), "\"what?\\?!\"");
+    assert_eq!(c_string_literal("nul\u{0}1"), "\"nul\\0001\"");
+    // A hex escape must not swallow the following hex digit
+    assert_eq!(c_string_literal("\u{e9}a"), "\"\\xC3\\xA9\"\"a\"");
+    assert_eq!(
+      c_string_literal_with("caf\u{e9}", NonAsciiMode::Utf8Prefix),
+      "u8\"caf\u{e9}\""
+    );
+    assert_eq!(
+      c_string_literal_with("cafe", NonAsciiMode::Utf8Prefix),
+      "\"cafe\""
+    );
+
+    assert_eq!(c_char_literal('a').unwrap(), "'a'");
+    assert_eq!(c_char_literal('\'').unwrap(), "'\\''");
+    assert_eq!(c_char_literal('\n').unwrap(), "'\\n'");
+    assert!(c_char_literal('\u{e9}').is_err());
+  }
+
+  #[test]
+  fn test_split_string_literal() {
+    let pieces = split_string_literal("line one\nline two is longer", 12, NonAsciiMode::HexEscape);
+    assert_eq!(
+      pieces,
+      vec!["\"line one\\n\"", "\"line two i\"", "\"s longer\""]
+    );
+    // Escapes are kept whole
+    let pieces = split_string_literal("ab\"cd", 5, NonAsciiMode::HexEscape);
+    assert_eq!(pieces, vec!["\"ab\"", "\"\\\"c\"", "\"d\""]);
+
+    let mut buffer = Cursor::new(Vec::new());
+    let mut writer = CodeWriter::new(&mut buffer);
+    writer
+      .write_string_define("GREETING", "hi \"you\"")
+      .unwrap();
+    writer
+      .write_string_constant("static const char banner[]", "first\nsecond\n", 80)
+      .unwrap();
+    let output = String::from_utf8(buffer.into_inner()).unwrap();
+    let expected = "#define GREETING \"hi \\\"you\\\"\"\nstatic const char banner[] =\n    \"first\\n\"\n    \"second\\n\";\n";
+    assert_eq!(output, expected);
+  }
+
+  #[test]
+  fn test_typed_integer_literals() {
+    assert_eq!(
+      int_literal(42, CType::UInt32, NumberFormat::Decimal).unwrap(),
+      "42U"
+    );
+    assert_eq!(
+      int_literal(0xFF, CType::UInt64, NumberFormat::Hex { width: 8 }).unwrap(),
+      "0x000000FFULL"
+    );
+    assert_eq!(
+      int_literal(7, CType::ULong, NumberFormat::Decimal).unwrap(),
+      "7UL"
+    );
+    assert_eq!(
+      int_literal(i32::MIN as i128, CType::Int32, NumberFormat::Decimal).unwrap(),
+      "(-2147483647 - 1)"
+    );
+    assert_eq!(
+      int_literal(i64::MIN as i128, CType::Int64, NumberFormat::Decimal).unwrap(),
+      "(-9223372036854775807LL - 1)"
+    );
+    assert!(matches!(
+      int_literal(256, CType::UInt8, NumberFormat::Decimal),
+      Err(CodeGenError::ValueOutOfRange { .. })
+    ));
+    assert!(int_literal(-1, CType::UInt32, NumberFormat::Decimal).is_err());
+  }
+
+  #[test]
+  fn test_typed_float_literals() {
+    assert_eq!(float_literal(0.1, CType::Float).unwrap(), "0.1f");
+    assert_eq!(float_literal(0.1, CType::Double).unwrap(), "0.1");
+    assert_eq!(float_literal(2.0, CType::Double).unwrap(), "2.0");
+    assert_eq!(float_literal(1e-7, CType::Float).unwrap(), "1e-7f");
+    assert!(float_literal(f64::NAN, CType::Double).is_err());
+    assert!(float_literal(1e300, CType::Float).is_err());
+    assert!(float_literal(1e-50, CType::Float).is_err());
+    assert_eq!(float_literal(0.0, CType::Float).unwrap(), "0.0f");
+    assert_eq!(float_literal(-0.0, CType::Float).unwrap(), "-0.0f");
+    assert!(float_literal(1.0, CType::Int32).is_err());
+  }
+
+  #[test]
+  fn test_typed_literals_in_writer() {
+    let mut buffer = Cursor::new(Vec::new());
+    let mut writer = CodeWriter::new(&mut buffer);
+
+    writer
+      .write_int_define("MAX_LEN", 64, CType::UInt32, NumberFormat::Decimal)
+      .unwrap();
+    writer
+      .write_int_define("OFFSET", -4, CType::Int32, NumberFormat::Decimal)
+      .unwrap();
+    writer
+      .write_float_define("GAIN", -0.5, CType::Float)
+      .unwrap();
+    writer.write_enum_member_value("BIG", 1 << 20).unwrap();
+    assert!(writer.write_enum_member_value("HUGE", 1 << 40).is_err());
+
+    let options = TableOptions {
+      literal_type: Some(CType::UInt16),
+      format: NumberFormat::Hex { width: 4 },
+      ..Default::default()
+    };
+    writer
+      .write_array("uint16_t", "crc", &[1u32, 0xFFFF], &options)
+      .unwrap();
+    assert!(writer
+      .write_array("uint16_t", "crc", &[0x10000u32], &options)
+      .is_err());
+    assert!(writer
+      .write_array("float", "bad", &[f32::NAN], &TableOptions::default())
+      .is_err());
+
+    let output = String::from_utf8(buffer.into_inner()).unwrap();
+    let expected = "#define MAX_LEN 64U\n#define OFFSET (-4)\n#define GAIN (-0.5f)\n    BIG = 1048576,\nstatic const uint16_t crc[2] = {\n    0x0001U, 0xFFFFU,\n};\n";
+    assert_eq!(output, expected);
+  }
+
+  #[test]
+  fn test_case_conversion() {
+    assert_eq!(
+      split_words("HTTPServer2Config"),
+      vec!["HTTP", "Server2", "Config"]
+    );
+    assert_eq!(
+      split_words("sensor-id value"),
+      vec!["sensor", "id", "value"]
+    );
+
+    assert_eq!(to_snake_case("HTTPServerConfig"), "http_server_config");
+    assert_eq!(to_screaming_snake_case("uartBaudRate"), "UART_BAUD_RATE");
+    assert_eq!(to_upper_camel_case("rx_fifo_level"), "RxFifoLevel");
+    assert_eq!(to_lower_camel_case("RX FIFO level"), "rxFifoLevel");
+  }
+
+  #[test]
+  fn test_sanitize_identifier() {
+    assert_eq!(
+      sanitize_identifier("température", LanguageStandard::C99),
+      "temp_rature"
+    );
+    assert_eq!(
+      sanitize_identifier("3d-point", LanguageStandard::C99),
+      "n3d_point"
+    );
+    assert_eq!(
+      sanitize_identifier("__Reserved", LanguageStandard::C99),
+      "Reserved"
+    );
+    assert_eq!(sanitize_identifier("a__b", LanguageStandard::Cpp17), "a_b");
+    assert_eq!(sanitize_identifier("", LanguageStandard::C99), "unnamed");
+
+    assert_eq!(sanitize_identifier("int", LanguageStandard::C89), "int_");
+    assert_eq!(
+      sanitize_identifier("inline", LanguageStandard::C89),
+      "inline"
+    );
+    assert_eq!(
+      sanitize_identifier("inline", LanguageStandard::C99),
+      "inline_"
+    );
+    assert_eq!(sanitize_identifier("class", LanguageStandard::C11), "class");
+    assert_eq!(
+      sanitize_identifier("class", LanguageStandard::Cpp11),
+      "class_"
+    );
+    assert_eq!(
+      sanitize_identifier("requires", LanguageStandard::Cpp17),
+      "requires"
+    );
+    assert_eq!(
+      sanitize_identifier("requires", LanguageStandard::Cpp20),
+      "requires_"
+    );
+
+    assert!(is_reserved_identifier("_Bool", LanguageStandard::C99));
+    assert!(is_reserved_identifier("__x", LanguageStandard::C99));
+    assert!(!is_reserved_identifier("a__b", LanguageStandard::C99));
+    assert!(is_reserved_identifier("a__b", LanguageStandard::Cpp11));
+  }
+
+  #[test]
+  fn test_naming_convention() {
+    let types = NamingConvention::new(Case::Snake, LanguageStandard::C99)
+      .with_prefix("mylib_")
+      .with_suffix("_t");
+    assert_eq!(types.apply("SensorConfig"), "mylib_sensor_config_t");
+
+    let constants =
+      NamingConvention::new(Case::ScreamingSnake, LanguageStandard::C99).with_prefix("MYLIB_");
+    assert_eq!(constants.apply("max packet size"), "MYLIB_MAX_PACKET_SIZE");
+    assert_eq!(
+      constants.apply("max packet size"),
+      constants.apply("max packet size")
+    );
+
+    let fields = NamingConvention::new(Case::LowerCamel, LanguageStandard::Cpp17);
+    assert_eq!(fields.apply("Default"), "default_");
+    assert_eq!(fields.apply("2nd value"), "n2ndValue");
+  }
+
+  #[test]
+  fn test_symbol_registry_allows_declaration_and_definition() {
+    let registry = SymbolRegistry::new();
+
+    let mut header = CodeWriter::new(Cursor::new(Vec::new()));
+    header.set_symbol_registry(registry.clone(), "api.h");
+    header.write_define("API_VERSION", Some("2")).unwrap();
+    header
+      .write_function_declaration("int", "api_init", &[])
+      .unwrap();
+
+    let mut source = CodeWriter::new(Cursor::new(Vec::new()));
+    source.set_symbol_registry(registry.clone(), "api.c");
+    source.begin_function("int", "api_init", &[]).unwrap();
+    source.end_function().unwrap();
+
+    // Alternative definitions within one file are not collisions
+    let mut config = CodeWriter::new(Cursor::new(Vec::new()));
+    config.set_symbol_registry(registry.clone(), "config.h");
+    config.write_ifdef("NDEBUG").unwrap();
+    config.write_define("LOG_LEVEL", Some("0")).unwrap();
+    config.write_else().unwrap();
+    config.write_define("LOG_LEVEL", Some("3")).unwrap();
+    config.write_endif(None).unwrap();
+
+    // File-local functions of the same name do not collide
+    let mut motor = CodeWriter::new(Cursor::new(Vec::new()));
+    motor.set_symbol_registry(registry.clone(), "motor.c");
+    motor.begin_function("static void", "init", &[]).unwrap();
+    motor.end_function().unwrap();
+    let mut fan = CodeWriter::new(Cursor::new(Vec::new()));
+    fan.set_symbol_registry(registry.clone(), "fan.c");
+    fan
+      .write_function_declaration("static inline int", "init", &[])
+      .unwrap();
+    fan
+      .begin_function("static inline int", "init", &[])
+      .unwrap();
+    fan.end_function().unwrap();
+    assert!(!registry.contains("init"));
+
+    assert_eq!(registry.len(), 3);
+    let entries = registry.lookup("api_init");
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[0].role, SymbolRole::Declaration);
+    assert_eq!(entries[1].kind, SymbolKind::Function);
+    assert_eq!(entries[1].origin.to_string(), "api.c:1");
+  }
+
+  #[test]
+  fn test_symbol_registry_reports_collisions() {
+    let registry = SymbolRegistry::new();
+
+    let mut first = CodeWriter::new(Cursor::new(Vec::new()));
+    first.set_symbol_registry(registry.clone(), "motor.h");
+    first.writeln("#pragma once").unwrap();
+    first.write_define("MAX_SPEED", Some("100")).unwrap();
+    first.begin_enum("mode").unwrap();
+    first.write_enum_member("MODE_OFF", None).unwrap();
+    first.end_enum().unwrap();
+
+    let mut second = CodeWriter::new(Cursor::new(Vec::new()));
+    second.set_symbol_registry(registry.clone(), "fan.h");
+    match second.write_define("MAX_SPEED", Some("50")) {
+      Err(CodeGenError::SymbolCollision {
+        name,
+        first,
+        second,
+      }) => {
+        assert_eq!(name, "MAX_SPEED");
+        assert_eq!(first, "motor.h:2");
+        assert_eq!(second, "fan.h:1");
+      }
+      other => panic!("expected a symbol collision, got {:?}", other),
+    }
+    assert!(second
+      .write_documented_enum_member("MODE_OFF", None, "Off")
+      .is_err());
+
+    let mut source = CodeWriter::new(Cursor::new(Vec::new()));
+    source.set_symbol_registry(registry.clone(), "motor.c");
+    source.begin_function("void", "motor_stop", &[]).unwrap();
+    let mut other = CodeWriter::new(Cursor::new(Vec::new()));
+    other.set_symbol_registry(registry.clone(), "fan.c");
+    assert!(other.begin_function("void", "motor_stop", &[]).is_err());
+
+    // Struct tags have their own namespace
+    let mut tags = CodeWriter::new(Cursor::new(Vec::new()));
+    tags.set_symbol_registry(registry.clone(), "a.h");
+    tags.begin_struct("device").unwrap();
+    let mut functions = CodeWriter::new(Cursor::new(Vec::new()));
+    functions.set_symbol_registry(registry.clone(), "b.h");
+    functions
+      .write_function_declaration("int", "device", &[("int", "id")])
+      .unwrap();
+    let mut structs = CodeWriter::new(Cursor::new(Vec::new()));
+    structs.set_symbol_registry(registry, "c.h");
+    assert!(structs.begin_struct("device").is_err());
+  }
+
+  #[test]
+  fn test_compilation_unit_header_and_source() {
+    let mut unit = CompilationUnit::new(HeaderScaffold::new(IncludeGuard::PragmaOnce));
+    unit.add_header_include("stdint.h", true);
+    unit.add_function(
+      FunctionDef::new(
+        "uint32_t",
+        "crc_update",
+        &[("uint32_t", "crc"), ("uint8_t", "byte")],
+      )
+      .with_doc(DocComment::new("Feed one byte into the CRC"))
+      .with_body(&["return crc_step(crc ^ byte);"]),
+    );
+    unit.add_function(
+      FunctionDef::new("uint32_t", "crc_step", &[("uint32_t", "value")])
+        .with_storage(StorageClass::StaticInline)
+        .with_body(&["return value >> 1;"]),
+    );
+
+    let mut header_sections = UserSectionManager::new();
+    unit.define_header_sections(&mut header_sections);
+    let mut buffer = Cursor::new(Vec::new());
+    let mut writer = CodeWriter::new(&mut buffer);
+    unit.write_header(&mut writer, &header_sections).unwrap();
+    let header = String::from_utf8(buffer.into_inner()).unwrap();
+    assert!(header.contains("#include <stdint.h>\n"));
+    assert!(header.contains(
+      "/**\n * @brief Feed one byte into the CRC\n */\nuint32_t crc_update(uint32_t crc, uint8_t byte);\n"
+    ));
+    assert!(!header.contains("crc_step"));
+
+    let mut source_sections = UserSectionManager::new();
+    unit.define_source_sections(&mut source_sections);
+    let mut buffer = Cursor::new(Vec::new());
+    let mut writer = CodeWriter::new(&mut buffer);
+    unit
+      .write_source(&mut writer, "crc.h", &source_sections)
+      .unwrap();
+    let source = String::from_utf8(buffer.into_inner()).unwrap();
+    let expected = "#include \"crc.h\"\n\nstatic inline uint32_t crc_step(uint32_t value);\n\nuint32_t crc_update(uint32_t crc, uint8_t byte) {\n    return crc_step(crc ^ byte);\n}\n\nstatic inline uint32_t crc_step(uint32_t value) {\n    return value >> 1;\n}\n\n";
+    assert!(source.contains(expected), "{}", source);
+  }
+
+  #[test]
+  fn test_compilation_unit_preserves_function_bodies() {
+    let dir = tempdir().unwrap();
+    let header_path = dir.path().join("motor.h");
+    let source_path = dir.path().join("motor.c");
+
+    let mut unit = CompilationUnit::new(HeaderScaffold::for_file(&header_path).unwrap());
+    unit.add_function(FunctionDef::new("void", "motor_stop", &[]).with_user_section("MotorStop"));
+    unit.generate(&header_path, &source_path).unwrap();
+
+    let header = fs::read_to_string(&header_path).unwrap();
+    assert!(header.contains("void motor_stop(void);\n"));
+    let source = fs::read_to_string(&source_path).unwrap();
+    assert!(source.contains(
+      "void motor_stop(void) {\n/* USER CODE BEGIN MotorStop */\n/* USER CODE END MotorStop */\n}\n"
+    ));
+
+    fs::write(
+      &source_path,
+      source.replace(
+        "/* USER CODE BEGIN MotorStop */\n",
+        "/* USER CODE BEGIN MotorStop */\n    pwm_disable();\n",
+      ),
+    )
+    .unwrap();
+    unit.generate(&header_path, &source_path).unwrap();
+    let source = fs::read_to_string(&source_path).unwrap();
+    assert!(source.contains(
+      "/* USER CODE BEGIN MotorStop */\n    pwm_disable();\n/* USER CODE END MotorStop */\n}\n"
+    ));
+  }
+
+  #[test]
+  fn test_type_graph_orders_and_forward_declares() {
+    let mut graph = TypeGraph::new();
+    graph
+      .add_typedef("node_ref", TypeRef::pointer_to("struct node"))
+      .unwrap();
+    graph
+      .add_struct(
+        StructDef::new("node")
+          .with_typedef()
+          .with_field(FieldDef::new("current", TypeRef::new("packet"))),
+      )
+      .unwrap();
+    graph
+      .add_struct(
+        StructDef::new("packet")
+          .with_typedef()
+          .with_field(FieldDef::new("hdr", TypeRef::new("header")))
+          .with_field(FieldDef::new("next", TypeRef::pointer_to("struct packet")))
+          .with_field(FieldDef::new(
+            "owner",
+            TypeRef::pointer_to("node").with_const(),
+          )),
+      )
+      .unwrap();
+    graph
+      .add_struct(
+        StructDef::union("header")
+          .with_typedef()
+          .with_field(FieldDef::new("version", TypeRef::new("uint8_t")).with_bit_width(4))
+          .with_field(
+            FieldDef::new("raw", TypeRef::new("uint8_t"))
+              .with_array_len("2")
+              .with_doc("Raw bytes"),
+          ),
+      )
+      .unwrap();
+
+    let names: Vec<&str> = graph.sorted().unwrap().iter().map(|t| t.name()).collect();
+    assert_eq!(names, vec!["node_ref", "header", "packet", "node"]);
+
+    let mut buffer = Cursor::new(Vec::new());
+    let mut writer = CodeWriter::new(&mut buffer);
+    graph.write(&mut writer).unwrap();
+    let output = String::from_utf8(buffer.into_inner()).unwrap();
+    let expected = "typedef struct node node;\n\ntypedef struct node *node_ref;\n\nunion header {\n    uint8_t version : 4;\n    uint8_t raw[2]; ///< Raw bytes\n};\ntypedef union header header;\n\nstruct packet {\n    header hdr;\n    struct packet *next;\n    const node *owner;\n};\ntypedef struct packet packet;\n\nstruct node {\n    packet current;\n};\n";
+    assert_eq!(output, expected);
+  }
+
+  #[test]
+  fn test_type_graph_reports_cycles() {
+    let mut graph = TypeGraph::new();
+    graph
+      .add_struct(StructDef::new("a").with_field(FieldDef::new("b", TypeRef::new("struct b"))))
+      .unwrap();
+    graph
+      .add_struct(StructDef::new("b").with_field(FieldDef::new("a", TypeRef::new("struct a"))))
+      .unwrap();
+    match graph.sorted() {
+      Err(CodeGenError::DependencyCycle(path)) => assert_eq!(path, "a -> b -> a"),
+      other => panic!("expected a dependency cycle, got {:?}", other),
+    }
+
+    let mut graph = TypeGraph::new();
+    graph
+      .add_struct(StructDef::new("list").with_field(FieldDef::new("next", TypeRef::new("list"))))
+      .unwrap();
+    assert!(graph.sorted().is_err());
+    assert!(graph.add_struct(StructDef::new("list")).is_err());
+
+    // The same references through pointers are fine
+    let mut graph = TypeGraph::new();
+    graph
+      .add_struct(
+        StructDef::new("a").with_field(FieldDef::new("b", TypeRef::pointer_to("struct b"))),
+      )
+      .unwrap();
+    graph
+      .add_struct(
+        StructDef::new("b").with_field(FieldDef::new("a", TypeRef::pointer_to("struct a"))),
+      )
+      .unwrap();
+    let mut buffer = Cursor::new(Vec::new());
+    let mut writer = CodeWriter::new(&mut buffer);
+    graph.write(&mut writer).unwrap();
+    let output = String::from_utf8(buffer.into_inner()).unwrap();
+    assert!(output.starts_with("struct b;\n\nstruct a {\n    struct b *b;"));
+
+    // A self-referential list through its typedef, held by value elsewhere
+    let mut graph = TypeGraph::new();
+    graph
+      .add_struct(
+        StructDef::new("holder").with_field(FieldDef::new("head", TypeRef::new("node_t"))),
+      )
+      .unwrap();
+    graph
+      .add_typedef("node_t", TypeRef::new("struct node"))
+      .unwrap();
+    graph
+      .add_struct(
+        StructDef::new("node").with_field(FieldDef::new("next", TypeRef::pointer_to("node_t"))),
+      )
+      .unwrap();
+    let mut buffer = Cursor::new(Vec::new());
+    let mut writer = CodeWriter::new(&mut buffer);
+    graph.write(&mut writer).unwrap();
+    let output = String::from_utf8(buffer.into_inner()).unwrap();
+    assert_eq!(
+      output,
+      concat!(
+        "struct node;\n\n",
+        "typedef struct node node_t;\n\n",
+        "struct node {\n    node_t *next;\n};\n\n",
+        "struct holder {\n    node_t head;\n};\n",
+      )
+    );
+  }
+
+  #[test]
+  fn test_struct_layout_per_abi() {
+    let mut graph = TypeGraph::new();
+    graph
+      .add_struct(
+        StructDef::new("sample")
+          .with_field(FieldDef::new("tag", TypeRef::new("uint8_t")))
+          .with_field(FieldDef::new("value", TypeRef::new("double")))
+          .with_field(FieldDef::new("next", TypeRef::pointer_to("void")))
+          .with_field(FieldDef::new("crc", TypeRef::new("uint16_t"))),
+      )
+      .unwrap();
+    let offsets = |abi: &TargetAbi| {
+      let layout = abi.layout(&graph, "sample").unwrap();
+      let offsets: Vec<usize> = layout.fields.iter().map(|f| f.offset).collect();
+      (offsets, layout.size, layout.align)
+    };
+
+    assert_eq!(
+      offsets(&TargetAbi::ilp32_arm_eabi()),
+      (vec![0, 8, 16, 20], 24, 8)
+    );
+    assert_eq!(
+      offsets(&TargetAbi::lp64_x86_64()),
+      (vec![0, 8, 16, 24], 32, 8)
+    );
+    assert_eq!(
+      offsets(&TargetAbi::ilp32_arm_eabi().with_pack(1)),
+      (vec![0, 1, 9, 13], 15, 1)
+    );
+
+    let abi = TargetAbi::ilp32_arm_eabi();
+    let layout = abi.layout(&graph, "sample").unwrap();
+    let Some(TypeDef::Aggregate(def)) = graph.get("sample") else {
+      panic!("sample is a struct");
+    };
+    let padded = layout.pad_struct(def);
+    let names: Vec<&str> = padded.fields.iter().map(|f| f.name.as_str()).collect();
+    assert_eq!(names, vec!["tag", "pad0", "value", "next", "crc", "pad1"]);
+    assert_eq!(padded.fields[1].array_len.as_deref(), Some("7"));
+    assert_eq!(padded.fields[5].array_len.as_deref(), Some("2"));
+  }
+
+  #[test]
+  fn test_struct_layout_bit_fields_and_nesting() {
+    let mut graph = TypeGraph::new();
+    graph
+      .add_struct(
+        StructDef::new("flags")
+          .with_field(FieldDef::new("a", TypeRef::new("uint32_t")).with_bit_width(3))
+          .with_field(FieldDef::new("b", TypeRef::new("uint32_t")).with_bit_width(30))
+          .with_field(FieldDef::new("c", TypeRef::new("uint8_t"))),
+      )
+      .unwrap();
+    graph
+      .add_typedef("flags_t", TypeRef::new("struct flags"))
+      .unwrap();
+    graph
+      .add_struct(
+        StructDef::new("outer")
+          .with_field(FieldDef::new("x", TypeRef::new("uint8_t")))
+          .with_field(FieldDef::new("f", TypeRef::new("flags_t")))
+          .with_field(FieldDef::new("raw", TypeRef::new("uint16_t")).with_array_len("3")),
+      )
+      .unwrap();
+    graph
+      .add_struct(
+        StructDef::union("word")
+          .with_field(FieldDef::new("u32", TypeRef::new("uint32_t")))
+          .with_field(FieldDef::new("bytes", TypeRef::new("uint8_t")).with_array_len("6")),
+      )
+      .unwrap();
+
+    let abi = TargetAbi::ilp32_arm_eabi();
+    let flags = abi.layout(&graph, "flags").unwrap();
+    let b = flags.field("b").unwrap();
+    assert_eq!((b.offset, b.bit_offset), (4, Some(0)));
+    assert_eq!(flags.field("c").unwrap().offset, 8);
+    assert_eq!(flags.size, 12);
+
+    let outer = abi.layout(&graph, "outer").unwrap();
+    assert_eq!(outer.field("f").unwrap().offset, 4);
+    assert_eq!(outer.field("raw").unwrap().offset, 16);
+    assert_eq!(outer.size, 24);
+
+    let word = abi.layout(&graph, "word").unwrap();
+    assert_eq!((word.size, word.align), (8, 4));
+
+    assert!(abi.with_pack(1).layout(&graph, "flags").is_err());
+  }
+
+  #[test]
+  fn test_write_struct_with_layout_checks() {
+    let mut graph = TypeGraph::new();
+    graph
+      .add_struct(
+        StructDef::new("msg")
+          .with_typedef()
+          .with_field(FieldDef::new("tag", TypeRef::new("uint8_t")))
+          .with_field(FieldDef::new("value", TypeRef::new("uint32_t"))),
+      )
+      .unwrap();
+    let options = LayoutOptions {
+      padding: true,
+      offset_comments: true,
+      static_asserts: true,
+    };
+
+    let mut buffer = Cursor::new(Vec::new());
+    let mut writer = CodeWriter::new(&mut buffer);
+    TargetAbi::ilp32_arm_eabi()
+      .write_struct(&mut writer, &graph, "msg", &options)
+      .unwrap();
+    let output = String::from_utf8(buffer.into_inner()).unwrap();
+    let expected = "struct msg {\n    uint8_t tag; /* offset 0 */\n    uint8_t pad0[3]; /* offset 1 */\n    uint32_t value; /* offset 4 */\n};\ntypedef struct msg msg;\n_Static_assert(sizeof(struct msg) == 8, \"struct msg must be 8 bytes\");\n_Static_assert(offsetof(struct msg, tag) == 0, \"tag must be at offset 0 in struct msg\");\n_Static_assert(offsetof(struct msg, pad0) == 1, \"pad0 must be at offset 1 in struct msg\");\n_Static_assert(offsetof(struct msg, value) == 4, \"value must be at offset 4 in struct msg\");\n";
+    assert_eq!(output, expected);
+
+    let mut buffer = Cursor::new(Vec::new());
+    let mut writer = CodeWriter::new(&mut buffer);
+    let layout = TargetAbi::ilp32_arm_eabi()
+      .with_pack(2)
+      .write_struct(&mut writer, &graph, "msg", &LayoutOptions::default())
+      .unwrap();
+    assert_eq!(layout.size, 6);
+    let output = String::from_utf8(buffer.into_inner()).unwrap();
+    assert!(output.starts_with("#pragma pack(push, 2)\nstruct msg {\n"));
+    assert!(output.contains("};\ntypedef struct msg msg;\n#pragma pack(pop)\n"));
+  }
+
+  #[test]
+  fn test_static_assert_styles() {
+    let invariants = [
+      Invariant::size_of("struct msg", 8),
+      Invariant::offset_of("struct msg", "value", 4),
+      Invariant::enum_count("color", "COLOR_COUNT", 3),
+      Invariant::macro_value("ERR_BASE", -100),
+      Invariant::custom("CHAR_BIT == 8", "bytes must have 8 bits"),
+    ];
+
+    let render = |style| {
+      let mut buffer = Cursor::new(Vec::new());
+      let mut writer = CodeWriter::new(&mut buffer);
+      writer.set_assert_style(style);
+      writer.write_static_asserts(&invariants).unwrap();
+      String::from_utf8(buffer.into_inner()).unwrap()
+    };
+
+    assert_eq!(
+      render(AssertStyle::StaticAssertC11),
+      "_Static_assert(sizeof(struct msg) == 8, \"struct msg must be 8 bytes\");\n\
+       _Static_assert(offsetof(struct msg, value) == 4, \"value must be at offset 4 in struct msg\");\n\
+       _Static_assert(COLOR_COUNT == 3, \"color must have 3 values\");\n\
+       _Static_assert(ERR_BASE == (-100), \"ERR_BASE must be -100\");\n\
+       _Static_assert(CHAR_BIT == 8, \"bytes must have 8 bits\");\n"
+    );
+    assert!(render(AssertStyle::StaticAssertCpp)
+      .starts_with("static_assert(sizeof(struct msg) == 8, \"struct msg must be 8 bytes\");\n"));
+    assert_eq!(
+      render(AssertStyle::NegativeArray),
+      "typedef char static_assert_sizeof_struct_msg_1[(sizeof(struct msg) == 8) ? 1 : -1];\n\
+       typedef char static_assert_offsetof_struct_msg_value_2[(offsetof(struct msg, value) == 4) ? 1 : -1];\n\
+       typedef char static_assert_count_COLOR_COUNT_3[(COLOR_COUNT == 3) ? 1 : -1];\n\
+       typedef char static_assert_value_ERR_BASE_4[(ERR_BASE == (-100)) ? 1 : -1];\n\
+       typedef char static_assert_bytes_must_have_8_bits_5[(CHAR_BIT == 8) ? 1 : -1];\n"
+    );
+
+    // Checks sharing a message still get distinct typedefs
+    let mut buffer = Cursor::new(Vec::new());
+    let mut writer = CodeWriter::new(&mut buffer);
+    writer.set_assert_style(AssertStyle::NegativeArray);
+    writer
+      .write_static_asserts(&[
+        Invariant::custom("A > 0", "must be positive"),
+        Invariant::custom("B > 0", "must be positive"),
+      ])
+      .unwrap();
+    assert_eq!(
+      String::from_utf8(buffer.into_inner()).unwrap(),
+      "typedef char static_assert_must_be_positive_1[(A > 0) ? 1 : -1];\n\
+       typedef char static_assert_must_be_positive_2[(B > 0) ? 1 : -1];\n"
+    );
+
+    // Headers included together keep their typedefs apart by scope
+    let mut buffer = Cursor::new(Vec::new());
+    let mut writer = CodeWriter::new(&mut buffer);
+    writer.set_assert_style(AssertStyle::NegativeArray);
+    writer.set_assert_scope("MOTOR_H");
+    writer
+      .write_static_assert(&Invariant::custom("A > 0", "must be positive"))
+      .unwrap();
+    assert_eq!(
+      String::from_utf8(buffer.into_inner()).unwrap(),
+      "typedef char static_assert_must_be_positive_MOTOR_H_1[(A > 0) ? 1 : -1];\n"
+    );
+
+    assert_eq!(
+      AssertStyle::for_standard(LanguageStandard::C99),
+      AssertStyle::NegativeArray
+    );
+    assert_eq!(
+      AssertStyle::for_standard(LanguageStandard::C11),
+      AssertStyle::StaticAssertC11
+    );
+    assert_eq!(
+      AssertStyle::for_standard(LanguageStandard::Cpp14),
+      AssertStyle::StaticAssertCpp
+    );
+  }
+
+  #[test]
+  fn test_layout_invariants_follow_assert_style() {
+    let mut graph = TypeGraph::new();
+    graph
+      .add_struct(
+        StructDef::new("pair")
+          .with_field(FieldDef::new("a", TypeRef::new("uint16_t")))
+          .with_field(FieldDef::new("b", TypeRef::new("uint32_t"))),
+      )
+      .unwrap();
+    let layout = TargetAbi::lp64_x86_64().layout(&graph, "pair").unwrap();
+    assert_eq!(
+      layout.invariants(),
+      vec![
+        Invariant::size_of("struct pair", 8),
+        Invariant::offset_of("struct pair", "a", 0),
+        Invariant::offset_of("struct pair", "b", 4),
+      ]
+    );
+
+    let mut buffer = Cursor::new(Vec::new());
+    let mut writer = CodeWriter::new(&mut buffer);
+    writer.set_assert_style(AssertStyle::StaticAssertCpp);
+    layout.write_static_asserts(&mut writer).unwrap();
+    let output = String::from_utf8(buffer.into_inner()).unwrap();
+    assert!(output.ends_with(
+      "static_assert(offsetof(struct pair, b) == 4, \"b must be at offset 4 in struct pair\");\n"
+    ));
+  }
+
+  #[test]
+  fn test_c89_output_and_rejected_constructs() {
+    let mut buffer = Cursor::new(Vec::new());
+    let mut writer = CodeWriter::new(&mut buffer);
+    writer.set_language_standard(Some(LanguageStandard::C89));
+
+    writer.write_comment("Generated").unwrap();
+    writer
+      .write_documented_variable("int", "count", "Number of items")
+      .unwrap();
+    writer.begin_if(&PpExpr::defined("DEBUG")).unwrap();
+    writer.end_if().unwrap();
+    writer
+      .write_static_assert(&Invariant::size_of("int", 4))
+      .unwrap();
+    assert_eq!(writer.null_pointer(), "NULL");
+    assert!(writer.bool_type().is_err());
+
+    match writer.begin_namespace("app") {
+      Err(CodeGenError::UnsupportedConstruct {
+        construct,
+        standard,
+      }) => {
+        assert_eq!(construct, "namespaces");
+        assert_eq!(standard, "C89");
+      }
+      other => panic!("expected an unsupported construct, got {:?}", other),
+    }
+    assert!(writer.begin_enum_class("color", None).is_err());
+    assert!(writer.write_template(&["typename T"]).is_err());
+    let options = TableOptions {
+      designated_indices: true,
+      ..Default::default()
+    };
+    assert!(writer.write_array("int", "t", &[1, 2], &options).is_err());
+    let helper = FunctionDef::new("int", "helper", &[]).with_storage(StorageClass::StaticInline);
+    assert!(helper.write_declaration(&mut writer).is_err());
+
+    let output = String::from_utf8(buffer.into_inner()).unwrap();
+    let expected = "/* Generated */\nint count; /**< Number of items */\n#if defined(DEBUG)\n#endif /* defined(DEBUG) */\ntypedef char static_assert_sizeof_int_1[(sizeof(int) == 4) ? 1 : -1];\n";
+    assert_eq!(output, expected);
+  }
+
+  #[test]
+  fn test_c89_rejects_mixed_declarations() {
+    let mut buffer = Cursor::new(Vec::new());
+    let mut writer = CodeWriter::new(&mut buffer);
+    writer.set_language_standard(Some(LanguageStandard::C89));
+
+    writer.begin_function("void", "run", &[]).unwrap();
+    writer.indent();
+    writer.write_variable("int", "a", Some("first")).unwrap();
+    writer.write_comment("then").unwrap();
+    writer.write_variable("int", "b", None).unwrap();
+    writer.writeln("a = 1;").unwrap();
+    assert!(writer.write_variable("int", "c", None).is_err());
+    writer.dedent();
+    writer.end_function().unwrap();
+
+    // Outside a function body and in later standards nothing is tracked
+    writer.write_variable("int", "global", None).unwrap();
+    writer.set_language_standard(Some(LanguageStandard::C99));
+    writer.begin_function("void", "run2", &[]).unwrap();
+    writer.writeln("run();").unwrap();
+    writer.write_variable("int", "c", None).unwrap();
+    writer.end_function().unwrap();
+    assert_eq!(writer.bool_type().unwrap(), "_Bool");
+  }
+
+  #[test]
+  fn test_cpp_standards() {
+    let render = |standard| {
+      let mut buffer = Cursor::new(Vec::new());
+      let mut writer = CodeWriter::new(&mut buffer);
+      writer.set_language_standard(Some(standard));
+      writer.begin_namespace("app::detail").unwrap();
+      writer.begin_enum_class("mode", Some("uint8_t")).unwrap();
+      writer.end_enum_class().unwrap();
+      writer.end_namespace("app::detail").unwrap();
+      writer
+        .write_static_assert(&Invariant::macro_value("VERSION", 2))
+        .unwrap();
+      assert_eq!(writer.null_pointer(), "nullptr");
+      assert_eq!(writer.bool_type().unwrap(), "bool");
+      String::from_utf8(buffer.into_inner()).unwrap()
+    };
+
+    assert_eq!(
+      render(LanguageStandard::Cpp14),
+      "namespace app {\nnamespace detail {\nenum class mode : uint8_t {\n};\n} // namespace detail\n} // namespace app\nstatic_assert(VERSION == 2, \"VERSION must be 2\");\n"
+    );
+    assert_eq!(
+      render(LanguageStandard::Cpp17),
+      "namespace app::detail {\nenum class mode : uint8_t {\n};\n} // namespace app::detail\nstatic_assert(VERSION == 2, \"VERSION must be 2\");\n"
+    );
+
+    let mut table = EnumTable::new("mode");
+    table.add_variant("MODE_OFF", Some(0));
+    let mut buffer = Cursor::new(Vec::new());
+    let mut writer = CodeWriter::new(&mut buffer);
+    writer.set_language_standard(Some(LanguageStandard::Cpp11));
+    table.write_definitions(&mut writer).unwrap();
+    let output = String::from_utf8(buffer.into_inner()).unwrap();
+    assert!(output.contains("if (str == nullptr) {"));
+  }
+
+  #[test]
+  fn test_attribute_rendering_per_compiler() {
+    let attrs = [
+      Attribute::Weak,
+      Attribute::Section(".fast".to_string()),
+      Attribute::Aligned(8),
+    ];
+    let target = AttributeTarget::Declaration;
+
+    let gcc = Compiler::GccClang.render(&attrs, target).unwrap();
+    assert_eq!(
+      gcc.prefixed("int x;"),
+      "__attribute__((weak, section(\".fast\"), aligned(8))) int x;"
+    );
+
+    let iar = Compiler::Iar.render(&attrs, target).unwrap();
+    assert_eq!(
+      iar.prefix,
+      "__weak _Pragma(\"location=\\\".fast\\\"\") _Pragma(\"data_alignment=8\")"
+    );
+
+    let armcc = Compiler::Armcc.render(&attrs, target).unwrap();
+    assert_eq!(
+      armcc.prefix,
+      "__weak __align(8) __attribute__((section(\".fast\")))"
+    );
+
+    let portable = Compiler::Portable("CG_".to_string())
+      .render(&attrs, target)
+      .unwrap();
+    assert_eq!(
+      portable.prefix,
+      "CG_WEAK CG_SECTION(\".fast\") CG_ALIGNED(8)"
+    );
+
+    match Compiler::Msvc.render(&attrs, target) {
+      Err(CodeGenError::UnsupportedAttribute {
+        attribute,
+        compiler,
+      }) => {
+        assert_eq!(attribute, "weak");
+        assert_eq!(compiler, "MSVC");
+      }
+      other => panic!("expected unsupported attribute, got {:?}", other),
+    }
+    assert!(Compiler::GccClang
+      .render(&[Attribute::Packed], AttributeTarget::Declaration)
+      .is_err());
+
+    // Aligned types
+    let aligned = [Attribute::Aligned(16)];
+    let target = AttributeTarget::Type;
+    let armcc = Compiler::Armcc.render(&aligned, target).unwrap();
+    assert_eq!(armcc.suffix, "__attribute__((aligned(16)))");
+    let portable = Compiler::Portable("CG_".to_string())
+      .render(&aligned, target)
+      .unwrap();
+    assert_eq!(
+      (portable.prefix.as_str(), portable.suffix.as_str()),
+      ("CG_ALIGNED_BEGIN(16)", "CG_ALIGNED_END(16)")
+    );
+    assert!(Compiler::Iar.render(&aligned, target).is_err());
+  }
+
+  #[test]
+  fn test_packed_struct_attributes() {
+    let def = StructDef::new("frame")
+      .with_field(FieldDef::new("id", TypeRef::new("uint8_t")))
+      .with_field(FieldDef::new("value", TypeRef::new("uint32_t")))
+      .with_attribute(Attribute::Packed);
+
+    let mut buffer = Cursor::new(Vec::new());
+    let mut writer = CodeWriter::new(&mut buffer);
+    def.write_definition(&mut writer, false).unwrap();
+    writer.set_compiler(Compiler::Msvc);
+    def.write_definition(&mut writer, false).unwrap();
+    writer.set_compiler(Compiler::Iar);
+    def.write_definition(&mut writer, false).unwrap();
+    let output = String::from_utf8(buffer.into_inner()).unwrap();
+    let body = "    uint8_t id;\n    uint32_t value;\n";
+    assert_eq!(
+      output,
+      format!(
+        "struct frame {{\n{0}}} __attribute__((packed));\n__pragma(pack(push, 1))\nstruct frame {{\n{0}}} __pragma(pack(pop));\n__packed\nstruct frame {{\n{0}}};\n",
+        body
+      )
+    );
+
+    let mut graph = TypeGraph::new();
+    graph.add_struct(def).unwrap();
+    let layout = TargetAbi::lp64_x86_64().layout(&graph, "frame").unwrap();
+    assert_eq!(layout.size, 5);
+    assert_eq!(layout.field("value").unwrap().offset, 1);
+  }
+
+  #[test]
+  fn test_function_attributes_and_portability_macros() {
+    let function = FunctionDef::new("void", "fatal_error", &[("int", "code")])
+      .with_attribute(Attribute::NoReturn)
+      .with_attribute(Attribute::Deprecated(Some("use panic".to_string())));
+
+    let mut buffer = Cursor::new(Vec::new());
+    let mut writer = CodeWriter::new(&mut buffer);
+    function.write_declaration(&mut writer).unwrap();
+    writer.set_compiler(Compiler::Portable("CG_".to_string()));
+    function.write_declaration(&mut writer).unwrap();
+    let output = String::from_utf8(buffer.into_inner()).unwrap();
+    assert_eq!(
+      output,
+      "__attribute__((noreturn, deprecated(\"use panic\"))) void fatal_error(int code);\nCG_NORETURN CG_DEPRECATED_MSG(\"use panic\") void fatal_error(int code);\n"
+    );
+
+    let mut buffer = Cursor::new(Vec::new());
+    let mut writer = CodeWriter::new(&mut buffer);
+    write_portability_macros(&mut writer, "CG_").unwrap();
+    let output = String::from_utf8(buffer.into_inner()).unwrap();
+    assert!(output.starts_with(
+      "#define CG_DO_PRAGMA(x) _Pragma(#x)\n\n#if defined(__CC_ARM)\n#define CG_PACKED_BEGIN __packed\n"
+    ));
+    assert!(output.contains("#elif defined(__ICCARM__)\n"));
+    assert!(output.contains("#define CG_SECTION(name) CG_DO_PRAGMA(location = name)\n"));
+    assert!(output.contains("#define CG_ALIGNED(n) CG_DO_PRAGMA(data_alignment = n)\n"));
+    assert!(output.contains("#define CG_ALIGNED_BEGIN(n) __declspec(align(n))\n"));
+    assert!(output.contains("#define CG_PACKED_END __attribute__((packed))\n"));
+    assert!(output.contains("#define CG_ALIGNED(n) __declspec(align(n))\n"));
+    assert!(!output.contains("#define CG_WEAK\n"));
+    assert!(
+      output.ends_with("#else\n#error \"Unsupported compiler\"\n#endif // defined(__CC_ARM)\n")
+    );
+  }
+
+  #[test]
+  fn test_expr_minimal_parentheses() {
+    let a = || Expr::ident("a");
+    let b = || Expr::ident("b");
+    let c = || Expr::ident("c");
+
+    let sum_times = Expr::binary(a(), BinaryOp::Add, b()).op(BinaryOp::Mul, c());
+    assert_eq!(sum_times.to_string(), "(a + b) * c");
+    let times_sum = a().op(BinaryOp::Mul, b()).op(BinaryOp::Add, c());
+    assert_eq!(times_sum.to_string(), "a * b + c");
+    let nested_sub = a().op(BinaryOp::Sub, b().op(BinaryOp::Sub, c()));
+    assert_eq!(nested_sub.to_string(), "a - (b - c)");
+    let mul_div = a().op(BinaryOp::Mul, b().op(BinaryOp::Div, c()));
+    assert_eq!(mul_div.to_string(), "a * (b / c)");
+    let chained_or = a().op(BinaryOp::BitOr, b()).op(BinaryOp::BitOr, c());
+    assert_eq!(chained_or.to_string(), "a | b | c");
+    let mask = a().op(BinaryOp::BitOr, b().op(BinaryOp::Shl, Expr::int(2)));
+    assert_eq!(mask.to_string(), "a | b << 2");
+    // Regrouping + or * could change overflow, so the grouping is kept
+    let nested_add = a().op(BinaryOp::Add, b().op(BinaryOp::Add, c()));
+    assert_eq!(nested_add.to_string(), "a + (b + c)");
+    let nested_mul = a().op(BinaryOp::Mul, b().op(BinaryOp::Mul, c()));
+    assert_eq!(nested_mul.to_string(), "a * (b * c)");
+    let nested_and = a().op(BinaryOp::And, b().op(BinaryOp::And, c()));
+    assert_eq!(nested_and.to_string(), "a && b && c");
+
+    // Only mixes that compilers warn about get extra parentheses
+    let x_and_y = a()
+      .op(BinaryOp::Eq, Expr::int(1))
+      .op(BinaryOp::And, b().op(BinaryOp::Eq, Expr::int(2)));
+    assert_eq!(x_and_y.to_string(), "a == 1 && b == 2");
+    let less = a().op(BinaryOp::Lt, b().op(BinaryOp::Add, Expr::int(1)));
+    assert_eq!(less.to_string(), "a < b + 1");
+    let shift = a().op(BinaryOp::Shl, b().op(BinaryOp::Add, Expr::int(1)));
+    assert_eq!(shift.to_string(), "a << (b + 1)");
+    let logic = a().op(BinaryOp::And, b()).op(BinaryOp::Or, c());
+    assert_eq!(logic.to_string(), "(a && b) || c");
+    let flags = a().op(BinaryOp::BitAnd, b()).op(BinaryOp::BitOr, c());
+    assert_eq!(flags.to_string(), "(a & b) | c");
+    let test = a().op(BinaryOp::BitAnd, b().op(BinaryOp::Eq, c()));
+    assert_eq!(test.to_string(), "a & (b == c)");
+
+    assert_eq!(
+      Expr::unary(UnaryOp::Neg, Expr::int(-5)).to_string(),
+      "-(-5)"
+    );
+    assert_eq!(
+      Expr::unary(UnaryOp::BitNot, a().op(BinaryOp::Add, b())).to_string(),
+      "~(a + b)"
+    );
+    assert_eq!(
+      Expr::unary(UnaryOp::Deref, Expr::ident("p"))
+        .member("x")
+        .to_string(),
+      "(*p).x"
+    );
+    assert_eq!(
+      a().op(BinaryOp::Add, b()).cast("uint8_t").to_string(),
+      "(uint8_t)(a + b)"
+    );
+    assert_eq!(
+      Expr::ident("regs")
+        .arrow("data")
+        .index(Expr::int(3))
+        .to_string(),
+      "regs->data[3]"
+    );
+    let ternary = Expr::ternary(
+      a().op(BinaryOp::Gt, Expr::int(0)),
+      Expr::call("f", vec![a(), Expr::string("x\n")]),
+      Expr::ternary(b(), c(), Expr::int(0)),
+    );
+    assert_eq!(ternary.to_string(), "a > 0 ? f(a, \"x\\n\") : b ? c : 0");
+    assert_eq!(
+      Expr::ternary(ternary, a(), b()).to_string(),
+      "(a > 0 ? f(a, \"x\\n\") : b ? c : 0) ? a : b"
+    );
+  }
+
+  #[test]
+  fn test_expr_macros() {
+    let mut buffer = Cursor::new(Vec::new());
+    let mut writer = CodeWriter::new(&mut buffer);
+    let mask = Expr::ident("A").op(
+      BinaryOp::BitOr,
+      Expr::ident("B").op(BinaryOp::Shl, Expr::int(2)),
+    );
+    writer.write_define_expr("MASK", &mask).unwrap();
+    writer
+      .write_define_expr(
+        "LIMIT",
+        &Expr::typed_int(-2147483648, CType::Int32).unwrap(),
+      )
+      .unwrap();
+    writer.write_define_expr("ONE", &Expr::int(1)).unwrap();
+    let square = Expr::param("x").op(BinaryOp::Mul, Expr::param("x"));
+    writer
+      .write_macro(&FunctionMacro::from_expr("SQUARE", &["x"], &square))
+      .unwrap();
+    let output = String::from_utf8(buffer.into_inner()).unwrap();
+    assert_eq!(
+      output,
+      "#define MASK (A | B << 2)\n#define LIMIT (-2147483647 - 1)\n#define ONE 1\n#define SQUARE(x) ((x) * (x))\n"
+    );
+  }
+
+  #[derive(Serialize)]
+  enum UartMode {
+    Polling,
+    Dma,
+  }
+
+  #[derive(Serialize)]
+  struct UartConfig {
+    baud: u32,
+    mode: UartMode,
+  }
+
+  #[derive(Serialize)]
+  struct DeviceConfig {
+    name: String,
+    gain: f32,
+    offset: i64,
+    enabled: bool,
+    pins: [u8; 3],
+    uart: UartConfig,
+    channels: Vec<(char, u16)>,
+  }
+
+  #[test]
+  fn test_serialize_designated_initializer() {
+    let config = DeviceConfig {
+      name: "dev \"0\"".to_string(),
+      gain: 1.5,
+      offset: -5_000_000_000,
+      enabled: true,
+      pins: [4, 5, 6],
+      uart: UartConfig {
+        baud: 3_000_000_000,
+        mode: UartMode::Dma,
+      },
+      channels: vec![('a', 1), ('b', 2)],
+    };
+
+    let mut buffer = Cursor::new(Vec::new());
+    let mut writer = CodeWriter::new(&mut buffer);
+    writer
+      .write_serialized(
+        "static const device_config_t config",
+        &config,
+        &InitializerOptions::default(),
+      )
+      .unwrap();
+    let output = String::from_utf8(buffer.into_inner()).unwrap();
+    assert_eq!(
+      output,
+      r#"static const device_config_t config = {
+    .name = "dev \"0\"",
+    .gain = 1.5f,
+    .offset = -5000000000LL,
+    .enabled = true,
+    .pins = { 4, 5, 6 },
+    .uart = {
+        .baud = 3000000000U,
+        .mode = UART_MODE_DMA,
+    },
+    .channels = {
+        { 'a', 1 },
+        { 'b', 2 },
+    },
+};
+"#
+    );
+
+    // C++20 has member designators but still no array designators
+    let uart = UartConfig {
+      baud: 9600,
+      mode: UartMode::Dma,
+    };
+    let mut writer = CodeWriter::new(Cursor::new(Vec::new()));
+    writer.set_language_standard(Some(LanguageStandard::Cpp17));
+    assert!(writer
+      .write_serialized(
+        "const uart_config_t uart",
+        &uart,
+        &InitializerOptions::default()
+      )
+      .is_err());
+    writer.set_language_standard(Some(LanguageStandard::Cpp20));
+    writer
+      .write_serialized(
+        "const uart_config_t uart",
+        &uart,
+        &InitializerOptions::default(),
+      )
+      .unwrap();
+    let options = TableOptions {
+      designated_indices: true,
+      ..Default::default()
+    };
+    assert!(writer.write_array("int", "t", &[1, 2], &options).is_err());
+
+    // Member designators alone are fine in a C++20 table
+    let options = TableOptions {
+      designated_fields: true,
+      ..Default::default()
+    };
+    let points = [StructValue::new().with_field("x", 1u32)];
+    writer
+      .write_array("Point", "points", &points, &options)
+      .unwrap();
+    writer.set_language_standard(Some(LanguageStandard::C89));
+    assert!(writer
+      .write_array("Point", "points", &points, &options)
+      .is_err());
+  }
+
+  #[test]
+  fn test_serialize_positional_initializer() {
+    let options = InitializerOptions {
+      designated: false,
+      format: NumberFormat::Hex { width: 2 },
+      prefix_variants: false,
+      ..Default::default()
+    };
+    let uart = UartConfig {
+      baud: 255,
+      mode: UartMode::Polling,
+    };
+    assert_eq!(
+      Initializer::from_value(&uart, &options).unwrap(),
+      Initializer::Struct(vec![
+        ("baud".to_string(), Initializer::Scalar("0xFF".to_string())),
+        (
+          "mode".to_string(),
+          Initializer::Scalar("POLLING".to_string())
+        ),
+      ])
+    );
+
+    let mut buffer = Cursor::new(Vec::new());
+    let mut writer = CodeWriter::new(&mut buffer);
+    writer
+      .write_serialized("const uart_config_t uarts[]", &[&uart, &uart], &options)
+      .unwrap();
+    let output = String::from_utf8(buffer.into_inner()).unwrap();
+    assert_eq!(
+      output,
+      "const uart_config_t uarts[] = {\n    {\n        0xFF,\n        POLLING,\n    },\n    {\n        0xFF,\n        POLLING,\n    },\n};\n"
+    );
+
+    let mut buffer = Cursor::new(Vec::new());
+    let mut writer = CodeWriter::new(&mut buffer);
+    writer.set_language_standard(Some(LanguageStandard::C89));
+    assert!(matches!(
+      writer.write_serialized(
+        "const uart_config_t uart",
+        &uart,
+        &InitializerOptions::default()
+      ),
+      Err(CodeGenError::UnsupportedConstruct { .. })
+    ));
+    assert!(matches!(
+      writer.write_serialized("const int x", &None::<u8>, &options),
+      Err(CodeGenError::Serialization(_))
+    ));
+
+    // Lists hold no designators, and C89 has no true/false
+    writer
+      .write_serialized(
+        "const int flags[]",
+        &[true, false],
+        &InitializerOptions::default(),
+      )
+      .unwrap();
+    let output = String::from_utf8(buffer.into_inner()).unwrap();
+    assert_eq!(output, "const int flags[] = { 1, 0 };\n");
+
+    // Long lists wrap like data tables
+    let options = InitializerOptions {
+      per_line: 4,
+      ..Default::default()
+    };
+    let mut buffer = Cursor::new(Vec::new());
+    let mut writer = CodeWriter::new(&mut buffer);
+    writer.set_language_standard(Some(LanguageStandard::C99));
+    writer
+      .write_serialized(
+        "const uint16_t levels[]",
+        &[1u16, 20, 300, 4000, 50000],
+        &options,
+      )
+      .unwrap();
+    writer
+      .write_serialized("const _Bool on", &true, &options)
+      .unwrap();
+    let output = String::from_utf8(buffer.into_inner()).unwrap();
+    assert_eq!(
+      output,
+      "const uint16_t levels[] = {\n        1,    20,   300,  4000,\n    50000,\n};\nconst _Bool on = 1;\n"
+    );
+  }
+
+  const DEVICE_SCHEMA: &str = r#"
+[header]
+guard = "DEVICE_H"
+includes = ["<stdint.h>", "board.h"]
+
+[[constants]]
+name = "DEVICE_MAX"
+value = 4
+type = "uint32_t"
+doc = "Maximum number of devices"
+
+[[constants]]
+name = "DEVICE_NAME_LEN"
+value = "(DEVICE_MAX * 4)"
+
+[[enums]]
+name = "device_state"
+typedef = true
+values = [
+  { name = "DEVICE_OFF", value = 0 },
+  { name = "DEVICE_ON", doc = "Powered" },
+]
+
+[[structs]]
+name = "device"
+typedef = true
+fields = [
+  { name = "bus", type = "struct bus *" },
+  { name = "name", type = "char", array = "DEVICE_NAME_LEN" },
+  { name = "state", type = "device_state" },
+]
+
+[[structs]]
+name = "bus"
+fields = [{ name = "id", type = "uint8_t", bits = 4 }]
+
+[[functions]]
+name = "device_open"
+returns = "int"
+params = [{ name = "dev", type = "device *", doc = "The device" }]
+"#;
+
+  #[test]
+  fn test_schema_header_from_toml() {
+    let schema = Schema::from_toml_str(DEVICE_SCHEMA).unwrap();
+    let mut buffer = Cursor::new(Vec::new());
+    let mut writer = CodeWriter::new(&mut buffer);
+    let scaffold = schema.scaffold(std::path::Path::new("device.h")).unwrap();
+    let mut sections = UserSectionManager::new();
+    scaffold.define_sections(&mut sections);
+    scaffold
+      .write(&mut writer, &sections, |w| schema.write_declarations(w))
+      .unwrap();
+    let output = String::from_utf8(buffer.into_inner()).unwrap();
+
+    assert!(output.contains("#ifndef DEVICE_H\n#define DEVICE_H\n"));
+    assert!(output.contains("#include <stdint.h>\n\n#include \"board.h\"\n"));
+    assert!(output.contains(
+      "/**\n * @brief Maximum number of devices\n */\n#define DEVICE_MAX 4U\n#define DEVICE_NAME_LEN (DEVICE_MAX * 4)\n"
+    ));
+    assert!(output.contains(
+      "enum device_state {\n    DEVICE_OFF = 0,\n    DEVICE_ON, ///< Powered\n};\ntypedef enum device_state device_state;\n"
+    ));
+    assert!(output.contains("struct bus;\n"));
+    assert!(output.contains("    char name[DEVICE_NAME_LEN];\n"));
+    assert!(output.contains("/**\n * @param dev The device\n */\n"));
+    assert!(output.contains("int device_open(device * dev);\n"));
+  }
+
+  #[test]
+  fn test_schema_errors_have_locations() {
+    let err = Schema::from_toml_str("[[structs]]\nname = \"a\"\nfeilds = []\n").unwrap_err();
+    match err {
+      CodeGenError::Schema { location, message } => {
+        assert!(location.starts_with("line "), "{}", location);
+        assert!(message.contains("feilds"), "{}", message);
+      }
+      other => panic!("unexpected error {:?}", other),
+    }
+
+    let json = r#"{"structs": [{"name": "a", "fields": [
+      {"name": "x", "type": "int"},
+      {"name": "x", "type": "int"}
+    ]}]}"#;
+    assert_eq!(
+      Schema::from_json_str(json).unwrap_err().to_string(),
+      "Schema error at structs[0].fields[1].name: 'x' is already defined at structs[0].fields[0]"
+    );
+
+    let json = r#"{"constants": [{"name": "BIG", "value": 300, "type": "uint8_t"}]}"#;
+    assert_eq!(
+      Schema::from_json_str(json).unwrap_err().to_string(),
+      "Schema error at constants[0].value: Value 300 does not fit in type uint8_t"
+    );
+
+    let json =
+      r#"{"functions": [{"name": "f", "params": [{"name": "p", "type": "char * const"}]}]}"#;
+    assert_eq!(
+      Schema::from_json_str(json).unwrap_err().to_string(),
+      "Schema error at functions[0].params[0].type: invalid type 'char * const'"
+    );
+
+    // extern "C" headers are also compiled as C++
+    let json = r#"{"functions": [{"name": "f", "params": [{"name": "class", "type": "int"}]}]}"#;
+    assert_eq!(
+      Schema::from_json_str(json).unwrap_err().to_string(),
+      "Schema error at functions[0].params[0].name: 'class' is a C++ keyword"
+    );
+    let json = r#"{"header": {"extern_c": false},
+      "functions": [{"name": "f", "params": [{"name": "class", "type": "int"}]}]}"#;
+    assert!(Schema::from_json_str(json).is_ok());
+
+    let json = r#"{"constants": [{"name": "_Max", "value": 1}]}"#;
+    assert_eq!(
+      Schema::from_json_str(json).unwrap_err().to_string(),
+      "Schema error at constants[0].name: '_Max' is reserved for the implementation"
+    );
+
+    // A typedef name shares the namespace of functions and constants
+    let json = r#"{"structs": [{"name": "point", "typedef": true, "fields": [{"name": "x", "type": "int"}]}],
+      "functions": [{"name": "point"}]}"#;
+    assert_eq!(
+      Schema::from_json_str(json).unwrap_err().to_string(),
+      "Schema error at functions[0].name: 'point' is already defined at structs[0]"
+    );
+
+    let json = "{\"enums\": [{\"name\": \"e\",\n \"values\": 3}]}";
+    match Schema::from_json_str(json).unwrap_err() {
+      CodeGenError::Schema { location, .. } => assert_eq!(location, "line 2, column 12"),
+      other => panic!("unexpected error {:?}", other),
+    }
+  }
+
+  #[test]
+  fn test_schema_generate_preserves_sections() {
+    let dir = tempdir().unwrap();
+    let schema_path = dir.path().join("device.toml");
+    let header_path = dir.path().join("device.h");
+    fs::write(&schema_path, DEVICE_SCHEMA).unwrap();
+
+    let schema = Schema::load(&schema_path).unwrap();
+    schema.generate(&header_path).unwrap();
+    let generated = fs::read_to_string(&header_path).unwrap();
+    assert!(generated.contains("#ifndef DEVICE_H\n#define DEVICE_H\n"));
+    assert!(generated.contains("#include \"board.h\"\n"));
+    assert!(generated.contains("typedef enum device_state device_state;\n"));
+
+    fs::write(
+      &header_path,
+      generated.replace(
+        "/* USER CODE BEGIN Declarations */\n",
+        "/* USER CODE BEGIN Declarations */\nvoid device_extra(void);\n",
+      ),
+    )
+    .unwrap();
+    schema.generate(&header_path).unwrap();
+    let regenerated = fs::read_to_string(&header_path).unwrap();
+    assert!(regenerated.contains("void device_extra(void);\n/* USER CODE END Declarations */"));
+
+    let bad_path = dir.path().join("bad.json");
+    fs::write(
+      &bad_path,
+      "{\"enums\": [{\"name\": \"e\", \"values\": []}]}",
+    )
+    .unwrap();
+    assert_eq!(
+      Schema::load(&bad_path).unwrap_err().to_string(),
+      format!(
+        "Schema error at {}, enums[0].values: an enum needs at least one value",
+        bad_path.display()
+      )
+    );
+  }
+
+  const UART_SVD: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<device schemaVersion="1.3">
+  <name>DEMO</name>
+  <size>32</size>
+  <access>read-write</access>
+  <peripherals>
+    <peripheral>
+      <name>UART0</name>
+      <description>Universal
+        asynchronous receiver</description>
+      <baseAddress>0x40001000</baseAddress>
+      <registers>
+        <register>
+          <name>CR</name>
+          <description>Control</description>
+          <addressOffset>0x0</addressOffset>
+          <fields>
+            <field><name>EN</name><bitOffset>0</bitOffset><bitWidth>1</bitWidth></field>
+            <field><name>MODE</name><bitRange>[5:4]</bitRange></field>
+          </fields>
+        </register>
+        <register>
+          <name>SR</name>
+          <addressOffset>0x4</addressOffset>
+          <access>read-only</access>
+        </register>
+        <register>
+          <name>DATA[%s]</name>
+          <addressOffset>0x10</addressOffset>
+          <dim>2</dim>
+          <dimIncrement>4</dimIncrement>
+        </register>
+      </registers>
+    </peripheral>
+    <peripheral derivedFrom="UART0">
+      <name>UART1</name>
+      <baseAddress>0x40002000</baseAddress>
+    </peripheral>
+  </peripherals>
+</device>
+"#;
+
+  #[test]
+  fn test_svd_register_map_mask_shift() {
+    let device = SvdDevice::parse(UART_SVD).unwrap();
+    let uart0 = device.peripheral("UART0").unwrap();
+    assert_eq!(uart0.registers[1].access, RegisterAccess::ReadOnly);
+    assert_eq!(uart0.registers[2].dim, Some(2));
+    assert_eq!(
+      device
+        .register_source(device.peripheral("UART1").unwrap())
+        .name,
+      "UART0"
+    );
+
+    let mut buffer = Cursor::new(Vec::new());
+    let mut writer = CodeWriter::new(&mut buffer);
+    device
+      .write_register_map(&mut writer, &RegisterMapOptions::default())
+      .unwrap();
+    let output = String::from_utf8(buffer.into_inner()).unwrap();
+
+    assert!(output.contains(concat!(
+      "/**\n * @brief Universal asynchronous receiver (UART0)\n */\n",
+      "struct UART0_Type {\n",
+      "    volatile uint32_t CR; /* offset 0x000 */ ///< Control\n",
+      "    const volatile uint32_t SR; /* offset 0x004 */\n",
+      "    uint8_t RESERVED0[8]; /* offset 0x008 */\n",
+      "    volatile uint32_t DATA[2]; /* offset 0x010 */\n",
+      "};\n",
+      "typedef struct UART0_Type UART0_Type;\n",
+    )));
+    assert!(output.contains("_Static_assert(offsetof(UART0_Type, DATA) == 16"));
+    assert!(output.contains("_Static_assert(sizeof(UART0_Type) == 24"));
+    assert!(output.contains(concat!(
+      "#define UART0_CR_MODE_Pos 4U\n",
+      "#define UART0_CR_MODE_Msk (0x3UL << UART0_CR_MODE_Pos)\n",
+    )));
+    assert!(output.contains("#define UART1_BASE 0x40002000UL\n"));
+    assert!(output.contains("#define UART1 ((UART0_Type *)UART1_BASE)\n"));
+    assert!(!output.contains("UART1_Type"));
+
+    // Scaled integers: a + sign, size suffixes and don't-care bits
+    let scaled = UART_SVD
+      .replace("<baseAddress>0x40002000", "<baseAddress>+64k")
+      .replace(
+        "<addressOffset>0x4</addressOffset>",
+        "<addressOffset>#1x0</addressOffset>",
+      );
+    let device = SvdDevice::parse(&scaled).unwrap();
+    assert_eq!(device.peripheral("UART1").unwrap().base_address, 0x10000);
+    assert_eq!(
+      device.peripheral("UART0").unwrap().registers[1].address_offset,
+      4
+    );
+  }
+
+  #[test]
+  fn test_svd_register_map_bitfields() {
+    let device = SvdDevice::parse(UART_SVD).unwrap();
+    let mut buffer = Cursor::new(Vec::new());
+    let mut writer = CodeWriter::new(&mut buffer);
+    let options = RegisterMapOptions {
+      field_style: FieldStyle::Bitfields,
+      layout_checks: false,
+    };
+    device.write_register_map(&mut writer, &options).unwrap();
+    let output = String::from_utf8(buffer.into_inner()).unwrap();
+
+    assert!(output.contains(concat!(
+      "struct UART0_CR_Bits {\n",
+      "    uint32_t EN : 1;\n",
+      "    uint32_t : 3;\n",
+      "    uint32_t MODE : 2;\n",
+      "    uint32_t : 26;\n",
+      "};\n",
+    )));
+    assert!(output.contains(concat!(
+      "union UART0_CR_Type {\n",
+      "    UART0_CR_Bits bit;\n",
+      "    uint32_t reg;\n",
+      "};\n",
+    )));
+    assert!(output.contains("    volatile UART0_CR_Type CR; /* offset 0x000 */ ///< Control\n"));
+    assert!(!output.contains("_Pos"));
+    assert!(!output.contains("_Static_assert"));
+  }
+
+  #[test]
+  fn test_svd_errors_have_locations() {
+    let overlapping = UART_SVD.replace("[5:4]", "[1:0]");
+    assert_eq!(
+      SvdDevice::parse(&overlapping).unwrap_err().to_string(),
+      "SVD error at UART0.CR.MODE: field overlaps another field"
+    );
+
+    let reserved = UART_SVD.replace("<name>SR</name>", "<name>_SR</name>");
+    assert_eq!(
+      SvdDevice::parse(&reserved).unwrap_err().to_string(),
+      "SVD error at UART0._SR: '_SR' is reserved for the implementation"
+    );
+
+    let missing = UART_SVD.replace("<baseAddress>0x40002000</baseAddress>", "");
+    assert_eq!(
+      SvdDevice::parse(&missing).unwrap_err().to_string(),
+      "SVD error at line 35, column 5: <peripheral> is missing <baseAddress>"
+    );
+
+    match SvdDevice::parse("<device><name>X</name>").unwrap_err() {
+      CodeGenError::Svd { location, .. } => assert!(location.starts_with("line 1"), "{}", location),
+      other => panic!("unexpected error: {}", other),
+    }
+
+    let overlapping = UART_SVD.replace(
+      "<addressOffset>0x4</addressOffset>",
+      "<addressOffset>0x2</addressOffset>",
+    );
+    let device = SvdDevice::parse(&overlapping).unwrap();
+    let mut writer = CodeWriter::new(Cursor::new(Vec::new()));
+    assert_eq!(
+      device
+        .write_register_map(&mut writer, &RegisterMapOptions::default())
+        .unwrap_err()
+        .to_string(),
+      "SVD error at UART0.SR: register at offset 0x2 overlaps the previous register"
+    );
+
+    let empty = UART_SVD.replace(
+      "<register>\n          <name>SR</name>",
+      "<cluster><name>C</name><addressOffset>0x8</addressOffset></cluster>\n        <register>\n          <name>SR</name>",
+    );
+    assert_eq!(
+      SvdDevice::parse(&empty).unwrap_err().to_string(),
+      "SVD error at UART0.C: cluster has no registers"
+    );
+  }
+
+  #[test]
+  fn test_svd_clusters_and_alternate_registers() {
+    let svd = UART_SVD.replace(
+      "<register>\n          <name>SR</name>",
+      concat!(
+        "<register><name>CR_ALT</name><alternateRegister>CR</alternateRegister>",
+        "<addressOffset>0x0</addressOffset></register>\n",
+        "<cluster><name>CH[%s]</name><description>Channel</description>",
+        "<dim>2</dim><dimIncrement>8</dimIncrement><addressOffset>0x20</addressOffset>",
+        "<register><name>CFG</name><addressOffset>0x0</addressOffset>",
+        "<fields><field><name>ON</name><bitRange>[0:0]</bitRange></field></fields></register>",
+        "</cluster>\n",
+        "        <register>\n          <name>SR</name>",
+      ),
+    );
+    let device = SvdDevice::parse(&svd).unwrap();
+    let uart0 = device.peripheral("UART0").unwrap();
+    assert!(uart0.registers[1].alternate);
+    assert_eq!(uart0.clusters[0].dim, Some(2));
+
+    let mut buffer = Cursor::new(Vec::new());
+    let mut writer = CodeWriter::new(&mut buffer);
+    device
+      .write_register_map(&mut writer, &RegisterMapOptions::default())
+      .unwrap();
+    let output = String::from_utf8(buffer.into_inner()).unwrap();
+
+    assert!(output.contains(concat!(
+      "/**\n * @brief Channel\n */\n",
+      "struct UART0_CH_Type {\n",
+      "    volatile uint32_t CFG; /* offset 0x000 */\n",
+      "    uint8_t RESERVED0[4]; /* offset 0x004 */\n",
+      "};\n",
+    )));
+    assert!(output.contains("_Static_assert(sizeof(UART0_CH_Type) == 8"));
+    assert!(output.contains("#define UART0_CH_CFG_ON_Pos 0U\n"));
+    assert!(output.contains(concat!(
+      "struct UART0_Type {\n",
+      "    union { /* offset 0x000 */\n",
+      "        volatile uint32_t CR; ///< Control\n",
+      "        volatile uint32_t CR_ALT;\n",
+      "    };\n",
+      "    const volatile uint32_t SR; /* offset 0x004 */\n",
+      "    uint8_t RESERVED0[8]; /* offset 0x008 */\n",
+      "    volatile uint32_t DATA[2]; /* offset 0x010 */\n",
+      "    uint8_t RESERVED1[8]; /* offset 0x018 */\n",
+      "    UART0_CH_Type CH[2]; /* offset 0x020 */ ///< Channel\n",
+      "};\n",
+    )));
+    assert!(output.contains("_Static_assert(offsetof(UART0_Type, CR_ALT) == 0"));
+    assert!(output.contains("_Static_assert(sizeof(UART0_Type) == 48"));
+
+    // Anonymous unions need C11
+    let mut writer = CodeWriter::new(Cursor::new(Vec::new()));
+    writer.set_language_standard(Some(LanguageStandard::C99));
+    assert!(device
+      .write_register_map(&mut writer, &RegisterMapOptions::default())
+      .is_err());
+  }
+
+  #[test]
+  fn test_svd_generate_preserves_sections() {
+    let dir = tempdir().unwrap();
+    let svd_path = dir.path().join("demo.svd");
+    let header_path = dir.path().join("demo.h");
+    fs::write(&svd_path, UART_SVD).unwrap();
+
+    let device = SvdDevice::load(&svd_path).unwrap();
+    let options = RegisterMapOptions::default();
+    device.generate(&header_path, &options).unwrap();
+    let generated = fs::read_to_string(&header_path).unwrap();
+    assert!(generated.contains("#ifndef DEMO_H\n#define DEMO_H\n"));
+    assert!(generated.contains("#include <stddef.h>\n#include <stdint.h>\n"));
+    assert!(generated.contains("#define UART1 ((UART0_Type *)UART1_BASE)\n"));
+
+    // Hand-added helpers survive regeneration
+    fs::write(
+      &header_path,
+      generated.replace(
+        "/* USER CODE BEGIN Declarations */\n",
+        "/* USER CODE BEGIN Declarations */\n#define UART0_ENABLE() (UART0->CR |= UART0_CR_EN_Msk)\n",
+      ),
+    )
+    .unwrap();
+    device.generate(&header_path, &options).unwrap();
+    let regenerated = fs::read_to_string(&header_path).unwrap();
+    assert!(regenerated.contains("#define UART0_ENABLE()"));
+  }
+
+  fn control_register() -> RegisterDef {
+    RegisterDef::new("Ctrl", 16)
+      .with_field(BitField::new("Enable", 0, 1).with_doc("Peripheral enable"))
+      .with_field(BitField::new("Mode", 4, 3))
+      .with_field(BitField::new("Busy", 8, 1).with_access(RegisterAccess::ReadOnly))
+      .with_field(BitField::new("Clear", 15, 1).with_access(RegisterAccess::WriteOnly))
+  }
+
+  #[test]
+  fn test_register_accessors() {
+    let mut buffer = Cursor::new(Vec::new());
+    let mut writer = CodeWriter::new(&mut buffer);
+    control_register()
+      .write_accessors(&mut writer, &AccessorOptions::default())
+      .unwrap();
+    let output = String::from_utf8(buffer.into_inner()).unwrap();
+
+    assert!(output.contains(concat!(
+      "/**\n * @brief Peripheral enable\n */\n",
+      "#define CTRL_ENABLE_Pos 0U\n",
+      "#define CTRL_ENABLE_Msk (0x1UL << CTRL_ENABLE_Pos)\n",
+      "#define CTRL_MODE_Pos 4U\n",
+      "#define CTRL_MODE_Msk (0x7UL << CTRL_MODE_Pos)\n",
+    )));
+    assert!(output.contains(concat!(
+      "static inline uint16_t ctrl_mode_get(uint16_t reg) {\n",
+      "    return (uint16_t)((reg & CTRL_MODE_Msk) >> CTRL_MODE_Pos);\n",
+      "}\n",
+    )));
+    assert!(output.contains(concat!(
+      "static inline uint16_t ctrl_mode_set(uint16_t reg, uint16_t value) {\n",
+      "    return (uint16_t)((reg & ~CTRL_MODE_Msk) | (value << CTRL_MODE_Pos & CTRL_MODE_Msk));\n",
+      "}\n",
+    )));
+    // Clear is write-only, so reading the register back is undefined
+    assert!(!output.contains("_modify"));
+
+    let mut buffer = Cursor::new(Vec::new());
+    let mut writer = CodeWriter::new(&mut buffer);
+    RegisterDef::new("Ctrl", 16)
+      .with_field(BitField::new("Mode", 4, 3))
+      .with_field(BitField::new("Busy", 8, 1).with_access(RegisterAccess::ReadOnly))
+      .write_accessors(&mut writer, &AccessorOptions::default())
+      .unwrap();
+    let output = String::from_utf8(buffer.into_inner()).unwrap();
+    assert!(output.contains(concat!(
+      "static inline void ctrl_mode_modify(volatile uint16_t * reg, uint16_t value) {\n",
+      "    *reg = ctrl_mode_set(*reg, value);\n",
+      "}\n",
+    )));
+  }
+
+  #[test]
+  fn test_register_access_enforcement() {
+    let names: Vec<String> = control_register()
+      .accessors(&AccessorOptions::default())
+      .into_iter()
+      .map(|f| f.name)
+      .collect();
+    assert!(names.contains(&"ctrl_busy_get".to_string()));
+    assert!(!names.contains(&"ctrl_busy_set".to_string()));
+    assert!(!names.contains(&"ctrl_busy_modify".to_string()));
+    assert!(names.contains(&"ctrl_clear_set".to_string()));
+    assert!(!names.contains(&"ctrl_clear_get".to_string()));
+    assert!(!names.contains(&"ctrl_clear_modify".to_string()));
+
+    let write_only = control_register().with_access(RegisterAccess::WriteOnly);
+    let names: Vec<String> = write_only
+      .accessors(&AccessorOptions::default())
+      .into_iter()
+      .map(|f| f.name)
+      .collect();
+    assert!(names.iter().all(|n| !n.ends_with("_modify")));
+    assert!(names.contains(&"ctrl_mode_set".to_string()));
+    assert!(!names.contains(&"ctrl_mode_get".to_string()));
+
+    let read_only = RegisterDef::new("Sr", 32)
+      .with_access(RegisterAccess::ReadOnly)
+      .with_field(BitField::new("Ready", 0, 1));
+    let names: Vec<String> = read_only
+      .accessors(&AccessorOptions::default())
+      .into_iter()
+      .map(|f| f.name)
+      .collect();
+    assert_eq!(names, vec!["sr_ready_get".to_string()]);
+
+    let mut buffer = Cursor::new(Vec::new());
+    let mut writer = CodeWriter::new(&mut buffer);
+    writer.set_language_standard(Some(LanguageStandard::C89));
+    assert!(control_register()
+      .write_accessors(&mut writer, &AccessorOptions::default())
+      .is_err());
+    let options = AccessorOptions {
+      functions: false,
+      ..AccessorOptions::default()
+    };
+    control_register()
+      .write_accessors(&mut writer, &options)
+      .unwrap();
+  }
+
+  #[test]
+  fn test_register_validation() {
+    let overlapping = control_register().with_field(BitField::new("Extra", 5, 2));
+    assert_eq!(
+      overlapping.validate().unwrap_err().to_string(),
+      "Invalid definition: register 'Ctrl': field 'Extra' overlaps another field"
+    );
+    let too_wide = RegisterDef::new("Ctrl", 8).with_field(BitField::new("Wide", 4, 5));
+    assert!(too_wide.validate().is_err());
+    assert!(RegisterDef::new("Ctrl", 24).validate().is_err());
+
+    let naming =
+      NamingConvention::new(Case::ScreamingSnake, LanguageStandard::C99).with_prefix("HW_");
+    let options = AccessorOptions {
+      macro_naming: naming,
+      ..AccessorOptions::default()
+    };
+    let mut buffer = Cursor::new(Vec::new());
+    let mut writer = CodeWriter::new(&mut buffer);
+    RegisterDef::new("Status", 64)
+      .with_field(BitField::new("Count", 32, 32))
+      .write_accessors(&mut writer, &options)
+      .unwrap();
+    let output = String::from_utf8(buffer.into_inner()).unwrap();
+    assert!(output.contains("#define HW_STATUS_COUNT_Msk (0xFFFFFFFFULL << HW_STATUS_COUNT_Pos)\n"));
+    assert!(output.contains("static inline uint64_t status_count_get(uint64_t reg)"));
+  }
 }
@@ -0,0 +1,578 @@
+use std::collections::{HashMap, HashSet};
+use std::io::Write;
+
+use crate::attribute::{Attribute, AttributeTarget};
+use crate::code_writer::CodeWriter;
+use crate::doc_comment::DocComment;
+use crate::error::{CodeGenError, Result};
+
+/// A reference to a type as used by a field or typedef
+///
+/// `name` is written verbatim, e.g. `uint32_t`, `node_t` or `struct node`.
+/// `is_const` and `is_volatile` qualify the referenced type, not the pointer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TypeRef {
+  /// The type name
+  pub name: String,
+  /// Number of `*` after the type name
+  pub pointer_depth: usize,
+  /// Whether the type is `const` qualified
+  pub is_const: bool,
+  /// Whether the type is `volatile` qualified
+  pub is_volatile: bool,
+}
+
+impl TypeRef {
+  /// Create a reference to a type by value
+  pub fn new(name: &str) -> Self {
+    Self {
+      name: name.to_string(),
+      pointer_depth: 0,
+      is_const: false,
+      is_volatile: false,
+    }
+  }
+
+  /// Create a pointer to a type
+  pub fn pointer_to(name: &str) -> Self {
+    Self {
+      pointer_depth: 1,
+      ..Self::new(name)
+    }
+  }
+
+  /// Parse a spelling such as `const char *` or `struct node **`
+  ///
+  /// Qualifiers must come before the type name; qualified pointers
+  /// (`char *const`) are not supported.
+  pub fn parse(spelling: &str) -> Result<Self> {
+    let invalid = || CodeGenError::InvalidDefinition(format!("invalid type '{}'", spelling));
+    let trimmed = spelling.trim();
+    let base = trimmed.trim_end_matches(|c: char| c == '*' || c.is_whitespace());
+    let pointer_depth = trimmed[base.len()..].matches('*').count();
+
+    let mut ty = Self::new("");
+    let mut words = Vec::new();
+    for word in base.split_whitespace() {
+      match word {
+        "const" if words.is_empty() => ty.is_const = true,
+        "volatile" if words.is_empty() => ty.is_volatile = true,
+        "const" | "volatile" => return Err(invalid()),
+        _ if word.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') => words.push(word),
+        _ => return Err(invalid()),
+      }
+    }
+    if words.is_empty() {
+      return Err(invalid());
+    }
+    ty.name = words.join(" ");
+    ty.pointer_depth = pointer_depth;
+    Ok(ty)
+  }
+
+  /// Make the referenced type `const`, returning the reference for chaining
+  pub fn with_const(mut self) -> Self {
+    self.is_const = true;
+    self
+  }
+
+  /// Make the referenced type `volatile`, returning the reference for chaining
+  pub fn with_volatile(mut self) -> Self {
+    self.is_volatile = true;
+    self
+  }
+
+  /// Check whether the reference is a pointer
+  pub fn is_pointer(&self) -> bool {
+    self.pointer_depth > 0
+  }
+
+  /// Get the name with any `struct`/`union` keyword removed
+  pub(crate) fn base_name(&self) -> &str {
+    let name = self.name.trim();
+    name
+      .strip_prefix("struct ")
+      .or_else(|| name.strip_prefix("union "))
+      .unwrap_or(name)
+      .trim()
+  }
+
+  /// Check whether the name is spelled with a `struct`/`union` keyword
+  fn has_tag_keyword(&self) -> bool {
+    self.base_name() != self.name.trim()
+  }
+
+  /// Declare `ident` with this type, e.g. `const uint8_t *data`
+  pub fn declare(&self, ident: &str) -> String {
+    let mut out = String::new();
+    if self.is_const {
+      out.push_str("const ");
+    }
+    if self.is_volatile {
+      out.push_str("volatile ");
+    }
+    out.push_str(&self.name);
+    out.push(' ');
+    out.push_str(&"*".repeat(self.pointer_depth));
+    out.push_str(ident);
+    out
+  }
+}
+
+/// A member of a struct or union
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldDef {
+  /// The field name
+  pub name: String,
+  /// The field type
+  pub ty: TypeRef,
+  /// Array length expression, if the field is an array
+  pub array_len: Option<String>,
+  /// Width in bits, if the field is a bit-field
+  pub bit_width: Option<u32>,
+  /// Trailing documentation comment
+  pub doc: Option<String>,
+}
+
+impl FieldDef {
+  /// Create a new field
+  pub fn new(name: &str, ty: TypeRef) -> Self {
+    Self {
+      name: name.to_string(),
+      ty,
+      array_len: None,
+      bit_width: None,
+      doc: None,
+    }
+  }
+
+  /// Make the field an array, returning the field for chaining
+  pub fn with_array_len(mut self, len: &str) -> Self {
+    self.array_len = Some(len.to_string());
+    self
+  }
+
+  /// Make the field a bit-field, returning the field for chaining
+  pub fn with_bit_width(mut self, width: u32) -> Self {
+    self.bit_width = Some(width);
+    self
+  }
+
+  /// Set the trailing documentation comment, returning the field for chaining
+  pub fn with_doc(mut self, doc: &str) -> Self {
+    self.doc = Some(doc.to_string());
+    self
+  }
+
+  /// Render the member declaration, e.g. `uint8_t data[4];`
+  ///
+  /// An empty name declares an unnamed bit-field, e.g. `uint32_t : 3;`.
+  pub fn declaration(&self) -> String {
+    let mut out = self.ty.declare(&self.name).trim_end().to_string();
+    if let Some(ref len) = self.array_len {
+      out.push_str(&format!("[{}]", len));
+    }
+    if let Some(width) = self.bit_width {
+      out.push_str(&format!(" : {}", width));
+    }
+    out.push(';');
+    out
+  }
+}
+
+/// Whether an aggregate is a struct or a union
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AggregateKind {
+  Struct,
+  Union,
+}
+
+impl AggregateKind {
+  /// Get the C keyword
+  pub fn keyword(&self) -> &'static str {
+    match self {
+      AggregateKind::Struct => "struct",
+      AggregateKind::Union => "union",
+    }
+  }
+}
+
+/// A struct or union definition
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StructDef {
+  /// The tag name
+  pub name: String,
+  /// Struct or union
+  pub kind: AggregateKind,
+  /// The members, in declaration order
+  pub fields: Vec<FieldDef>,
+  /// Whether a typedef of the same name is written (`typedef struct x x;`)
+  pub typedef: bool,
+  /// Optional documentation comment
+  pub doc: Option<String>,
+  /// Attributes of the type, e.g. `Attribute::Packed`
+  pub attributes: Vec<Attribute>,
+}
+
+impl StructDef {
+  /// Create a new struct
+  pub fn new(name: &str) -> Self {
+    Self {
+      name: name.to_string(),
+      kind: AggregateKind::Struct,
+      fields: Vec::new(),
+      typedef: false,
+      doc: None,
+      attributes: Vec::new(),
+    }
+  }
+
+  /// Create a new union
+  pub fn union(name: &str) -> Self {
+    Self {
+      kind: AggregateKind::Union,
+      ..Self::new(name)
+    }
+  }
+
+  /// Add a member
+  pub fn add_field(&mut self, field: FieldDef) {
+    self.fields.push(field);
+  }
+
+  /// Add a member, returning the definition for chaining
+  pub fn with_field(mut self, field: FieldDef) -> Self {
+    self.add_field(field);
+    self
+  }
+
+  /// Also write a typedef of the same name, returning the definition for chaining
+  pub fn with_typedef(mut self) -> Self {
+    self.typedef = true;
+    self
+  }
+
+  /// Set the documentation comment, returning the definition for chaining
+  pub fn with_doc(mut self, doc: &str) -> Self {
+    self.doc = Some(doc.to_string());
+    self
+  }
+
+  /// Add an attribute, returning the definition for chaining
+  pub fn with_attribute(mut self, attribute: Attribute) -> Self {
+    self.attributes.push(attribute);
+    self
+  }
+
+  /// Check whether the definition has `attribute`
+  pub fn has_attribute(&self, attribute: &Attribute) -> bool {
+    self.attributes.contains(attribute)
+  }
+
+  /// Write the forward declaration (`struct x;` or `typedef struct x x;`)
+  pub fn write_forward_declaration<W: Write>(&self, writer: &mut CodeWriter<W>) -> Result<()> {
+    if self.typedef {
+      writer.writeln(&format!(
+        "typedef {} {} {};",
+        self.kind.keyword(),
+        self.name,
+        self.name
+      ))
+    } else {
+      writer.writeln(&format!("{} {};", self.kind.keyword(), self.name))
+    }
+  }
+
+  /// Write the definition, followed by its typedef unless `forward_declared`
+  pub fn write_definition<W: Write>(
+    &self,
+    writer: &mut CodeWriter<W>,
+    forward_declared: bool,
+  ) -> Result<()> {
+    self.write_annotated(writer, forward_declared, &[])
+  }
+
+  /// Write the definition with a plain comment after each member that has one in `comments`
+  pub(crate) fn write_annotated<W: Write>(
+    &self,
+    writer: &mut CodeWriter<W>,
+    forward_declared: bool,
+    comments: &[String],
+  ) -> Result<()> {
+    let attributes = writer.render_attributes(&self.attributes, AttributeTarget::Type)?;
+    if let Some(ref doc) = self.doc {
+      writer.write_doc_comment(&DocComment::new(doc))?;
+    }
+    if !attributes.prefix.is_empty() {
+      writer.writeln(&attributes.prefix)?;
+    }
+    match self.kind {
+      AggregateKind::Struct => writer.begin_struct(&self.name)?,
+      AggregateKind::Union => writer.begin_union(&self.name)?,
+    }
+    writer.indent();
+    for (i, field) in self.fields.iter().enumerate() {
+      let mut line = field.declaration();
+      if let Some(comment) = comments.get(i) {
+        line.push_str(&format!(" /* {} */", comment));
+      }
+      if let Some(ref doc) = field.doc {
+        let trailing = writer.trailing_doc(doc)?;
+        line.push_str(&format!(" {}", trailing));
+      }
+      writer.writeln(&line)?;
+    }
+    writer.dedent();
+    if !attributes.suffix.is_empty() {
+      writer.writeln(&format!("}} {};", attributes.suffix))?;
+    } else {
+      match self.kind {
+        AggregateKind::Struct => writer.end_struct()?,
+        AggregateKind::Union => writer.end_union()?,
+      }
+    }
+    if self.typedef && !forward_declared {
+      self.write_forward_declaration(writer)?;
+    }
+    Ok(())
+  }
+}
+
+/// A type definition managed by a `TypeGraph`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TypeDef {
+  /// A struct or union
+  Aggregate(StructDef),
+  /// `typedef target name;`
+  Typedef { name: String, target: TypeRef },
+}
+
+impl TypeDef {
+  /// Get the name other types refer to this one by
+  pub fn name(&self) -> &str {
+    match self {
+      TypeDef::Aggregate(def) => &def.name,
+      TypeDef::Typedef { name, .. } => name,
+    }
+  }
+
+  /// Get the type references this definition depends on
+  fn references(&self) -> Vec<&TypeRef> {
+    match self {
+      TypeDef::Aggregate(def) => def.fields.iter().map(|f| &f.ty).collect(),
+      TypeDef::Typedef { target, .. } => vec![target],
+    }
+  }
+}
+
+/// A set of type definitions written in dependency order
+///
+/// Types contained by value are defined before the types that contain them.
+/// Pointers to a struct or union, directly or through a typedef, and
+/// typedefs of a struct or union only need a declaration, so a forward
+/// declaration is written when the target is defined later. Types that
+/// contain each other by value cannot be ordered and are reported as
+/// `CodeGenError::DependencyCycle`.
+#[derive(Debug, Clone, Default)]
+pub struct TypeGraph {
+  types: Vec<TypeDef>,
+}
+
+impl TypeGraph {
+  /// Create an empty type graph
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Add a type definition, failing if the name is already taken
+  pub fn add(&mut self, def: TypeDef) -> Result<()> {
+    if self.types.iter().any(|t| t.name() == def.name()) {
+      return Err(CodeGenError::InvalidDefinition(format!(
+        "type '{}' is defined twice",
+        def.name()
+      )));
+    }
+    self.types.push(def);
+    Ok(())
+  }
+
+  /// Add a struct or union definition
+  pub fn add_struct(&mut self, def: StructDef) -> Result<()> {
+    self.add(TypeDef::Aggregate(def))
+  }
+
+  /// Add a typedef
+  pub fn add_typedef(&mut self, name: &str, target: TypeRef) -> Result<()> {
+    self.add(TypeDef::Typedef {
+      name: name.to_string(),
+      target,
+    })
+  }
+
+  /// Find a definition by name
+  pub fn get(&self, name: &str) -> Option<&TypeDef> {
+    self.types.iter().find(|t| t.name() == name)
+  }
+
+  /// Get the definitions in insertion order
+  pub fn types(&self) -> &[TypeDef] {
+    &self.types
+  }
+
+  /// Find the definition a reference resolves to
+  fn resolve(&self, ty: &TypeRef) -> Option<usize> {
+    self.types.iter().position(|t| t.name() == ty.base_name())
+  }
+
+  /// Find the struct or union a definition names, following typedef chains
+  fn aggregate_of(&self, mut index: usize) -> Option<usize> {
+    // A chain longer than the graph loops back on itself
+    for _ in 0..self.types.len() {
+      match self.types[index] {
+        TypeDef::Aggregate(_) => return Some(index),
+        TypeDef::Typedef { ref target, .. } if !target.is_pointer() => {
+          index = self.resolve(target)?;
+        }
+        TypeDef::Typedef { .. } => return None,
+      }
+    }
+    None
+  }
+
+  /// Check whether a reference of `def` only needs the target to be declared
+  ///
+  /// Pointers to a struct or union need only its tag, and so does a typedef
+  /// of one, as `typedef struct node node_t;` declares the tag itself.
+  fn is_weak(&self, def: &TypeDef, ty: &TypeRef, target: usize) -> bool {
+    matches!(self.types[target], TypeDef::Aggregate(_))
+      && (ty.is_pointer() || matches!(def, TypeDef::Typedef { .. }))
+  }
+
+  /// Get the indices of the definitions that must precede each definition
+  ///
+  /// A pointer through a typedef needs the typedef but not the struct behind
+  /// it, while a member held by value through a typedef needs both.
+  fn strong_dependencies(&self) -> Vec<Vec<usize>> {
+    self
+      .types
+      .iter()
+      .map(|def| {
+        let mut deps = Vec::new();
+        for ty in def.references() {
+          let Some(target) = self.resolve(ty) else {
+            continue;
+          };
+          if self.is_weak(def, ty, target) {
+            continue;
+          }
+          deps.push(target);
+          if !ty.is_pointer() && matches!(def, TypeDef::Aggregate(_)) {
+            deps.extend(self.aggregate_of(target).filter(|&a| a != target));
+          }
+        }
+        deps
+      })
+      .collect()
+  }
+
+  /// Get the definition order, keeping insertion order where dependencies allow
+  pub fn sorted(&self) -> Result<Vec<&TypeDef>> {
+    let deps = self.strong_dependencies();
+    let mut emitted = vec![false; self.types.len()];
+    let mut order = Vec::with_capacity(self.types.len());
+
+    while order.len() < self.types.len() {
+      let ready = (0..self.types.len())
+        .find(|&i| !emitted[i] && deps[i].iter().all(|&d| d != i && emitted[d]));
+      match ready {
+        Some(i) => {
+          emitted[i] = true;
+          order.push(i);
+        }
+        None => return Err(self.cycle_error(&deps, &emitted)),
+      }
+    }
+
+    Ok(order.into_iter().map(|i| &self.types[i]).collect())
+  }
+
+  /// Describe a by-value cycle among the definitions not yet emitted
+  fn cycle_error(&self, deps: &[Vec<usize>], emitted: &[bool]) -> CodeGenError {
+    // Every remaining definition has a remaining dependency, so following
+    // them from any start must revisit a definition
+    let mut path: Vec<usize> = Vec::new();
+    let mut current = (0..self.types.len()).find(|&i| !emitted[i]).unwrap_or(0);
+    while !path.contains(&current) {
+      path.push(current);
+      current = deps[current]
+        .iter()
+        .copied()
+        .find(|&d| !emitted[d])
+        .unwrap_or(current);
+    }
+    let start = path.iter().position(|&i| i == current).unwrap_or(0);
+    let mut names: Vec<&str> = path[start..]
+      .iter()
+      .map(|&i| self.types[i].name())
+      .collect();
+    names.push(self.types[current].name());
+    CodeGenError::DependencyCycle(names.join(" -> "))
+  }
+
+  /// Write forward declarations followed by all definitions in dependency order
+  pub fn write<W: Write>(&self, writer: &mut CodeWriter<W>) -> Result<()> {
+    let order = self.sorted()?;
+    let position: HashMap<&str, usize> = order
+      .iter()
+      .enumerate()
+      .map(|(i, def)| (def.name(), i))
+      .collect();
+
+    // Aggregates referenced before their definition, or through their
+    // typedef name from inside themselves
+    let mut forward: HashSet<&str> = HashSet::new();
+    for (i, def) in order.iter().enumerate() {
+      for ty in def.references() {
+        let Some(target) = self.resolve(ty) else {
+          continue;
+        };
+        if !self.is_weak(def, ty, target) {
+          continue;
+        }
+        let target_name = self.types[target].name();
+        let self_by_typedef = target_name == def.name() && !ty.has_tag_keyword();
+        if position[target_name] > i || self_by_typedef {
+          forward.insert(target_name);
+        }
+      }
+    }
+
+    let mut wrote_forward = false;
+    for def in &order {
+      if let TypeDef::Aggregate(aggregate) = def {
+        if forward.contains(aggregate.name.as_str()) {
+          aggregate.write_forward_declaration(writer)?;
+          wrote_forward = true;
+        }
+      }
+    }
+    if wrote_forward {
+      writer.newline()?;
+    }
+
+    for (i, def) in order.iter().enumerate() {
+      if i > 0 {
+        writer.newline()?;
+      }
+      match def {
+        TypeDef::Aggregate(aggregate) => {
+          aggregate.write_definition(writer, forward.contains(aggregate.name.as_str()))?
+        }
+        TypeDef::Typedef { name, target } => {
+          writer.writeln(&format!("typedef {};", target.declare(name)))?
+        }
+      }
+    }
+    Ok(())
+  }
+}
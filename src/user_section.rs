@@ -361,13 +361,14 @@ impl UserSectionManager {
     if self.is_section_written(name) {
       return Ok(()); // Silently skip if already written
     }
-    self.mark_section_written(name);
+
+    let content = self.get_section_content(name).unwrap_or_default();
+    Self::check_content_markers(name, content)?;
 
     // Write section begin marker
     writer.writeln(&format!("/* USER CODE BEGIN {} */", name))?;
 
     // Write section content
-    let content = self.get_section_content(name).unwrap_or_default();
     if !content.is_empty() {
       writer.write(&content)?;
       // Ensure content ends with newline if it doesn't already
@@ -379,6 +380,8 @@ impl UserSectionManager {
     // Write section end marker
     writer.writeln(&format!("/* USER CODE END {} */", name))?;
 
+    // Only a complete write counts, so a failed one can be retried
+    self.mark_section_written(name);
     Ok(())
   }
 
@@ -392,11 +395,13 @@ impl UserSectionManager {
       return Err(CodeGenError::UnknownSection(name.to_string()));
     }
 
+    let content = self.get_section_content(name).unwrap_or_default();
+    Self::check_content_markers(name, content)?;
+
     // Write section begin marker
     writer.writeln(&format!("/* USER CODE BEGIN {} */", name))?;
 
     // Write section content
-    let content = self.get_section_content(name).unwrap_or_default();
     if !content.is_empty() {
       writer.write(&content)?;
       if !content.ends_with('\n') {
@@ -434,6 +439,15 @@ impl UserSectionManager {
     number: u32,
     default_content: Option<&str>,
   ) -> Result<()> {
+    let content = self
+      .partial_sections
+      .get(&number)
+      .map(|s| s.as_str())
+      .or(default_content);
+    if let Some(content) = content {
+      Self::check_partial_content_markers(number, content)?;
+    }
+
     // Write section begin marker
     writer.writeln(&format!("//!begin {}", number))?;
 
@@ -453,6 +467,29 @@ impl UserSectionManager {
     Ok(())
   }
 
+  /// Fail if content would close or reopen its own section when captured again
+  fn check_content_markers(name: &str, content: &str) -> Result<()> {
+    let begin_marker = format!("/* USER CODE BEGIN {} */", name);
+    let end_marker = format!("/* USER CODE END {} */", name);
+    if content.contains(&begin_marker) || content.contains(&end_marker) {
+      return Err(CodeGenError::MarkerInSectionContent(name.to_string()));
+    }
+    Ok(())
+  }
+
+  /// Fail if partial section content contains a marker for its own number
+  fn check_partial_content_markers(number: u32, content: &str) -> Result<()> {
+    let marker =
+      Regex::new(&format!(r"//!(begin|end)\s+{}\b", number)).map_err(CodeGenError::Regex)?;
+    if marker.is_match(content) {
+      return Err(CodeGenError::MarkerInSectionContent(format!(
+        "partial section {}",
+        number
+      )));
+    }
+    Ok(())
+  }
+
   /// Get the content of a partial section
   pub fn get_partial_section_content(&self, number: u32) -> Option<&str> {
     self.partial_sections.get(&number).map(|s| s.as_str())